@@ -0,0 +1,171 @@
+//! Every format decrypting the same amount of synthetic input, to compare
+//! cipher throughput side by side. Joox is left out: its AES blocks can not
+//! be synthesized without the per-file PBKDF2 key.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use parakeet_core::{
+    decryption::{
+        new_kgm, new_kwm, new_ncm, new_passthrough, new_qmc_v1, new_qmc_v2_with_key, new_vpr,
+        new_x2m, new_x3m, ximalaya::build_scramble_table,
+    },
+    decryptor::Decryptor,
+};
+
+const INPUT_SIZE: usize = 16 * 1024 * 1024;
+const CHUNK_SIZE: usize = 0x10_0000;
+
+// Same NCM key pair as `ncm_skip_metadata`.
+const NCM_CORE_KEY: [u8; 16] = [
+    0x80, 0x88, 0x6A, 0x09, 0x09, 0x2E, 0x28, 0x7F, 0xB1, 0x66, 0xB3, 0x8D, 0x0C, 0xEB, 0xC7, 0x1A,
+];
+
+const NCM_CONTENT_KEY_BLOCK: &[u8] = &[
+    0x4D, 0x3C, 0x5A, 0x96, 0x74, 0x42, 0x64, 0xD3, 0x14, 0x4F, 0x77, 0xBB, //
+    0x3C, 0x7B, 0x60, 0x56, 0x96, 0xA0, 0xD0, 0x12, 0xCB, 0xB8, 0xB6, 0x86, //
+    0x13, 0xE6, 0xEF, 0x51, 0x00, 0x7E, 0xED, 0x02, 0xDF, 0xFE, 0xD2, 0xED, //
+    0x6C, 0x4A, 0xA1, 0x33, 0x0C, 0xEA, 0x8E, 0x00, 0x3A, 0xBC, 0xAA, 0xFB, //
+    0x47, 0xAC, 0xE3, 0x0B, 0xBA, 0xEA, 0xA4, 0x88, 0x6D, 0x84, 0x74, 0xBE, //
+    0x28, 0x86, 0x1B, 0x43, 0xF7, 0x2F, 0x2A, 0xFD, 0x85, 0x44, 0xA0, 0xFC, //
+    0xCD, 0xE0, 0xD7, 0xEC, 0x8F, 0xDB, 0xB0, 0xB5, 0x39, 0xFD, 0x94, 0x27, //
+    0xD5, 0x24, 0x98, 0xCE, 0x2E, 0x6B, 0x7C, 0xBB, 0x16, 0x55, 0x0B, 0x63, //
+    0x63, 0x3E, 0x8E, 0x26, 0x91, 0xF9, 0x32, 0x37, 0x38, 0xC0, 0x93, 0xD9, //
+    0xCF, 0x40, 0x44, 0x5A, 0x6E, 0xDE, 0xEA, 0xCA, 0x27, 0xCB, 0x50, 0x54, //
+    0x12, 0xFE, 0x12, 0x89, 0x59, 0x06, 0x72, 0xA9, 0x81, 0x33, 0x1F, 0xBE, //
+    0xCB, 0xC9, 0x38, 0xFA, 0xE8, 0x94, 0xEB, 0xD7, 0x04, 0xF2, 0x58, 0xB4, //
+];
+
+const KGM_MAGIC: [u8; 16] = [
+    0x7c, 0xd5, 0x32, 0xeb, 0x86, 0x02, 0x7f, 0x4b, //
+    0xa8, 0xaf, 0xa6, 0x8e, 0x0f, 0xff, 0x99, 0x14, //
+];
+
+const VPR_MAGIC: [u8; 16] = [
+    0x05, 0x28, 0xbc, 0x96, 0xe9, 0xe4, 0x5a, 0x43, //
+    0x91, 0xaa, 0xbd, 0xd0, 0x7a, 0xf5, 0x36, 0x31, //
+];
+
+/// Deterministic filler; the ciphers do not look at the body content.
+fn bytes(len: usize, seed: u8) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+        .collect()
+}
+
+/// `header` followed by the body, `INPUT_SIZE` bytes in total.
+fn with_header(header: Vec<u8>) -> Vec<u8> {
+    let mut file = header;
+    let body = bytes(INPUT_SIZE - file.len(), 0x5a);
+    file.extend_from_slice(&body);
+    file
+}
+
+fn kugou_file(magic: &[u8; 16]) -> Vec<u8> {
+    let mut header = vec![0u8; 0x400];
+    header[..16].copy_from_slice(magic);
+    header[0x10..0x14].copy_from_slice(&0x400u32.to_le_bytes());
+    header[0x1c..0x2c].copy_from_slice(&bytes(16, 1));
+    with_header(header)
+}
+
+fn kuwo_file() -> Vec<u8> {
+    let mut header = vec![0u8; 0x400];
+    header[..0x10].copy_from_slice(b"yeelion-kuwo-tme");
+    header[0x10] = 1;
+    header[0x18..0x20].copy_from_slice(&0x1122_3344u64.to_le_bytes());
+    with_header(header)
+}
+
+fn ncm_file() -> Vec<u8> {
+    let mut header = b"CTENFDAM\xff\xff".to_vec();
+    header.extend_from_slice(&(NCM_CONTENT_KEY_BLOCK.len() as u32).to_le_bytes());
+    header.extend_from_slice(NCM_CONTENT_KEY_BLOCK);
+    // Small filler metadata (plus CRC and gap) and cover blocks.
+    header.extend_from_slice(&0x10u32.to_le_bytes());
+    header.resize(header.len() + 0x10 + 5, 0x55);
+    header.extend_from_slice(&0x10u32.to_le_bytes());
+    header.extend_from_slice(&0x10u32.to_le_bytes());
+    header.resize(header.len() + 0x10, 0xaa);
+    with_header(header)
+}
+
+fn decrypt<D: Decryptor + ?Sized>(decryptor: &mut D, file: &[u8]) -> usize {
+    let mut len = 0;
+    for chunk in file.chunks(CHUNK_SIZE) {
+        decryptor.write(chunk).unwrap();
+        len += black_box(decryptor.read_all_output()).len();
+    }
+    decryptor.end().unwrap();
+    len + decryptor.read_all_output().len()
+}
+
+fn all_formats(c: &mut Criterion) {
+    let kugou_t1: [u8; 17 * 16] = bytes(17 * 16, 2).try_into().unwrap();
+    let kugou_t2: [u8; 17 * 16] = bytes(17 * 16, 3).try_into().unwrap();
+    let kugou_v2: [u8; 17 * 16] = bytes(17 * 16, 4).try_into().unwrap();
+    let vpr_key: [u8; 17] = bytes(17, 5).try_into().unwrap();
+    let kuwo_key: [u8; 0x20] = bytes(0x20, 6).try_into().unwrap();
+    let qmc_key = bytes(256, 7);
+    let qmc_map_key = bytes(128, 8);
+    let qmc_rc4_key = bytes(512, 9);
+    let x2m_key: [u8; 4] = bytes(4, 10).try_into().unwrap();
+    let x3m_key: [u8; 0x20] = bytes(0x20, 11).try_into().unwrap();
+    let table = build_scramble_table(&bytes(2048, 12)).unwrap();
+
+    let raw = bytes(INPUT_SIZE, 0x5a);
+    let kgm = kugou_file(&KGM_MAGIC);
+    let vpr = kugou_file(&VPR_MAGIC);
+    let kwm = kuwo_file();
+    let ncm = ncm_file();
+
+    let mut group = c.benchmark_group("all formats, 16 MiB");
+    group.throughput(Throughput::Bytes(INPUT_SIZE as u64));
+    group.sample_size(10);
+
+    group.bench_function("pass-through", |b| {
+        b.iter(|| decrypt(&mut new_passthrough(), &raw))
+    });
+    group.bench_function("kgm", |b| {
+        b.iter(|| decrypt(&mut new_kgm(&kugou_t1, &kugou_t2, &kugou_v2), &kgm))
+    });
+    group.bench_function("vpr", |b| {
+        b.iter(|| {
+            decrypt(
+                &mut new_vpr(&kugou_t1, &kugou_t2, &kugou_v2, &vpr_key),
+                &vpr,
+            )
+        })
+    });
+    group.bench_function("kwm", |b| b.iter(|| decrypt(&mut new_kwm(&kuwo_key), &kwm)));
+    group.bench_function("ncm", |b| {
+        b.iter(|| decrypt(&mut new_ncm(&NCM_CORE_KEY), &ncm))
+    });
+    group.bench_function("qmcv1 static", |b| {
+        b.iter(|| decrypt(&mut new_qmc_v1(&qmc_key), &raw))
+    });
+    group.bench_function("qmcv2 map", |b| {
+        b.iter(|| {
+            decrypt(
+                new_qmc_v2_with_key(qmc_map_key.clone()).unwrap().as_mut(),
+                &raw,
+            )
+        })
+    });
+    group.bench_function("qmcv2 rc4", |b| {
+        b.iter(|| {
+            decrypt(
+                new_qmc_v2_with_key(qmc_rc4_key.clone()).unwrap().as_mut(),
+                &raw,
+            )
+        })
+    });
+    group.bench_function("x2m", |b| {
+        b.iter(|| decrypt(&mut new_x2m(x2m_key, table), &raw))
+    });
+    group.bench_function("x3m", |b| {
+        b.iter(|| decrypt(&mut new_x3m(x3m_key, table), &raw))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, all_formats);
+criterion_main!(benches);
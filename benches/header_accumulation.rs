@@ -0,0 +1,32 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use parakeet_core::{
+    decryption::ximalaya::{build_scramble_table, new_x2m, XMLY_SCRAMBLE_SIZE},
+    decryptor::Decryptor,
+};
+
+fn header_accumulation(c: &mut Criterion) {
+    let seed: Vec<u8> = (0..XMLY_SCRAMBLE_SIZE * 2).map(|i| i as u8).collect();
+    let table = build_scramble_table(&seed).unwrap();
+    let header = vec![0x55u8; XMLY_SCRAMBLE_SIZE];
+
+    c.bench_function("x2m header, 1-byte writes", |b| {
+        b.iter(|| {
+            let mut decryptor = new_x2m(*b"pkrt", table);
+            for byte in header.chunks(1) {
+                decryptor.write(black_box(byte)).unwrap();
+            }
+            black_box(decryptor.read_all_output())
+        })
+    });
+
+    c.bench_function("x2m header, single write", |b| {
+        b.iter(|| {
+            let mut decryptor = new_x2m(*b"pkrt", table);
+            decryptor.write(black_box(&header)).unwrap();
+            black_box(decryptor.read_all_output())
+        })
+    });
+}
+
+criterion_group!(benches, header_accumulation);
+criterion_main!(benches);
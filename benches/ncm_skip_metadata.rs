@@ -0,0 +1,71 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use parakeet_core::{
+    decryption::{new_ncm, new_ncm_audio_only},
+    decryptor::Decryptor,
+};
+
+const CORE_KEY: [u8; 16] = [
+    0x80, 0x88, 0x6A, 0x09, 0x09, 0x2E, 0x28, 0x7F, 0xB1, 0x66, 0xB3, 0x8D, 0x0C, 0xEB, 0xC7, 0x1A,
+];
+
+// Content key encrypted with `CORE_KEY`.
+const CONTENT_KEY_BLOCK: &[u8] = &[
+    0x4D, 0x3C, 0x5A, 0x96, 0x74, 0x42, 0x64, 0xD3, 0x14, 0x4F, 0x77, 0xBB, //
+    0x3C, 0x7B, 0x60, 0x56, 0x96, 0xA0, 0xD0, 0x12, 0xCB, 0xB8, 0xB6, 0x86, //
+    0x13, 0xE6, 0xEF, 0x51, 0x00, 0x7E, 0xED, 0x02, 0xDF, 0xFE, 0xD2, 0xED, //
+    0x6C, 0x4A, 0xA1, 0x33, 0x0C, 0xEA, 0x8E, 0x00, 0x3A, 0xBC, 0xAA, 0xFB, //
+    0x47, 0xAC, 0xE3, 0x0B, 0xBA, 0xEA, 0xA4, 0x88, 0x6D, 0x84, 0x74, 0xBE, //
+    0x28, 0x86, 0x1B, 0x43, 0xF7, 0x2F, 0x2A, 0xFD, 0x85, 0x44, 0xA0, 0xFC, //
+    0xCD, 0xE0, 0xD7, 0xEC, 0x8F, 0xDB, 0xB0, 0xB5, 0x39, 0xFD, 0x94, 0x27, //
+    0xD5, 0x24, 0x98, 0xCE, 0x2E, 0x6B, 0x7C, 0xBB, 0x16, 0x55, 0x0B, 0x63, //
+    0x63, 0x3E, 0x8E, 0x26, 0x91, 0xF9, 0x32, 0x37, 0x38, 0xC0, 0x93, 0xD9, //
+    0xCF, 0x40, 0x44, 0x5A, 0x6E, 0xDE, 0xEA, 0xCA, 0x27, 0xCB, 0x50, 0x54, //
+    0x12, 0xFE, 0x12, 0x89, 0x59, 0x06, 0x72, 0xA9, 0x81, 0x33, 0x1F, 0xBE, //
+    0xCB, 0xC9, 0x38, 0xFA, 0xE8, 0x94, 0xEB, 0xD7, 0x04, 0xF2, 0x58, 0xB4, //
+];
+
+const METADATA_SIZE: usize = 0x8_0000;
+const COVER_SIZE: usize = 0x10_0000;
+const AUDIO_SIZE: usize = 0x1000;
+
+/// An NCM file with large metadata and cover blocks, and little audio.
+fn ncm_file() -> Vec<u8> {
+    let mut file = b"CTENFDAM\xff\xff".to_vec();
+    file.extend_from_slice(&(CONTENT_KEY_BLOCK.len() as u32).to_le_bytes());
+    file.extend_from_slice(CONTENT_KEY_BLOCK);
+    file.extend_from_slice(&(METADATA_SIZE as u32).to_le_bytes());
+    file.resize(file.len() + METADATA_SIZE + 5, 0x55);
+    file.extend_from_slice(&(COVER_SIZE as u32).to_le_bytes());
+    file.extend_from_slice(&(COVER_SIZE as u32).to_le_bytes());
+    file.resize(file.len() + COVER_SIZE + AUDIO_SIZE, 0xaa);
+    file
+}
+
+fn decrypt(mut decryptor: impl Decryptor, file: &[u8]) -> Vec<u8> {
+    let mut output = vec![];
+    for chunk in file.chunks(0x1000) {
+        decryptor.write(chunk).unwrap();
+        output.append(&mut decryptor.read_all_output());
+    }
+    decryptor.end().unwrap();
+    output
+}
+
+fn ncm_skip_metadata(c: &mut Criterion) {
+    let file = ncm_file();
+    assert_eq!(
+        decrypt(new_ncm(&CORE_KEY), &file),
+        decrypt(new_ncm_audio_only(&CORE_KEY), &file)
+    );
+
+    c.bench_function("ncm, full header", |b| {
+        b.iter(|| black_box(decrypt(new_ncm(&CORE_KEY), black_box(&file))))
+    });
+
+    c.bench_function("ncm, audio only", |b| {
+        b.iter(|| black_box(decrypt(new_ncm_audio_only(&CORE_KEY), black_box(&file))))
+    });
+}
+
+criterion_group!(benches, ncm_skip_metadata);
+criterion_main!(benches);
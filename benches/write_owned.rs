@@ -0,0 +1,52 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use parakeet_core::{
+    decryption::ximalaya::{build_scramble_table, new_x2m, XMLY_SCRAMBLE_SIZE},
+    decryptor::Decryptor,
+};
+
+const CHUNK_SIZE: usize = 0x10_0000;
+const CHUNK_COUNT: usize = 16;
+
+fn chunks() -> Vec<Vec<u8>> {
+    (0..CHUNK_COUNT)
+        .map(|i| vec![i as u8; CHUNK_SIZE])
+        .collect()
+}
+
+fn write_owned(c: &mut Criterion) {
+    let seed: Vec<u8> = (0..XMLY_SCRAMBLE_SIZE * 2).map(|i| i as u8).collect();
+    let table = build_scramble_table(&seed).unwrap();
+    let key = [0x5au8; 4];
+
+    // Output is drained after each chunk, as a streaming caller would.
+    c.bench_function("x2m pass-through, write", |b| {
+        b.iter_batched(
+            chunks,
+            |chunks| {
+                let mut decryptor = new_x2m(key, table);
+                for chunk in chunks {
+                    decryptor.write(black_box(&chunk)).unwrap();
+                    black_box(decryptor.read_all_output());
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    c.bench_function("x2m pass-through, write_owned", |b| {
+        b.iter_batched(
+            chunks,
+            |chunks| {
+                let mut decryptor = new_x2m(key, table);
+                for chunk in chunks {
+                    decryptor.write_owned(black_box(chunk)).unwrap();
+                    black_box(decryptor.read_all_output());
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, write_owned);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use parakeet_core::{
+    decryption::ximalaya::{build_scramble_table, new_x3m, ScrambleTable, XMLY_SCRAMBLE_SIZE},
+    decryptor::Decryptor,
+};
+
+fn ximalaya_identity(c: &mut Criterion) {
+    let seed: Vec<u8> = (0..XMLY_SCRAMBLE_SIZE * 2).map(|i| i as u8).collect();
+    let permuted = build_scramble_table(&seed).unwrap();
+    let mut identity: ScrambleTable = [0u16; XMLY_SCRAMBLE_SIZE];
+    for (i, v) in identity.iter_mut().enumerate() {
+        *v = i as u16;
+    }
+    let header = vec![0x55u8; XMLY_SCRAMBLE_SIZE];
+    let key = [0x5au8; 0x20];
+
+    c.bench_function("x3m header, identity table", |b| {
+        b.iter_batched(
+            || new_x3m(key, identity),
+            |mut decryptor| {
+                decryptor.write(black_box(&header)).unwrap();
+                black_box(decryptor.read_all_output())
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    c.bench_function("x3m header, permuted table", |b| {
+        b.iter_batched(
+            || new_x3m(key, permuted),
+            |mut decryptor| {
+                decryptor.write(black_box(&header)).unwrap();
+                black_box(decryptor.read_all_output())
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, ximalaya_identity);
+criterion_main!(benches);
@@ -0,0 +1,100 @@
+//! Decrypt a Ximalaya `.x2m` / `.x3m` file.
+//!
+//! ```text
+//! cargo run --example decrypt_ximalaya -- <content key> <scramble seed> <input> <output stem>
+//! ```
+//!
+//! The content key file holds the raw key: 4 bytes for X2M, 32 bytes for X3M.
+//! The output extension is picked from the decrypted audio, e.g. `out.m4a`.
+
+use std::{
+    env,
+    error::Error,
+    fs::{self, File},
+    io::{Read, Write},
+    path::PathBuf,
+    process,
+};
+
+use parakeet_core::{
+    decryption::ximalaya::{build_scramble_table, new_ximalaya},
+    decryptor::Decryptor,
+    utils::audio::{detect_audio_type, get_audio_header_metadata_size, AudioExtensionName},
+};
+
+/// Output buffered before sniffing the audio type.
+const SNIFF_SIZE: usize = 0x100;
+
+fn new_decryptor(key: &[u8], seed: &[u8]) -> Result<Box<dyn Decryptor>, Box<dyn Error>> {
+    Ok(new_ximalaya(key, build_scramble_table(seed)?)?)
+}
+
+/// Name the output after the sniffed audio type, once enough is buffered.
+fn open_output(stem: &str, head: &[u8]) -> Result<(File, PathBuf), Box<dyn Error>> {
+    let ext = detect_audio_type(head).to_audio_ext().to_string();
+    let path = PathBuf::from(format!("{}.{}", stem, ext));
+    Ok((File::create(&path)?, path))
+}
+
+fn run(args: &[String]) -> Result<PathBuf, Box<dyn Error>> {
+    let [key_path, seed_path, input_path, output_stem] = args else {
+        return Err(
+            "usage: decrypt_ximalaya <content key> <scramble seed> <input> <output stem>".into(),
+        );
+    };
+
+    let mut decryptor = new_decryptor(&fs::read(key_path)?, &fs::read(seed_path)?)?;
+    let mut input = File::open(input_path)?;
+
+    let mut output: Option<(File, PathBuf)> = None;
+    let mut head = vec![];
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = input.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        decryptor.write(&buf[..n])?;
+
+        let chunk = decryptor.read_all_output();
+        match output.as_mut() {
+            Some((file, _)) => file.write_all(&chunk)?,
+            None => {
+                head.extend_from_slice(&chunk);
+                if head.len() >= get_audio_header_metadata_size(&head) + SNIFF_SIZE {
+                    let (mut file, path) = open_output(output_stem, &head)?;
+                    file.write_all(&head)?;
+                    output = Some((file, path));
+                }
+            }
+        }
+    }
+
+    decryptor.end()?;
+    let tail = decryptor.read_all_output();
+    let (file, path) = match output {
+        Some((mut file, path)) => {
+            file.write_all(&tail)?;
+            (file, path)
+        }
+        None => {
+            head.extend_from_slice(&tail);
+            let (mut file, path) = open_output(output_stem, &head)?;
+            file.write_all(&head)?;
+            (file, path)
+        }
+    };
+    file.sync_all()?;
+    Ok(path)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(path) => println!("decrypted to {}", path.display()),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
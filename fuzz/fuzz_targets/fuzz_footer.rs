@@ -0,0 +1,17 @@
+//! Feed arbitrary trailing bytes to the QMC footer parser.
+//!
+//! ```text
+//! cargo +nightly fuzz run fuzz_footer
+//! ```
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use parakeet_core::tencent::qmc_footer::parse;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some(result) = parse(data) {
+        assert!(result.key.len() <= data.len());
+        assert!(result.eof_bytes_ignore <= data.len());
+    }
+});
@@ -0,0 +1,182 @@
+use crate::decryptor::{
+    consume_output_buffer, DecryptError, DecryptErrorCode, DecryptManifest, Decryptor,
+    DEFAULT_OUTPUT_SHRINK_FACTOR,
+};
+
+/// Releases the output in multiples of `block_size` only, for encoders asking
+/// for aligned blocks. The remainder is released once the input ended.
+///
+/// `read_all_output` always returns a multiple of `block_size` (bar the final
+/// remainder); `consume_output` takes from the aligned output as requested.
+pub struct BlockAlignDecryptor<D: Decryptor> {
+    inner: D,
+    block_size: usize,
+    finished: bool,
+    buf_out: Vec<u8>,
+    output_shrink_factor: usize,
+}
+
+impl<D: Decryptor> BlockAlignDecryptor<D> {
+    pub fn new(inner: D, block_size: usize) -> Result<Self, DecryptError> {
+        if block_size == 0 {
+            return Err(DecryptError::new(
+                DecryptErrorCode::InvalidBlockSize,
+                "block size must not be zero",
+            ));
+        }
+
+        Ok(Self {
+            inner,
+            block_size,
+            finished: false,
+            buf_out: vec![],
+            output_shrink_factor: DEFAULT_OUTPUT_SHRINK_FACTOR,
+        })
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn collect_output(&mut self) {
+        self.buf_out.append(&mut self.inner.read_all_output());
+    }
+}
+
+impl<D: Decryptor> Decryptor for BlockAlignDecryptor<D> {
+    fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        self.inner.write(data)?;
+        self.collect_output();
+        Ok(())
+    }
+
+    fn write_owned(&mut self, chunk: Vec<u8>) -> Result<(), DecryptError> {
+        self.inner.write_owned(chunk)?;
+        self.collect_output();
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<(), DecryptError> {
+        self.inner.end()?;
+        self.collect_output();
+        self.finished = true;
+        Ok(())
+    }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+
+    fn read_all_output(&mut self) -> Vec<u8> {
+        let len = self.output_ready();
+        consume_output_buffer(&mut self.buf_out, len, self.output_shrink_factor)
+    }
+
+    fn get_eof_reserve(&self) -> usize {
+        self.inner.get_eof_reserve()
+    }
+
+    fn output_ready(&self) -> usize {
+        if self.finished {
+            self.buf_out.len()
+        } else {
+            self.buf_out.len() - self.buf_out.len() % self.block_size
+        }
+    }
+
+    fn reset(&mut self) -> Result<(), DecryptError> {
+        self.inner.reset()?;
+        self.finished = false;
+        self.buf_out.clear();
+        Ok(())
+    }
+
+    fn consume_output(&mut self, len: usize) -> Vec<u8> {
+        let len = len.min(self.output_ready());
+        consume_output_buffer(&mut self.buf_out, len, self.output_shrink_factor)
+    }
+
+    fn set_output_shrink_factor(&mut self, factor: usize) {
+        self.output_shrink_factor = factor;
+        self.inner.set_output_shrink_factor(factor)
+    }
+
+    fn warmup(&mut self) {
+        self.inner.warmup()
+    }
+
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        self.inner.validate_config()
+    }
+
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        self.inner.bytes_until_next_phase()
+    }
+
+    fn audio_start_offset(&self) -> u64 {
+        self.inner.audio_start_offset()
+    }
+
+    fn manifest(&self) -> DecryptManifest {
+        self.inner.manifest()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BlockAlignDecryptor;
+    use crate::{
+        decryption::new_passthrough,
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::test_util::test::{decrypt_in_chunks, generate_test_data},
+    };
+
+    #[test]
+    fn test_block_aligned_chunks() {
+        let data = generate_test_data(0x1000 + 123, "block align data");
+        let mut decryptor = BlockAlignDecryptor::new(new_passthrough(), 0x100).unwrap();
+
+        let mut chunks = vec![];
+        for p in data.chunks(77) {
+            decryptor.write(p).unwrap();
+            chunks.push(decryptor.read_all_output());
+        }
+        decryptor.end().unwrap();
+        chunks.push(decryptor.read_all_output());
+
+        let (last, released) = chunks.split_last().unwrap();
+        assert_eq!(last.len(), 123);
+        assert!(released.iter().all(|chunk| chunk.len() % 0x100 == 0));
+        let blocks: Vec<&[u8]> = released.iter().flat_map(|c| c.chunks(0x100)).collect();
+        assert!(blocks.iter().all(|block| block.len() == 0x100));
+        assert_eq!(chunks.concat(), data);
+
+        let mut decryptor = BlockAlignDecryptor::new(new_passthrough(), 0x100).unwrap();
+        assert_eq!(decrypt_in_chunks(&mut decryptor, &data, 7), data);
+    }
+
+    #[test]
+    fn test_output_shrink_factor() {
+        let data = vec![0u8; 0x100_0000];
+        for (factor, keeps_capacity) in [(0, true), (4, false)] {
+            let mut decryptor = BlockAlignDecryptor::new(new_passthrough(), 0x100).unwrap();
+            decryptor.set_output_shrink_factor(factor);
+            decryptor.write(&data).unwrap();
+            decryptor.consume_output(data.len() - 0x100);
+            let capacity = decryptor.buf_out.capacity();
+            assert_eq!(capacity >= data.len(), keeps_capacity, "factor {}", factor);
+        }
+    }
+
+    #[test]
+    fn test_zero_block_size() {
+        let err = BlockAlignDecryptor::new(new_passthrough(), 0)
+            .err()
+            .unwrap();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidBlockSize));
+    }
+}
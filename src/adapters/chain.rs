@@ -0,0 +1,103 @@
+use crate::decryptor::{DecryptError, DecryptManifest, Decryptor};
+
+/// Feeds the output of `first` into `second`, e.g. to undo a transport layer
+/// before the format decryption.
+pub struct ChainDecryptor<A: Decryptor, B: Decryptor> {
+    first: A,
+    second: B,
+    name: String,
+}
+
+impl<A: Decryptor, B: Decryptor> ChainDecryptor<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        let name = format!("{} -> {}", first.get_name(), second.get_name());
+        Self {
+            first,
+            second,
+            name,
+        }
+    }
+
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+pub fn chain<A: Decryptor, B: Decryptor>(first: A, second: B) -> ChainDecryptor<A, B> {
+    ChainDecryptor::new(first, second)
+}
+
+impl<A: Decryptor, B: Decryptor> Decryptor for ChainDecryptor<A, B> {
+    fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        self.first.write(data)?;
+        self.second.write_owned(self.first.read_all_output())
+    }
+
+    fn write_owned(&mut self, chunk: Vec<u8>) -> Result<(), DecryptError> {
+        self.first.write_owned(chunk)?;
+        self.second.write_owned(self.first.read_all_output())
+    }
+
+    fn end(&mut self) -> Result<(), DecryptError> {
+        self.first.end()?;
+        self.second.write_owned(self.first.read_all_output())?;
+        self.second.end()
+    }
+
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn read_all_output(&mut self) -> Vec<u8> {
+        self.second.read_all_output()
+    }
+
+    fn get_eof_reserve(&self) -> usize {
+        self.first.get_eof_reserve()
+    }
+
+    fn output_ready(&self) -> usize {
+        self.second.output_ready()
+    }
+
+    fn reset(&mut self) -> Result<(), DecryptError> {
+        self.first.reset()?;
+        self.second.reset()
+    }
+
+    fn consume_output(&mut self, len: usize) -> Vec<u8> {
+        self.second.consume_output(len)
+    }
+
+    fn set_output_shrink_factor(&mut self, factor: usize) {
+        self.first.set_output_shrink_factor(factor);
+        self.second.set_output_shrink_factor(factor);
+    }
+
+    fn warmup(&mut self) {
+        self.first.warmup();
+        self.second.warmup();
+    }
+
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        self.first.validate_config()?;
+        self.second.validate_config()
+    }
+
+    /// Input is only ever fed to the first stage.
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        self.first.bytes_until_next_phase()
+    }
+
+    fn audio_start_offset(&self) -> u64 {
+        self.second.audio_start_offset()
+    }
+
+    /// The manifest of the format stage, under the chain's name.
+    fn manifest(&self) -> DecryptManifest {
+        DecryptManifest {
+            format: self.name.clone(),
+            ..self.second.manifest()
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{
+    decryption::{detect_format, new_decryptor},
+    decryptor::{DecryptError, DecryptErrorCode, DecryptManifest, Decryptor, SeekableDecryptor},
+    io::DETECT_HEADER_SIZE,
+    keys::KeyProvider,
+};
+
+/// A decryptor for input of unknown format: buffers the first bytes written,
+/// detects the format from them and replays them through the matching
+/// decryptor, created with keys from `keys`.
+///
+/// Detection waits for as many bytes as [`crate::io::open_decryptor`] reads,
+/// or the end of input for shorter files.
+pub struct DetectingDecryptor<K: KeyProvider> {
+    keys: K,
+    buf_in: Vec<u8>,
+    inner: Option<Box<dyn Decryptor>>,
+    /// Why detection failed, returned again by every later call.
+    failed: Option<DecryptError>,
+}
+
+impl<K: KeyProvider> DetectingDecryptor<K> {
+    pub fn new(keys: K) -> Self {
+        Self {
+            keys,
+            buf_in: vec![],
+            inner: None,
+            failed: None,
+        }
+    }
+
+    /// The decryptor picked for the input, once detected.
+    pub fn inner(&self) -> Option<&dyn Decryptor> {
+        self.inner.as_deref()
+    }
+
+    fn detect(&mut self) -> Result<&mut Box<dyn Decryptor>, DecryptError> {
+        let buf_in = std::mem::take(&mut self.buf_in);
+        let inner = detect_format(&buf_in)
+            .ok_or_else(|| DecryptError::new(DecryptErrorCode::UnsupportedFormat, "unknown format"))
+            .and_then(|format| new_decryptor(format, &self.keys));
+        match inner {
+            Ok(mut inner) => {
+                inner.write_owned(buf_in)?;
+                Ok(self.inner.insert(inner))
+            }
+            Err(err) => Err(self.failed.insert(err).clone()),
+        }
+    }
+}
+
+impl<K: KeyProvider> Decryptor for DetectingDecryptor<K> {
+    fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        if let Some(inner) = self.inner.as_mut() {
+            return inner.write(data);
+        }
+        if let Some(err) = self.failed.as_ref() {
+            return Err(err.clone());
+        }
+
+        self.buf_in.extend_from_slice(data);
+        if self.buf_in.len() >= DETECT_HEADER_SIZE {
+            self.detect()?;
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<(), DecryptError> {
+        if let Some(err) = self.failed.as_ref() {
+            return Err(err.clone());
+        }
+        match self.inner.as_mut() {
+            Some(inner) => inner.end(),
+            None if self.buf_in.is_empty() => Err(DecryptError::new(
+                DecryptErrorCode::EmptyInput,
+                "no input to detect the format from",
+            )),
+            None => self.detect()?.end(),
+        }
+    }
+
+    fn get_name(&self) -> &str {
+        match self.inner.as_ref() {
+            Some(inner) => inner.get_name(),
+            None => "Detecting",
+        }
+    }
+
+    fn read_all_output(&mut self) -> Vec<u8> {
+        self.inner
+            .as_mut()
+            .map(|inner| inner.read_all_output())
+            .unwrap_or_default()
+    }
+
+    fn get_eof_reserve(&self) -> usize {
+        self.inner
+            .as_ref()
+            .map_or(0, |inner| inner.get_eof_reserve())
+    }
+
+    fn output_ready(&self) -> usize {
+        self.inner.as_ref().map_or(0, |inner| inner.output_ready())
+    }
+
+    fn consume_output(&mut self, len: usize) -> Vec<u8> {
+        self.inner
+            .as_mut()
+            .map(|inner| inner.consume_output(len))
+            .unwrap_or_default()
+    }
+
+    /// Forgets the detected format, so a file of another format can follow.
+    fn reset(&mut self) -> Result<(), DecryptError> {
+        self.inner = None;
+        self.buf_in.clear();
+        self.failed = None;
+        Ok(())
+    }
+
+    fn set_output_shrink_factor(&mut self, factor: usize) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.set_output_shrink_factor(factor)
+        }
+    }
+
+    fn as_seekable(&self) -> Option<&dyn SeekableDecryptor> {
+        self.inner.as_ref().and_then(|inner| inner.as_seekable())
+    }
+
+    fn manifest(&self) -> DecryptManifest {
+        match self.inner.as_ref() {
+            Some(inner) => inner.manifest(),
+            None => DecryptManifest::new(self.get_name()),
+        }
+    }
+
+    fn audio_start_offset(&self) -> u64 {
+        self.inner
+            .as_ref()
+            .map_or(0, |inner| inner.audio_start_offset())
+    }
+
+    /// Keys are only looked up once the format was detected.
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        self.inner
+            .as_ref()
+            .map_or(Ok(()), |inner| inner.validate_config())
+    }
+
+    /// Until detected, the bytes left to fill the detection header.
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        match self.inner.as_ref() {
+            Some(inner) => inner.bytes_until_next_phase(),
+            None if self.failed.is_some() => None,
+            None => Some(DETECT_HEADER_SIZE.saturating_sub(self.buf_in.len())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DetectingDecryptor;
+    use crate::{decryptor::DecryptErrorCode, keys::StaticKeyProvider};
+
+    #[cfg(feature = "ncm")]
+    #[test]
+    fn test_detecting_ncm_byte_by_byte() {
+        use crate::{
+            decryption::{
+                ncm::{test::ncm_test_data, NCM_DECRYPTION_KEY_SIZE},
+                new_ncm,
+            },
+            decryptor::Decryptor,
+            keys::KeyId,
+            utils::test_util::test::{decrypt_in_chunks, generate_test_data},
+        };
+
+        let key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let encrypted = ncm_test_data();
+        let expected = decrypt_in_chunks(&mut new_ncm(key[..].try_into().unwrap()), &encrypted, 0);
+
+        let keys = StaticKeyProvider::new().with_key(KeyId::NcmCoreKey, &key);
+        let mut decryptor = DetectingDecryptor::new(keys);
+        assert_eq!(decryptor.get_name(), "Detecting");
+        assert_eq!(decrypt_in_chunks(&mut decryptor, &encrypted, 1), expected);
+        assert_eq!(decryptor.get_name(), "Netease");
+    }
+
+    #[test]
+    fn test_detecting_unknown_and_empty() {
+        use crate::decryptor::Decryptor;
+
+        let mut decryptor = DetectingDecryptor::new(StaticKeyProvider::new());
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::EmptyInput));
+
+        decryptor.write(&[0u8; 0x20]).unwrap();
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::UnsupportedFormat));
+    }
+
+    #[test]
+    fn test_bytes_until_next_phase_after_failed_detection() {
+        use crate::{decryptor::Decryptor, io::DETECT_HEADER_SIZE};
+
+        let mut decryptor = DetectingDecryptor::new(StaticKeyProvider::new());
+        assert_eq!(decryptor.bytes_until_next_phase(), Some(DETECT_HEADER_SIZE));
+        decryptor.write(&[0u8; 0x10]).unwrap();
+        assert_eq!(
+            decryptor.bytes_until_next_phase(),
+            Some(DETECT_HEADER_SIZE - 0x10)
+        );
+
+        assert!(decryptor.write(&[0u8; 0x2000]).is_err());
+        assert_eq!(decryptor.bytes_until_next_phase(), None);
+    }
+
+    #[test]
+    fn test_failed_detection_is_latched() {
+        use crate::decryptor::Decryptor;
+
+        let mut decryptor = DetectingDecryptor::new(StaticKeyProvider::new());
+        let err = decryptor.write(&[0u8; 0x2000]).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::UnsupportedFormat));
+        assert!(decryptor.buf_in.is_empty());
+
+        // Not buffered nor detected again.
+        let err = decryptor.write(&[0u8; 0x2000]).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::UnsupportedFormat));
+        assert!(decryptor.buf_in.is_empty());
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::UnsupportedFormat));
+
+        decryptor.reset().unwrap();
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::EmptyInput));
+    }
+}
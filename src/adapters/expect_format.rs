@@ -0,0 +1,218 @@
+use crate::{
+    decryptor::{
+        consume_output_buffer, DecryptError, DecryptErrorCode, DecryptManifest, Decryptor,
+        DEFAULT_OUTPUT_SHRINK_FACTOR,
+    },
+    utils::audio::{detect_audio_type, get_audio_header_metadata_size, AudioType},
+};
+
+/// Bytes `detect_audio_type` needs past any leading metadata tag.
+const SNIFF_SIZE: usize = 16;
+
+/// Checks that the decrypted output starts with the `expected` audio type,
+/// which catches a wrong key early: its output will not sniff as audio.
+///
+/// Output is held back until the check passed.
+pub struct ExpectFormatDecryptor<D: Decryptor> {
+    inner: D,
+    expected: AudioType,
+    checked: bool,
+    /// The mismatch, returned again by every later call.
+    failed: Option<DecryptError>,
+    buf_out: Vec<u8>,
+    output_shrink_factor: usize,
+}
+
+impl<D: Decryptor> ExpectFormatDecryptor<D> {
+    pub fn new(inner: D, expected: AudioType) -> Self {
+        Self {
+            inner,
+            expected,
+            checked: false,
+            failed: None,
+            buf_out: vec![],
+            output_shrink_factor: DEFAULT_OUTPUT_SHRINK_FACTOR,
+        }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn check(&mut self) -> Result<(), DecryptError> {
+        let got = detect_audio_type(&self.buf_out);
+        if got != self.expected {
+            self.buf_out.clear();
+            let err = DecryptError::new(
+                DecryptErrorCode::UnexpectedAudioFormat {
+                    expected: self.expected,
+                    got,
+                },
+                &format!("expected {:?}, got {:?}", self.expected, got),
+            );
+            return Err(self.failed.insert(err).clone());
+        }
+
+        self.checked = true;
+        Ok(())
+    }
+
+    fn ensure_not_failed(&self) -> Result<(), DecryptError> {
+        match self.failed.as_ref() {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn collect_output(&mut self, at_end: bool) -> Result<(), DecryptError> {
+        self.buf_out.append(&mut self.inner.read_all_output());
+
+        if !self.checked {
+            let needed = get_audio_header_metadata_size(&self.buf_out) + SNIFF_SIZE;
+            if at_end || self.buf_out.len() >= needed {
+                self.check()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<D: Decryptor> Decryptor for ExpectFormatDecryptor<D> {
+    fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        self.ensure_not_failed()?;
+        self.inner.write(data)?;
+        self.collect_output(false)
+    }
+
+    fn write_owned(&mut self, chunk: Vec<u8>) -> Result<(), DecryptError> {
+        self.ensure_not_failed()?;
+        self.inner.write_owned(chunk)?;
+        self.collect_output(false)
+    }
+
+    fn end(&mut self) -> Result<(), DecryptError> {
+        self.ensure_not_failed()?;
+        self.inner.end()?;
+        self.collect_output(true)
+    }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+
+    fn read_all_output(&mut self) -> Vec<u8> {
+        if self.checked {
+            std::mem::take(&mut self.buf_out)
+        } else {
+            vec![]
+        }
+    }
+
+    fn get_eof_reserve(&self) -> usize {
+        self.inner.get_eof_reserve()
+    }
+
+    fn output_ready(&self) -> usize {
+        if self.checked {
+            self.buf_out.len()
+        } else {
+            0
+        }
+    }
+
+    fn reset(&mut self) -> Result<(), DecryptError> {
+        self.inner.reset()?;
+        self.checked = false;
+        self.failed = None;
+        self.buf_out.clear();
+        Ok(())
+    }
+
+    fn consume_output(&mut self, len: usize) -> Vec<u8> {
+        if self.checked {
+            consume_output_buffer(&mut self.buf_out, len, self.output_shrink_factor)
+        } else {
+            vec![]
+        }
+    }
+
+    fn set_output_shrink_factor(&mut self, factor: usize) {
+        self.output_shrink_factor = factor;
+        self.inner.set_output_shrink_factor(factor)
+    }
+
+    fn warmup(&mut self) {
+        self.inner.warmup()
+    }
+
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        self.inner.validate_config()
+    }
+
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        self.inner.bytes_until_next_phase()
+    }
+
+    fn audio_start_offset(&self) -> u64 {
+        self.inner.audio_start_offset()
+    }
+
+    fn manifest(&self) -> DecryptManifest {
+        self.inner.manifest()
+    }
+}
+
+#[cfg(all(test, feature = "ximalaya"))]
+mod test {
+    use super::ExpectFormatDecryptor;
+    use crate::{
+        decryption::ximalaya::{build_scramble_table, new_x2m, XMLY_SCRAMBLE_SIZE},
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::{
+            audio::AudioType,
+            test_util::test::{decrypt_in_chunks, generate_test_data, EncryptedBuilder},
+        },
+    };
+
+    #[test]
+    fn test_expect_format() {
+        let key = generate_test_data(4, "expect format key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "expect format seed");
+        let table = build_scramble_table(&seed).unwrap();
+
+        let mut plain = generate_test_data(0x2000, "expect format data");
+        plain[..4].copy_from_slice(b"fLaC");
+        let encrypted = EncryptedBuilder::x2m(key[..].try_into().unwrap(), table)
+            .body(&plain)
+            .build();
+
+        let mut decryptor = ExpectFormatDecryptor::new(
+            new_x2m(key[..].try_into().unwrap(), table),
+            AudioType::FLAC,
+        );
+        assert_eq!(decrypt_in_chunks(&mut decryptor, &encrypted, 7), plain);
+
+        let mut decryptor = ExpectFormatDecryptor::new(new_x2m(*b"oops", table), AudioType::FLAC);
+        let err = decryptor.write(&encrypted).unwrap_err();
+        assert!(matches!(
+            err.code(),
+            DecryptErrorCode::UnexpectedAudioFormat {
+                expected: AudioType::FLAC,
+                ..
+            }
+        ));
+        assert!(decryptor.read_all_output().is_empty());
+
+        // The mismatch sticks: later output is not let through unchecked.
+        for err in [
+            decryptor.write(&plain).unwrap_err(),
+            decryptor.end().unwrap_err(),
+        ] {
+            assert!(matches!(
+                err.code(),
+                DecryptErrorCode::UnexpectedAudioFormat { .. }
+            ));
+        }
+        assert!(decryptor.read_all_output().is_empty());
+    }
+}
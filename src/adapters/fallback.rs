@@ -0,0 +1,271 @@
+use crate::{
+    decryptor::{
+        consume_output_buffer, DecryptError, DecryptErrorCode, DecryptManifest, Decryptor,
+        DEFAULT_OUTPUT_SHRINK_FACTOR,
+    },
+    utils::audio::{detect_audio_type, AudioType},
+};
+
+struct Candidate {
+    decryptor: Box<dyn Decryptor>,
+    buf_out: Vec<u8>,
+}
+
+impl Candidate {
+    fn collect_output(&mut self) {
+        self.buf_out.append(&mut self.decryptor.read_all_output());
+    }
+
+    fn sniffs_as_audio(&self) -> bool {
+        detect_audio_type(&self.buf_out) != AudioType::UnknownType
+    }
+}
+
+/// Tries several candidate decryptors (e.g. one per possible key) on the same
+/// input, and commits to the first whose output sniffs as audio.
+///
+/// Every candidate is fed the input until it produced `min_header_bytes` of
+/// output. Candidates are judged in order, so an earlier one wins over a later
+/// one that also looks valid. Candidates that fail or do not sniff as audio
+/// are dropped; output is held back until one was picked.
+pub struct FallbackDecryptor {
+    candidates: Vec<Candidate>,
+    chosen: Option<Candidate>,
+    min_header_bytes: usize,
+    output_shrink_factor: usize,
+}
+
+impl FallbackDecryptor {
+    pub fn new(candidates: Vec<Box<dyn Decryptor>>, min_header_bytes: usize) -> Self {
+        Self {
+            candidates: candidates
+                .into_iter()
+                .map(|decryptor| Candidate {
+                    decryptor,
+                    buf_out: vec![],
+                })
+                .collect(),
+            chosen: None,
+            min_header_bytes,
+            output_shrink_factor: DEFAULT_OUTPUT_SHRINK_FACTOR,
+        }
+    }
+
+    /// The picked decryptor, once decided.
+    pub fn chosen(&self) -> Option<&dyn Decryptor> {
+        self.chosen.as_ref().map(|c| c.decryptor.as_ref())
+    }
+
+    /// Feed `write` to every remaining candidate, dropping those that fail.
+    fn write_candidates(
+        &mut self,
+        write: impl Fn(&mut dyn Decryptor) -> Result<(), DecryptError>,
+    ) -> Result<(), DecryptError> {
+        let mut last_err = None;
+        self.candidates
+            .retain_mut(|candidate| match write(candidate.decryptor.as_mut()) {
+                Ok(()) => {
+                    candidate.collect_output();
+                    true
+                }
+                Err(err) => {
+                    last_err = Some(err);
+                    false
+                }
+            });
+
+        match last_err {
+            Some(err) if self.candidates.is_empty() => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Judge candidates in order, up to the first one without enough output
+    /// yet. At the end of input, every candidate is judged.
+    fn select(&mut self, at_end: bool) -> Result<(), DecryptError> {
+        while let Some(candidate) = self.candidates.first() {
+            if !at_end && candidate.buf_out.len() < self.min_header_bytes {
+                return Ok(());
+            }
+
+            let candidate = self.candidates.remove(0);
+            if candidate.sniffs_as_audio() {
+                self.chosen = Some(candidate);
+                self.candidates.clear();
+                return Ok(());
+            }
+        }
+
+        Err(DecryptError::new(
+            DecryptErrorCode::UnknownEncryption,
+            "no candidate decryptor produced valid audio",
+        ))
+    }
+
+    fn collect_chosen(&mut self) {
+        if let Some(chosen) = self.chosen.as_mut() {
+            chosen.collect_output();
+        }
+    }
+}
+
+impl Decryptor for FallbackDecryptor {
+    fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        match self.chosen.as_mut() {
+            Some(chosen) => chosen.decryptor.write(data)?,
+            None => {
+                self.write_candidates(|decryptor| decryptor.write(data))?;
+                self.select(false)?;
+            }
+        }
+        self.collect_chosen();
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<(), DecryptError> {
+        match self.chosen.as_mut() {
+            Some(chosen) => chosen.decryptor.end()?,
+            None => {
+                self.write_candidates(|decryptor| decryptor.end())?;
+                self.select(true)?;
+            }
+        }
+        self.collect_chosen();
+        Ok(())
+    }
+
+    fn get_name(&self) -> &str {
+        match self.chosen.as_ref().or(self.candidates.first()) {
+            Some(candidate) => candidate.decryptor.get_name(),
+            None => "Fallback",
+        }
+    }
+
+    fn read_all_output(&mut self) -> Vec<u8> {
+        match self.chosen.as_mut() {
+            Some(chosen) => std::mem::take(&mut chosen.buf_out),
+            None => vec![],
+        }
+    }
+
+    fn get_eof_reserve(&self) -> usize {
+        match self.chosen.as_ref() {
+            Some(chosen) => chosen.decryptor.get_eof_reserve(),
+            None => self
+                .candidates
+                .iter()
+                .map(|c| c.decryptor.get_eof_reserve())
+                .max()
+                .unwrap_or(0),
+        }
+    }
+
+    fn output_ready(&self) -> usize {
+        self.chosen.as_ref().map_or(0, |c| c.buf_out.len())
+    }
+
+    fn consume_output(&mut self, len: usize) -> Vec<u8> {
+        match self.chosen.as_mut() {
+            Some(chosen) => {
+                consume_output_buffer(&mut chosen.buf_out, len, self.output_shrink_factor)
+            }
+            None => vec![],
+        }
+    }
+
+    fn set_output_shrink_factor(&mut self, factor: usize) {
+        self.output_shrink_factor = factor;
+        for candidate in self.candidates.iter_mut().chain(self.chosen.as_mut()) {
+            candidate.decryptor.set_output_shrink_factor(factor);
+        }
+    }
+
+    fn warmup(&mut self) {
+        for candidate in self.candidates.iter_mut().chain(self.chosen.as_mut()) {
+            candidate.decryptor.warmup();
+        }
+    }
+
+    /// Before a candidate was picked, valid as long as one candidate is, as
+    /// failing candidates are dropped.
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        if let Some(chosen) = self.chosen.as_ref() {
+            return chosen.decryptor.validate_config();
+        }
+
+        let mut first_err = None;
+        for candidate in self.candidates.iter() {
+            match candidate.decryptor.validate_config() {
+                Ok(()) => return Ok(()),
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+        first_err.map_or(Ok(()), Err)
+    }
+
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        self.chosen
+            .as_ref()
+            .and_then(|chosen| chosen.decryptor.bytes_until_next_phase())
+    }
+
+    fn manifest(&self) -> DecryptManifest {
+        match self.chosen.as_ref() {
+            Some(chosen) => chosen.decryptor.manifest(),
+            None => DecryptManifest::new(self.get_name()),
+        }
+    }
+
+    fn audio_start_offset(&self) -> u64 {
+        self.chosen
+            .as_ref()
+            .map_or(0, |c| c.decryptor.audio_start_offset())
+    }
+}
+
+#[cfg(all(test, feature = "ximalaya"))]
+mod test {
+    use super::FallbackDecryptor;
+    use crate::{
+        decryption::ximalaya::{build_scramble_table, new_x2m, X2M_CONTENT_KEY_SIZE},
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::test_util::test::{decrypt_in_chunks, generate_test_data, EncryptedBuilder},
+    };
+
+    #[test]
+    fn test_fallback_picks_right_key() {
+        let right_key = generate_test_data(X2M_CONTENT_KEY_SIZE, "fallback right key");
+        let wrong_key = generate_test_data(X2M_CONTENT_KEY_SIZE, "fallback wrong key");
+        let table = build_scramble_table(&generate_test_data(2048, "fallback seed")).unwrap();
+
+        let mut plain = generate_test_data(0x3000, "fallback plain");
+        plain[..4].copy_from_slice(b"fLaC");
+        let encrypted = EncryptedBuilder::x2m(right_key[..].try_into().unwrap(), table)
+            .body(&plain)
+            .build();
+
+        let candidates = || -> Vec<Box<dyn Decryptor>> {
+            vec![
+                new_x2m(wrong_key[..].try_into().unwrap(), table).into_boxed(),
+                new_x2m(right_key[..].try_into().unwrap(), table).into_boxed(),
+            ]
+        };
+
+        for chunk_size in [0, 7, 0x100] {
+            let mut decryptor = FallbackDecryptor::new(candidates(), 0x400);
+            assert_eq!(
+                decrypt_in_chunks(&mut decryptor, &encrypted, chunk_size),
+                plain
+            );
+            assert!(decryptor.chosen().is_some());
+        }
+
+        let mut only_wrong = candidates();
+        only_wrong.truncate(1);
+        let mut decryptor = FallbackDecryptor::new(only_wrong, 0x400);
+        let err = decryptor.write(&encrypted).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::UnknownEncryption));
+    }
+}
@@ -0,0 +1,205 @@
+use std::io::Write;
+
+use flate2::write::GzDecoder;
+
+use crate::decryptor::{
+    consume_output_buffer, DecryptError, DecryptErrorCode, DecryptManifest, Decryptor,
+    DEFAULT_OUTPUT_SHRINK_FACTOR,
+};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+enum State {
+    /// Fewer than `GZIP_MAGIC.len()` bytes of output seen so far.
+    Detect(Vec<u8>),
+    Gzip(GzDecoder<Vec<u8>>),
+    Plain,
+}
+
+/// Inflates the decrypted output when it starts with the gzip magic, and
+/// passes any other output through unchanged.
+pub struct GunzipDecryptor<D: Decryptor> {
+    inner: D,
+    state: State,
+    buf_out: Vec<u8>,
+    output_shrink_factor: usize,
+}
+
+fn inflate_error(_: std::io::Error) -> DecryptError {
+    DecryptError::new(DecryptErrorCode::IOError, "invalid gzip output")
+}
+
+impl<D: Decryptor> GunzipDecryptor<D> {
+    pub fn new(inner: D) -> Self {
+        Self {
+            inner,
+            state: State::Detect(vec![]),
+            buf_out: vec![],
+            output_shrink_factor: DEFAULT_OUTPUT_SHRINK_FACTOR,
+        }
+    }
+
+    /// Whether the output was found to be gzip'd; `None` until enough output
+    /// was seen to tell.
+    pub fn is_gzip(&self) -> Option<bool> {
+        match self.state {
+            State::Detect(_) => None,
+            State::Gzip(_) => Some(true),
+            State::Plain => Some(false),
+        }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn process_output(&mut self) -> Result<(), DecryptError> {
+        let mut output = self.inner.read_all_output();
+        if let State::Detect(head) = &mut self.state {
+            head.append(&mut output);
+            if head.len() < GZIP_MAGIC.len() {
+                return Ok(());
+            }
+            output = std::mem::take(head);
+            self.state = match output.starts_with(&GZIP_MAGIC) {
+                true => State::Gzip(GzDecoder::new(vec![])),
+                false => State::Plain,
+            };
+        }
+
+        match &mut self.state {
+            State::Gzip(decoder) => {
+                decoder.write_all(&output).map_err(inflate_error)?;
+                self.buf_out.append(decoder.get_mut());
+            }
+            _ => self.buf_out.append(&mut output),
+        }
+        Ok(())
+    }
+}
+
+impl<D: Decryptor> Decryptor for GunzipDecryptor<D> {
+    fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        self.inner.write(data)?;
+        self.process_output()
+    }
+
+    fn write_owned(&mut self, chunk: Vec<u8>) -> Result<(), DecryptError> {
+        self.inner.write_owned(chunk)?;
+        self.process_output()
+    }
+
+    fn end(&mut self) -> Result<(), DecryptError> {
+        self.inner.end()?;
+        self.process_output()?;
+        match &mut self.state {
+            State::Detect(head) => {
+                self.buf_out.append(head);
+                self.state = State::Plain;
+            }
+            State::Gzip(decoder) => {
+                decoder.try_finish().map_err(inflate_error)?;
+                self.buf_out.append(decoder.get_mut());
+            }
+            State::Plain => {}
+        }
+        Ok(())
+    }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+
+    fn read_all_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf_out)
+    }
+
+    fn get_eof_reserve(&self) -> usize {
+        self.inner.get_eof_reserve()
+    }
+
+    fn output_ready(&self) -> usize {
+        self.buf_out.len()
+    }
+
+    fn reset(&mut self) -> Result<(), DecryptError> {
+        self.inner.reset()?;
+        self.state = State::Detect(vec![]);
+        self.buf_out.clear();
+        Ok(())
+    }
+
+    fn consume_output(&mut self, len: usize) -> Vec<u8> {
+        consume_output_buffer(&mut self.buf_out, len, self.output_shrink_factor)
+    }
+
+    fn set_output_shrink_factor(&mut self, factor: usize) {
+        self.output_shrink_factor = factor;
+        self.inner.set_output_shrink_factor(factor)
+    }
+
+    fn warmup(&mut self) {
+        self.inner.warmup()
+    }
+
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        self.inner.validate_config()
+    }
+
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        self.inner.bytes_until_next_phase()
+    }
+
+    fn audio_start_offset(&self) -> u64 {
+        match self.state {
+            // Offsets of the compressed stream do not carry over.
+            State::Gzip(_) => 0,
+            _ => self.inner.audio_start_offset(),
+        }
+    }
+
+    fn manifest(&self) -> DecryptManifest {
+        self.inner.manifest()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::GunzipDecryptor;
+    use crate::{
+        decryption::new_passthrough,
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::test_util::test::{decrypt_in_chunks, generate_test_data},
+    };
+
+    #[test]
+    fn test_gunzip_output() {
+        let plain = generate_test_data(0x8000, "gunzip data");
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&plain).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        for chunk in [1, 0x100, 0] {
+            let mut decryptor = GunzipDecryptor::new(new_passthrough());
+            assert_eq!(decrypt_in_chunks(&mut decryptor, &gzipped, chunk), plain);
+            assert_eq!(decryptor.is_gzip(), Some(true));
+
+            let mut decryptor = GunzipDecryptor::new(new_passthrough());
+            assert_eq!(decrypt_in_chunks(&mut decryptor, &plain, chunk), plain);
+            assert_eq!(decryptor.is_gzip(), Some(false));
+        }
+
+        // Too short to hold the magic.
+        let mut decryptor = GunzipDecryptor::new(new_passthrough());
+        assert_eq!(decrypt_in_chunks(&mut decryptor, &[0x1f], 0), [0x1f]);
+
+        let mut decryptor = GunzipDecryptor::new(new_passthrough());
+        decryptor.write(&gzipped[..gzipped.len() / 2]).unwrap();
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::IOError));
+    }
+}
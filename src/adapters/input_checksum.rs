@@ -0,0 +1,122 @@
+use crate::{
+    decryptor::{DecryptError, DecryptManifest, Decryptor},
+    utils::crc32::Crc32,
+};
+
+/// Keeps a CRC32 of every byte written, to check the encrypted file against a
+/// known checksum. The output is passed through untouched.
+pub struct InputChecksum<D: Decryptor> {
+    inner: D,
+    crc: Crc32,
+}
+
+impl<D: Decryptor> InputChecksum<D> {
+    pub fn new(inner: D) -> Self {
+        Self {
+            inner,
+            crc: Crc32::new(),
+        }
+    }
+
+    /// CRC32 of the input written so far.
+    pub fn input_crc32(&self) -> u32 {
+        self.crc.value()
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D: Decryptor> Decryptor for InputChecksum<D> {
+    fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        self.crc.update(data);
+        self.inner.write(data)
+    }
+
+    fn write_owned(&mut self, chunk: Vec<u8>) -> Result<(), DecryptError> {
+        self.crc.update(&chunk);
+        self.inner.write_owned(chunk)
+    }
+
+    fn end(&mut self) -> Result<(), DecryptError> {
+        self.inner.end()
+    }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+
+    fn read_all_output(&mut self) -> Vec<u8> {
+        self.inner.read_all_output()
+    }
+
+    fn get_eof_reserve(&self) -> usize {
+        self.inner.get_eof_reserve()
+    }
+
+    fn output_ready(&self) -> usize {
+        self.inner.output_ready()
+    }
+
+    fn reset(&mut self) -> Result<(), DecryptError> {
+        self.inner.reset()?;
+        self.crc = Crc32::new();
+        Ok(())
+    }
+
+    fn consume_output(&mut self, len: usize) -> Vec<u8> {
+        self.inner.consume_output(len)
+    }
+
+    fn set_output_shrink_factor(&mut self, factor: usize) {
+        self.inner.set_output_shrink_factor(factor)
+    }
+
+    fn warmup(&mut self) {
+        self.inner.warmup()
+    }
+
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        self.inner.validate_config()
+    }
+
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        self.inner.bytes_until_next_phase()
+    }
+
+    fn audio_start_offset(&self) -> u64 {
+        self.inner.audio_start_offset()
+    }
+
+    fn manifest(&self) -> DecryptManifest {
+        self.inner.manifest()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::InputChecksum;
+    use crate::{
+        decryption::new_passthrough,
+        utils::test_util::test::{decrypt_in_chunks, generate_test_data, TEST_SIZE_1MB},
+    };
+
+    #[test]
+    fn test_input_crc32() {
+        let data = generate_test_data(TEST_SIZE_1MB, "input checksum data");
+
+        for chunk in [1, 7, 0x1000] {
+            let mut decryptor = InputChecksum::new(new_passthrough());
+            assert_eq!(
+                decrypt_in_chunks(&mut decryptor, &data[..0x2000], chunk),
+                &data[..0x2000]
+            );
+            assert_eq!(decryptor.input_crc32(), crc32fast::hash(&data[..0x2000]));
+        }
+
+        let mut decryptor = InputChecksum::new(new_passthrough());
+        decrypt_in_chunks(&mut decryptor, &data, 0x1000);
+        assert_eq!(decryptor.input_crc32(), crc32fast::hash(&data));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::decryptor::{DecryptError, DecryptErrorCode, DecryptManifest, Decryptor};
+
+/// Caps the total input at `max_bytes`, to guard services against oversized
+/// uploads. The write crossing the limit fails before any of it is processed.
+pub struct LimitedInputDecryptor<D: Decryptor> {
+    inner: D,
+    max_bytes: u64,
+    received: u64,
+}
+
+impl<D: Decryptor> LimitedInputDecryptor<D> {
+    pub fn new(inner: D, max_bytes: u64) -> Self {
+        Self {
+            inner,
+            max_bytes,
+            received: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn count_input(&mut self, len: usize) -> Result<(), DecryptError> {
+        let received = self.received.saturating_add(len as u64);
+        if received > self.max_bytes {
+            return Err(DecryptError::new(
+                DecryptErrorCode::InputTooLarge {
+                    limit: self.max_bytes,
+                },
+                &format!("input exceeds {} bytes", self.max_bytes),
+            ));
+        }
+
+        self.received = received;
+        Ok(())
+    }
+}
+
+impl<D: Decryptor> Decryptor for LimitedInputDecryptor<D> {
+    fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        self.count_input(data.len())?;
+        self.inner.write(data)
+    }
+
+    fn write_owned(&mut self, chunk: Vec<u8>) -> Result<(), DecryptError> {
+        self.count_input(chunk.len())?;
+        self.inner.write_owned(chunk)
+    }
+
+    fn end(&mut self) -> Result<(), DecryptError> {
+        self.inner.end()
+    }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+
+    fn read_all_output(&mut self) -> Vec<u8> {
+        self.inner.read_all_output()
+    }
+
+    fn get_eof_reserve(&self) -> usize {
+        self.inner.get_eof_reserve()
+    }
+
+    fn output_ready(&self) -> usize {
+        self.inner.output_ready()
+    }
+
+    fn reset(&mut self) -> Result<(), DecryptError> {
+        self.inner.reset()?;
+        self.received = 0;
+        Ok(())
+    }
+
+    fn consume_output(&mut self, len: usize) -> Vec<u8> {
+        self.inner.consume_output(len)
+    }
+
+    fn set_output_shrink_factor(&mut self, factor: usize) {
+        self.inner.set_output_shrink_factor(factor)
+    }
+
+    fn warmup(&mut self) {
+        self.inner.warmup()
+    }
+
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        self.inner.validate_config()
+    }
+
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        self.inner.bytes_until_next_phase()
+    }
+
+    fn audio_start_offset(&self) -> u64 {
+        self.inner.audio_start_offset()
+    }
+
+    fn manifest(&self) -> DecryptManifest {
+        self.inner.manifest()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LimitedInputDecryptor;
+    use crate::{
+        decryption::new_passthrough,
+        decryptor::{DecryptErrorCode, Decryptor},
+    };
+
+    #[test]
+    fn test_input_limit() {
+        let mut decryptor = LimitedInputDecryptor::new(new_passthrough(), 0x100);
+        decryptor.write(&[1u8; 0x80]).unwrap();
+
+        let err = decryptor.write(&[2u8; 0x81]).unwrap_err();
+        assert!(matches!(
+            err.code(),
+            DecryptErrorCode::InputTooLarge { limit: 0x100 }
+        ));
+        assert_eq!(decryptor.read_all_output(), [1u8; 0x80]);
+
+        // Exactly at the limit is fine.
+        decryptor.write(&[3u8; 0x80]).unwrap();
+    }
+}
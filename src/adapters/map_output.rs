@@ -0,0 +1,150 @@
+use crate::decryptor::{
+    consume_output_buffer, DecryptError, DecryptManifest, Decryptor, DEFAULT_OUTPUT_SHRINK_FACTOR,
+};
+
+/// Applies `map` to the complete output once the input ended, for final
+/// fixups (e.g. restoring a magic) that are not part of the cipher itself.
+///
+/// The whole output is held back until `end`.
+pub struct MapOutputDecryptor<D: Decryptor, F: FnMut(&mut Vec<u8>)> {
+    inner: D,
+    map: F,
+    finished: bool,
+    buf_out: Vec<u8>,
+    output_shrink_factor: usize,
+}
+
+impl<D: Decryptor, F: FnMut(&mut Vec<u8>)> MapOutputDecryptor<D, F> {
+    pub fn new(inner: D, map: F) -> Self {
+        Self {
+            inner,
+            map,
+            finished: false,
+            buf_out: vec![],
+            output_shrink_factor: DEFAULT_OUTPUT_SHRINK_FACTOR,
+        }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D: Decryptor, F: FnMut(&mut Vec<u8>)> Decryptor for MapOutputDecryptor<D, F> {
+    fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        self.inner.write(data)?;
+        self.buf_out.append(&mut self.inner.read_all_output());
+        Ok(())
+    }
+
+    fn write_owned(&mut self, chunk: Vec<u8>) -> Result<(), DecryptError> {
+        self.inner.write_owned(chunk)?;
+        self.buf_out.append(&mut self.inner.read_all_output());
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<(), DecryptError> {
+        self.inner.end()?;
+        self.buf_out.append(&mut self.inner.read_all_output());
+        if !self.finished {
+            (self.map)(&mut self.buf_out);
+            self.finished = true;
+        }
+        Ok(())
+    }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+
+    fn read_all_output(&mut self) -> Vec<u8> {
+        if self.finished {
+            std::mem::take(&mut self.buf_out)
+        } else {
+            vec![]
+        }
+    }
+
+    fn get_eof_reserve(&self) -> usize {
+        self.inner.get_eof_reserve()
+    }
+
+    fn output_ready(&self) -> usize {
+        if self.finished {
+            self.buf_out.len()
+        } else {
+            0
+        }
+    }
+
+    fn reset(&mut self) -> Result<(), DecryptError> {
+        self.inner.reset()?;
+        self.finished = false;
+        self.buf_out.clear();
+        Ok(())
+    }
+
+    fn consume_output(&mut self, len: usize) -> Vec<u8> {
+        if self.finished {
+            consume_output_buffer(&mut self.buf_out, len, self.output_shrink_factor)
+        } else {
+            vec![]
+        }
+    }
+
+    fn set_output_shrink_factor(&mut self, factor: usize) {
+        self.output_shrink_factor = factor;
+        self.inner.set_output_shrink_factor(factor)
+    }
+
+    fn warmup(&mut self) {
+        self.inner.warmup()
+    }
+
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        self.inner.validate_config()
+    }
+
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        self.inner.bytes_until_next_phase()
+    }
+
+    fn audio_start_offset(&self) -> u64 {
+        self.inner.audio_start_offset()
+    }
+
+    fn manifest(&self) -> DecryptManifest {
+        self.inner.manifest()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MapOutputDecryptor;
+    use crate::{
+        decryption::new_passthrough,
+        decryptor::Decryptor,
+        utils::test_util::test::{decrypt_in_chunks, generate_test_data},
+    };
+
+    #[test]
+    fn test_map_output_restores_magic() {
+        let plain = generate_test_data(0x1000, "map output data");
+        let mut encrypted = plain.clone();
+        encrypted[4..8].copy_from_slice(&[0; 4]);
+
+        let mut decryptor = MapOutputDecryptor::new(new_passthrough(), |output: &mut Vec<u8>| {
+            output[4..8].copy_from_slice(b"ftyp")
+        });
+        decryptor.write(&encrypted[..0x10]).unwrap();
+        assert_eq!(decryptor.output_ready(), 0);
+        assert!(decryptor.read_all_output().is_empty());
+
+        let mut decryptor = MapOutputDecryptor::new(new_passthrough(), |output: &mut Vec<u8>| {
+            output[4..8].copy_from_slice(b"ftyp")
+        });
+        let result = decrypt_in_chunks(&mut decryptor, &encrypted, 7);
+        assert_eq!(&result[4..8], b"ftyp");
+        assert_eq!(result[8..], plain[8..]);
+    }
+}
@@ -0,0 +1,91 @@
+mod block_align;
+mod chain;
+mod detecting;
+mod expect_format;
+mod fallback;
+#[cfg(feature = "flate2")]
+mod gunzip;
+mod input_checksum;
+mod limited_input;
+mod map_output;
+mod multi_hash;
+mod tee;
+mod throughput;
+
+pub use block_align::BlockAlignDecryptor;
+pub use chain::{chain, ChainDecryptor};
+pub use detecting::DetectingDecryptor;
+pub use expect_format::ExpectFormatDecryptor;
+pub use fallback::FallbackDecryptor;
+#[cfg(feature = "flate2")]
+pub use gunzip::GunzipDecryptor;
+pub use input_checksum::InputChecksum;
+pub use limited_input::LimitedInputDecryptor;
+pub use map_output::MapOutputDecryptor;
+pub use multi_hash::{HashAlgorithm, MultiHashDecryptor};
+pub use tee::TeeDecryptor;
+pub use throughput::ThroughputDecryptor;
+
+#[cfg(test)]
+mod test {
+    use super::{
+        chain, ExpectFormatDecryptor, InputChecksum, LimitedInputDecryptor, ThroughputDecryptor,
+    };
+    use crate::{
+        decryption::new_passthrough,
+        decryptor::{DecryptError, Decryptor},
+        utils::audio::AudioType,
+    };
+
+    /// Passes input through, claiming the audio starts at `0x20`.
+    struct PrefixedAudio<D: Decryptor>(D);
+
+    impl<D: Decryptor> Decryptor for PrefixedAudio<D> {
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            self.0.write(data)
+        }
+
+        fn get_name(&self) -> &str {
+            "PrefixedAudio"
+        }
+
+        fn read_all_output(&mut self) -> Vec<u8> {
+            self.0.read_all_output()
+        }
+
+        fn get_eof_reserve(&self) -> usize {
+            0
+        }
+
+        fn output_ready(&self) -> usize {
+            self.0.output_ready()
+        }
+
+        fn consume_output(&mut self, len: usize) -> Vec<u8> {
+            self.0.consume_output(len)
+        }
+
+        fn audio_start_offset(&self) -> u64 {
+            0x20
+        }
+    }
+
+    #[test]
+    fn test_adapters_forward_audio_start_offset() {
+        let prefixed = || PrefixedAudio(new_passthrough());
+        assert_eq!(new_passthrough().audio_start_offset(), 0);
+
+        let wrapped = ThroughputDecryptor::new(LimitedInputDecryptor::new(
+            InputChecksum::new(ExpectFormatDecryptor::new(prefixed(), AudioType::FLAC)),
+            0x100,
+        ));
+        assert_eq!(wrapped.audio_start_offset(), 0x20);
+
+        // A chain reports where the audio starts in the final output.
+        assert_eq!(chain(prefixed(), new_passthrough()).audio_start_offset(), 0);
+        assert_eq!(
+            chain(new_passthrough(), prefixed()).audio_start_offset(),
+            0x20
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+use crate::{
+    decryptor::{DecryptError, DecryptManifest, Decryptor},
+    utils::{md5::Md5, sha1::Sha1},
+};
+
+/// Digests [`MultiHashDecryptor`] can compute. SHA-256 needs the `ring`
+/// feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    #[cfg(feature = "ring")]
+    Sha256,
+}
+
+#[derive(Clone)]
+enum Hasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    #[cfg(feature = "ring")]
+    Ring(ring::digest::Context),
+}
+
+impl Hasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            HashAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            #[cfg(feature = "ring")]
+            HashAlgorithm::Sha256 => {
+                Hasher::Ring(ring::digest::Context::new(&ring::digest::SHA256))
+            }
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(md5) => md5.update(data),
+            Hasher::Sha1(sha1) => sha1.update(data),
+            #[cfg(feature = "ring")]
+            Hasher::Ring(context) => context.update(data),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            Hasher::Md5(md5) => md5.finish().to_vec(),
+            Hasher::Sha1(sha1) => sha1.finish().to_vec(),
+            #[cfg(feature = "ring")]
+            Hasher::Ring(context) => context.finish().as_ref().to_vec(),
+        }
+    }
+}
+
+/// Hashes the decrypted output with several algorithms in a single pass, as
+/// the output is read. The output is passed through untouched.
+pub struct MultiHashDecryptor<D: Decryptor> {
+    inner: D,
+    hashers: Vec<(HashAlgorithm, Hasher)>,
+}
+
+impl<D: Decryptor> MultiHashDecryptor<D> {
+    pub fn new(inner: D, algorithms: &[HashAlgorithm]) -> Self {
+        let mut hashers: Vec<(HashAlgorithm, Hasher)> = vec![];
+        for &algorithm in algorithms {
+            if !hashers.iter().any(|(a, _)| *a == algorithm) {
+                hashers.push((algorithm, Hasher::new(algorithm)));
+            }
+        }
+        Self { inner, hashers }
+    }
+
+    /// Digests of the output read so far.
+    pub fn digests(&self) -> HashMap<HashAlgorithm, Vec<u8>> {
+        self.hashers
+            .iter()
+            .map(|(algorithm, hasher)| (*algorithm, hasher.clone().finish()))
+            .collect()
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn hash(&mut self, output: &[u8]) {
+        for (_, hasher) in self.hashers.iter_mut() {
+            hasher.update(output);
+        }
+    }
+}
+
+impl<D: Decryptor> Decryptor for MultiHashDecryptor<D> {
+    fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        self.inner.write(data)
+    }
+
+    fn write_owned(&mut self, chunk: Vec<u8>) -> Result<(), DecryptError> {
+        self.inner.write_owned(chunk)
+    }
+
+    fn end(&mut self) -> Result<(), DecryptError> {
+        self.inner.end()
+    }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+
+    fn read_all_output(&mut self) -> Vec<u8> {
+        let output = self.inner.read_all_output();
+        self.hash(&output);
+        output
+    }
+
+    fn get_eof_reserve(&self) -> usize {
+        self.inner.get_eof_reserve()
+    }
+
+    fn output_ready(&self) -> usize {
+        self.inner.output_ready()
+    }
+
+    fn reset(&mut self) -> Result<(), DecryptError> {
+        self.inner.reset()?;
+        for (algorithm, hasher) in self.hashers.iter_mut() {
+            *hasher = Hasher::new(*algorithm);
+        }
+        Ok(())
+    }
+
+    fn consume_output(&mut self, len: usize) -> Vec<u8> {
+        let output = self.inner.consume_output(len);
+        self.hash(&output);
+        output
+    }
+
+    fn set_output_shrink_factor(&mut self, factor: usize) {
+        self.inner.set_output_shrink_factor(factor)
+    }
+
+    fn warmup(&mut self) {
+        self.inner.warmup()
+    }
+
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        self.inner.validate_config()
+    }
+
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        self.inner.bytes_until_next_phase()
+    }
+
+    fn audio_start_offset(&self) -> u64 {
+        self.inner.audio_start_offset()
+    }
+
+    fn manifest(&self) -> DecryptManifest {
+        self.inner.manifest()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HashAlgorithm, MultiHashDecryptor};
+    use crate::{decryption::new_passthrough, utils::test_util::test::decrypt_in_chunks};
+
+    #[test]
+    fn test_md5_over_chunks() {
+        let data =
+            b"12345678901234567890123456789012345678901234567890123456789012345678901234567890";
+        let mut decryptor = MultiHashDecryptor::new(new_passthrough(), &[HashAlgorithm::Md5]);
+        assert_eq!(decrypt_in_chunks(&mut decryptor, data, 7), data);
+
+        let digests = decryptor.digests();
+        assert_eq!(digests.len(), 1);
+        assert_eq!(
+            data_encoding::HEXLOWER.encode(&digests[&HashAlgorithm::Md5]),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+    }
+
+    #[test]
+    fn test_md5_and_sha1() {
+        let data = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+        let mut decryptor = MultiHashDecryptor::new(
+            new_passthrough(),
+            &[HashAlgorithm::Md5, HashAlgorithm::Sha1],
+        );
+        assert_eq!(decrypt_in_chunks(&mut decryptor, data, 5), data);
+
+        let digests = decryptor.digests();
+        assert_eq!(
+            data_encoding::HEXLOWER.encode(&digests[&HashAlgorithm::Md5]),
+            "8215ef0796a20bcaaae116d3876c664a"
+        );
+        assert_eq!(
+            data_encoding::HEXLOWER.encode(&digests[&HashAlgorithm::Sha1]),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[cfg(all(feature = "ring", feature = "ximalaya"))]
+    #[test]
+    fn test_x2m_md5_and_sha256() {
+        use crate::{
+            decryption::ximalaya::{build_scramble_table, new_x2m, X2M_CONTENT_KEY_SIZE},
+            decryptor::Decryptor,
+            utils::test_util::test::{generate_test_data, TEST_SIZE_1MB},
+        };
+
+        let test_data = generate_test_data(TEST_SIZE_1MB, "x2m-test-data");
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m content key");
+        let table = build_scramble_table(&generate_test_data(2048, "x2m seed")).unwrap();
+
+        let mut decryptor = MultiHashDecryptor::new(
+            new_x2m(key[..].try_into().unwrap(), table),
+            &[HashAlgorithm::Md5, HashAlgorithm::Sha256],
+        );
+        decryptor.write(&test_data).unwrap();
+        decryptor.end().unwrap();
+        decryptor.read_all_output();
+
+        let digests = decryptor.digests();
+        assert_eq!(
+            data_encoding::HEXLOWER.encode(&digests[&HashAlgorithm::Md5]),
+            "783201cadc24b803dcf3348a1311861a"
+        );
+        // Same reference as `ximalaya::test::test_x2m`.
+        assert_eq!(
+            data_encoding::HEXLOWER.encode(&digests[&HashAlgorithm::Sha256]),
+            "fd1ac1c4750f48b8d3c9562013f1c3202b12e45137b344995eda32a4f6b8a61f"
+        );
+    }
+}
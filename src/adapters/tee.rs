@@ -0,0 +1,174 @@
+use std::io::Write;
+
+use crate::{
+    decryptor::{
+        consume_output_buffer, DecryptError, DecryptManifest, Decryptor,
+        DEFAULT_OUTPUT_SHRINK_FACTOR,
+    },
+    io::io_error,
+};
+
+/// Mirrors every decrypted byte to a secondary sink (e.g. a cache file) as it
+/// is produced, while the output is still drained through `read_all_output`.
+pub struct TeeDecryptor<D: Decryptor, W: Write> {
+    inner: D,
+    sink: W,
+    buf_out: Vec<u8>,
+    output_shrink_factor: usize,
+}
+
+impl<D: Decryptor, W: Write> TeeDecryptor<D, W> {
+    pub fn new(inner: D, sink: W) -> Self {
+        Self {
+            inner,
+            sink,
+            buf_out: vec![],
+            output_shrink_factor: DEFAULT_OUTPUT_SHRINK_FACTOR,
+        }
+    }
+
+    pub fn sink(&self) -> &W {
+        &self.sink
+    }
+
+    pub fn into_inner(self) -> (D, W) {
+        (self.inner, self.sink)
+    }
+
+    /// The output is kept for `read_all_output` even if the sink fails.
+    fn mirror_output(&mut self) -> Result<(), DecryptError> {
+        let start = self.buf_out.len();
+        self.buf_out.append(&mut self.inner.read_all_output());
+        self.sink
+            .write_all(&self.buf_out[start..])
+            .map_err(|err| io_error("write to tee sink failed", err))
+    }
+}
+
+impl<D: Decryptor, W: Write> Decryptor for TeeDecryptor<D, W> {
+    fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        self.inner.write(data)?;
+        self.mirror_output()
+    }
+
+    fn write_owned(&mut self, chunk: Vec<u8>) -> Result<(), DecryptError> {
+        self.inner.write_owned(chunk)?;
+        self.mirror_output()
+    }
+
+    fn end(&mut self) -> Result<(), DecryptError> {
+        self.inner.end()?;
+        self.mirror_output()?;
+        self.sink
+            .flush()
+            .map_err(|err| io_error("flush tee sink failed", err))
+    }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+
+    fn read_all_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf_out)
+    }
+
+    fn get_eof_reserve(&self) -> usize {
+        self.inner.get_eof_reserve()
+    }
+
+    fn output_ready(&self) -> usize {
+        self.buf_out.len()
+    }
+
+    /// Resets the inner decryptor only: whatever reached the sink stays.
+    fn reset(&mut self) -> Result<(), DecryptError> {
+        self.inner.reset()?;
+        self.buf_out.clear();
+        Ok(())
+    }
+
+    fn consume_output(&mut self, len: usize) -> Vec<u8> {
+        consume_output_buffer(&mut self.buf_out, len, self.output_shrink_factor)
+    }
+
+    fn set_output_shrink_factor(&mut self, factor: usize) {
+        self.output_shrink_factor = factor;
+        self.inner.set_output_shrink_factor(factor)
+    }
+
+    fn warmup(&mut self) {
+        self.inner.warmup()
+    }
+
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        self.inner.validate_config()
+    }
+
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        self.inner.bytes_until_next_phase()
+    }
+
+    fn audio_start_offset(&self) -> u64 {
+        self.inner.audio_start_offset()
+    }
+
+    fn manifest(&self) -> DecryptManifest {
+        self.inner.manifest()
+    }
+}
+
+#[cfg(all(test, feature = "ximalaya"))]
+mod test {
+    use std::io::{self, Write};
+
+    use super::TeeDecryptor;
+    use crate::{
+        decryption::{
+            new_passthrough,
+            ximalaya::{build_scramble_table, new_x2m, XMLY_SCRAMBLE_SIZE},
+        },
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::test_util::test::{generate_test_data, TEST_SIZE_1MB},
+    };
+
+    #[test]
+    fn test_tee_sink_matches_output() {
+        let test_data = generate_test_data(TEST_SIZE_1MB, "x2m-test-data");
+        let key = generate_test_data(4, "x2m content key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m seed");
+        let table = build_scramble_table(&seed).unwrap();
+
+        let mut decryptor = TeeDecryptor::new(new_x2m(key.try_into().unwrap(), table), vec![]);
+        let mut output = vec![];
+        for chunk in test_data.chunks(4096 + 7) {
+            decryptor.write(chunk).unwrap();
+            output.append(&mut decryptor.read_all_output());
+        }
+        decryptor.end().unwrap();
+        output.append(&mut decryptor.read_all_output());
+
+        assert_eq!(output.len(), test_data.len());
+        assert_eq!(decryptor.sink(), &output);
+    }
+
+    struct FailingSink;
+
+    impl Write for FailingSink {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tee_sink_error_keeps_output() {
+        let mut decryptor = TeeDecryptor::new(new_passthrough(), FailingSink);
+        let err = decryptor.write(b"payload").unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::IOError));
+        assert!(err.to_string().contains("disk full"), "{}", err);
+        assert_eq!(decryptor.read_all_output(), b"payload");
+    }
+}
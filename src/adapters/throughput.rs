@@ -0,0 +1,148 @@
+use std::time::{Duration, Instant};
+
+use crate::decryptor::{DecryptError, DecryptManifest, Decryptor};
+
+/// Measures the wall-clock time spent in `write` and `end`, to spot formats
+/// that decrypt unexpectedly slowly.
+pub struct ThroughputDecryptor<D: Decryptor> {
+    inner: D,
+    bytes_in: u64,
+    elapsed: Duration,
+}
+
+impl<D: Decryptor> ThroughputDecryptor<D> {
+    pub fn new(inner: D) -> Self {
+        Self {
+            inner,
+            bytes_in: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Input bytes written so far.
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in
+    }
+
+    /// Time spent inside the decryptor so far.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Input processed per second of decryption time, in MB (10^6 bytes).
+    /// Zero until any time has been measured.
+    pub fn mb_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.bytes_in as f64 / 1_000_000.0 / secs
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn timed<T>(&mut self, f: impl FnOnce(&mut D) -> T) -> T {
+        let start = Instant::now();
+        let result = f(&mut self.inner);
+        self.elapsed += start.elapsed();
+        result
+    }
+}
+
+impl<D: Decryptor> Decryptor for ThroughputDecryptor<D> {
+    fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        self.timed(|inner| inner.write(data))?;
+        self.bytes_in += data.len() as u64;
+        Ok(())
+    }
+
+    fn write_owned(&mut self, chunk: Vec<u8>) -> Result<(), DecryptError> {
+        let len = chunk.len() as u64;
+        self.timed(|inner| inner.write_owned(chunk))?;
+        self.bytes_in += len;
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<(), DecryptError> {
+        self.timed(|inner| inner.end())
+    }
+
+    fn get_name(&self) -> &str {
+        self.inner.get_name()
+    }
+
+    fn read_all_output(&mut self) -> Vec<u8> {
+        self.inner.read_all_output()
+    }
+
+    fn get_eof_reserve(&self) -> usize {
+        self.inner.get_eof_reserve()
+    }
+
+    fn output_ready(&self) -> usize {
+        self.inner.output_ready()
+    }
+
+    fn reset(&mut self) -> Result<(), DecryptError> {
+        self.inner.reset()?;
+        self.bytes_in = 0;
+        self.elapsed = Duration::ZERO;
+        Ok(())
+    }
+
+    fn consume_output(&mut self, len: usize) -> Vec<u8> {
+        self.inner.consume_output(len)
+    }
+
+    fn set_output_shrink_factor(&mut self, factor: usize) {
+        self.inner.set_output_shrink_factor(factor)
+    }
+
+    fn warmup(&mut self) {
+        self.inner.warmup()
+    }
+
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        self.inner.validate_config()
+    }
+
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        self.inner.bytes_until_next_phase()
+    }
+
+    fn audio_start_offset(&self) -> u64 {
+        self.inner.audio_start_offset()
+    }
+
+    fn manifest(&self) -> DecryptManifest {
+        self.inner.manifest()
+    }
+}
+
+#[cfg(all(test, feature = "ximalaya"))]
+mod test {
+    use super::ThroughputDecryptor;
+    use crate::{
+        decryption::ximalaya::{build_scramble_table, new_x2m, X2M_CONTENT_KEY_SIZE},
+        utils::test_util::test::{decrypt_in_chunks, generate_test_data, TEST_SIZE_1MB},
+    };
+
+    #[test]
+    fn test_throughput_positive() {
+        let test_data = generate_test_data(TEST_SIZE_1MB, "x2m-test-data");
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m content key");
+        let table = build_scramble_table(&generate_test_data(2048, "x2m seed")).unwrap();
+
+        let mut decryptor = ThroughputDecryptor::new(new_x2m(key[..].try_into().unwrap(), table));
+        assert_eq!(decryptor.mb_per_sec(), 0.0);
+
+        decrypt_in_chunks(&mut decryptor, &test_data, 0x1_0000);
+        assert_eq!(decryptor.bytes_in(), TEST_SIZE_1MB as u64);
+        // Loose bounds only: anything from a slow debug build to a fast
+        // machine has to pass.
+        let rate = decryptor.mb_per_sec();
+        assert!(rate > 0.0 && rate.is_finite(), "rate: {}", rate);
+    }
+}
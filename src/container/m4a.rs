@@ -0,0 +1,135 @@
+use crate::utils::{
+    array_ext::ByteSliceExt,
+    audio::{detect_audio_type, AudioType},
+};
+
+/// Size and type of a box header.
+const BOX_HEADER_SIZE: usize = 8;
+
+struct BoxInfo {
+    start: usize,
+    len: usize,
+    kind: [u8; 4],
+}
+
+/// Split `data` into its top-level boxes, or `None` if a box size does not fit.
+fn top_level_boxes(data: &[u8]) -> Option<Vec<BoxInfo>> {
+    let mut boxes = vec![];
+    let mut start = 0;
+    while start < data.len() {
+        let rest = &data[start..];
+        if rest.len() < BOX_HEADER_SIZE {
+            return None;
+        }
+
+        let len = match rest.read_be::<u32>(0) {
+            // Extends to the end of the file.
+            0 => rest.len(),
+            // 64-bit size after the type.
+            1 if rest.len() >= BOX_HEADER_SIZE + 8 => {
+                usize::try_from(rest.read_be::<u64>(BOX_HEADER_SIZE)).ok()?
+            }
+            len => len as usize,
+        };
+        if len < BOX_HEADER_SIZE || len > rest.len() {
+            return None;
+        }
+
+        boxes.push(BoxInfo {
+            start,
+            len,
+            kind: rest[4..8].try_into().unwrap(),
+        });
+        start += len;
+    }
+    Some(boxes)
+}
+
+/// Move a misplaced top-level `ftyp` box back to the front of a decrypted
+/// M4A/MP4 file. Some QMC outputs place it after `moov`, which strict players
+/// reject even though the audio is intact.
+///
+/// Only an `ftyp` found ahead of `mdat` is moved, as the media offsets stored
+/// in `moov` then stay valid. Returns whether `data` was changed; anything that
+/// does not parse as MP4 boxes, or whose `ftyp` brand is not M4A/M4B/MP4, is
+/// left alone.
+pub fn repair_m4a(data: &mut [u8]) -> bool {
+    let Some(boxes) = top_level_boxes(data) else {
+        return false;
+    };
+    let Some(ftyp) = boxes.iter().position(|b| &b.kind == b"ftyp") else {
+        return false;
+    };
+    if ftyp == 0 {
+        return false;
+    }
+    if let Some(mdat) = boxes.iter().position(|b| &b.kind == b"mdat") {
+        if mdat < ftyp {
+            return false;
+        }
+    }
+
+    let BoxInfo { start, len, .. } = boxes[ftyp];
+    if !matches!(
+        detect_audio_type(&data[start..start + len]),
+        AudioType::M4A | AudioType::M4B | AudioType::MP4
+    ) {
+        return false;
+    }
+
+    data[..start + len].rotate_right(len);
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::repair_m4a;
+    use crate::utils::audio::{detect_audio_type, AudioType};
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut result = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        result.extend_from_slice(kind);
+        result.extend_from_slice(payload);
+        result
+    }
+
+    fn ftyp() -> Vec<u8> {
+        mp4_box(b"ftyp", b"M4A \x00\x00\x00\x00M4A mp42isom")
+    }
+
+    #[test]
+    fn test_repair_m4a_moves_ftyp_to_front() {
+        let moov = mp4_box(b"moov", &[0x11; 0x40]);
+        let mdat = mp4_box(b"mdat", &[0x22; 0x100]);
+        let mut data = [moov.clone(), ftyp(), mdat.clone()].concat();
+        assert_eq!(detect_audio_type(&data), AudioType::UnknownType);
+
+        assert!(repair_m4a(&mut data));
+        assert_eq!(data, [ftyp(), moov, mdat.clone()].concat());
+        assert_eq!(detect_audio_type(&data), AudioType::M4A);
+        // Media data did not move.
+        assert_eq!(&data[data.len() - mdat.len()..], &mdat[..]);
+
+        // Already valid: nothing to do.
+        assert!(!repair_m4a(&mut data));
+    }
+
+    #[test]
+    fn test_repair_m4a_leaves_unsafe_or_foreign_data() {
+        let moov = mp4_box(b"moov", &[0x11; 0x40]);
+        let mdat = mp4_box(b"mdat", &[0x22; 0x100]);
+
+        // Moving `ftyp` from after `mdat` would shift the media offsets.
+        let original = [moov.clone(), mdat.clone(), ftyp()].concat();
+        let mut data = original.clone();
+        assert!(!repair_m4a(&mut data));
+        assert_eq!(data, original);
+
+        // Box sizes that do not add up.
+        let mut data = [moov, ftyp(), vec![0xff; 3]].concat();
+        assert!(!repair_m4a(&mut data));
+
+        let mut flac = b"fLaC".repeat(16);
+        assert!(!repair_m4a(&mut flac));
+    }
+}
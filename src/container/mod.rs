@@ -0,0 +1,9 @@
+mod m4a;
+mod wav;
+#[cfg(feature = "zip")]
+mod zip;
+
+pub use self::m4a::repair_m4a;
+pub use self::wav::{wrap_pcm_as_wav, WAV_HEADER_SIZE};
+#[cfg(feature = "zip")]
+pub use self::zip::decrypt_from_zip;
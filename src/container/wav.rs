@@ -0,0 +1,105 @@
+use crate::decryptor::{DecryptError, DecryptErrorCode};
+
+/// Size of the canonical RIFF/WAVE header written by [`wrap_pcm_as_wav`].
+pub const WAV_HEADER_SIZE: usize = 44;
+
+/// `WAVE_FORMAT_PCM`, integer samples.
+const FORMAT_PCM: u16 = 1;
+
+/// Prepend a canonical 44 byte RIFF/WAVE header to headerless little-endian
+/// PCM, so players can open it.
+///
+/// No format in this crate decrypts to raw PCM yet; this is the step to run on
+/// the output of one that does. Sizes of PCM over 4 GiB do not fit the header
+/// and are saturated, which most players tolerate.
+///
+/// Errors out on zero `channels` or `bits`, or when a frame of `channels`
+/// samples does not fit the header's 16-bit block align.
+pub fn wrap_pcm_as_wav(
+    pcm: &[u8],
+    sample_rate: u32,
+    channels: u16,
+    bits: u16,
+) -> Result<Vec<u8>, DecryptError> {
+    let block_align = u32::from(channels) * u32::from(bits.div_ceil(8));
+    let block_align = match u16::try_from(block_align) {
+        Ok(block_align) if block_align != 0 => block_align,
+        _ => {
+            return Err(DecryptError::new(
+                DecryptErrorCode::InvalidBlockSize,
+                &format!(
+                    "unsupported pcm layout: {} channels of {} bits",
+                    channels, bits
+                ),
+            ))
+        }
+    };
+    let byte_rate = sample_rate.saturating_mul(block_align as u32);
+    let data_len = u32::try_from(pcm.len()).unwrap_or(u32::MAX);
+    let riff_len = data_len.saturating_add(WAV_HEADER_SIZE as u32 - 8);
+
+    let mut result = Vec::with_capacity(WAV_HEADER_SIZE + pcm.len());
+    result.extend_from_slice(b"RIFF");
+    result.extend_from_slice(&riff_len.to_le_bytes());
+    result.extend_from_slice(b"WAVE");
+
+    result.extend_from_slice(b"fmt ");
+    result.extend_from_slice(&16u32.to_le_bytes());
+    result.extend_from_slice(&FORMAT_PCM.to_le_bytes());
+    result.extend_from_slice(&channels.to_le_bytes());
+    result.extend_from_slice(&sample_rate.to_le_bytes());
+    result.extend_from_slice(&byte_rate.to_le_bytes());
+    result.extend_from_slice(&block_align.to_le_bytes());
+    result.extend_from_slice(&bits.to_le_bytes());
+
+    result.extend_from_slice(b"data");
+    result.extend_from_slice(&data_len.to_le_bytes());
+    result.extend_from_slice(pcm);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{wrap_pcm_as_wav, WAV_HEADER_SIZE};
+    use crate::{
+        decryptor::DecryptErrorCode,
+        utils::{
+            array_ext::ByteSliceExt,
+            audio::{detect_audio_type, AudioType},
+        },
+    };
+
+    #[test]
+    fn test_wrap_pcm_as_wav_header() {
+        let pcm = [0x5a; 0x1000];
+        let wav = wrap_pcm_as_wav(&pcm, 44100, 2, 16).unwrap();
+        assert_eq!(wav.len(), WAV_HEADER_SIZE + pcm.len());
+        assert_eq!(detect_audio_type(&wav), AudioType::WAV);
+
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav.read_le::<u32>(4) as usize, wav.len() - 8);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(wav.read_le::<u32>(16), 16);
+        assert_eq!(wav.read_le::<u16>(20), 1);
+        assert_eq!(wav.read_le::<u16>(22), 2);
+        assert_eq!(wav.read_le::<u32>(24), 44100);
+        assert_eq!(wav.read_le::<u32>(28), 44100 * 4);
+        assert_eq!(wav.read_le::<u16>(32), 4);
+        assert_eq!(wav.read_le::<u16>(34), 16);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(wav.read_le::<u32>(40) as usize, pcm.len());
+        assert_eq!(&wav[WAV_HEADER_SIZE..], &pcm[..]);
+    }
+
+    #[test]
+    fn test_wrap_pcm_as_wav_out_of_range() {
+        for (channels, bits) in [(0, 16), (2, 0), (u16::MAX, 16), (8, u16::MAX)] {
+            let err = wrap_pcm_as_wav(&[], 44100, channels, bits).unwrap_err();
+            assert!(matches!(err.code(), DecryptErrorCode::InvalidBlockSize));
+        }
+
+        // The largest frame that still fits.
+        let wav = wrap_pcm_as_wav(&[], 44100, 8191, 64).unwrap();
+        assert_eq!(wav.read_le::<u16>(32), 8191 * 8);
+    }
+}
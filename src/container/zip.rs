@@ -0,0 +1,95 @@
+use std::{
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use zip::{result::ZipError, ZipArchive};
+
+use crate::{
+    decryption::{detect_format, new_decryptor, AudioFormat},
+    decryptor::{DecryptError, DecryptErrorCode},
+    keys::KeyProvider,
+};
+
+/// Upper bound on the buffer reserved up front for an entry. The size comes
+/// from the archive and is not trusted; larger entries still grow the buffer
+/// as they are read.
+const MAX_ENTRY_PREALLOC: u64 = 64 * 1024 * 1024;
+
+fn zip_error(err: ZipError) -> DecryptError {
+    DecryptError::new(DecryptErrorCode::IOError, &format!("zip: {}", err))
+}
+
+/// Extract `entry` from a zip archive held in memory and decrypt it.
+///
+/// The format is detected from the entry's magic header, falling back to its
+/// file extension for formats without one.
+pub fn decrypt_from_zip(
+    zip_bytes: &[u8],
+    entry: &str,
+    keys: &dyn KeyProvider,
+) -> Result<Vec<u8>, DecryptError> {
+    let mut archive = ZipArchive::new(Cursor::new(zip_bytes)).map_err(zip_error)?;
+    let mut file = archive.by_name(entry).map_err(zip_error)?;
+
+    let mut data = Vec::with_capacity(file.size().min(MAX_ENTRY_PREALLOC) as usize);
+    file.read_to_end(&mut data)
+        .map_err(|err| DecryptError::new(DecryptErrorCode::IOError, &format!("zip: {}", err)))?;
+
+    let format = detect_format(&data)
+        .or_else(|| {
+            Path::new(entry)
+                .extension()
+                .and_then(|ext| AudioFormat::from_extension(&ext.to_string_lossy()))
+        })
+        .ok_or_else(|| {
+            DecryptError::new(DecryptErrorCode::UnknownEncryption, "unknown file format")
+        })?;
+
+    let mut decryptor = new_decryptor(format, keys)?;
+    let eof_reserve = decryptor.get_eof_reserve().min(data.len());
+    decryptor.write(&data[..data.len() - eof_reserve])?;
+    decryptor.end()?;
+    Ok(decryptor.read_all_output())
+}
+
+#[cfg(all(test, feature = "ximalaya"))]
+mod test {
+    use std::io::{Cursor, Write};
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::decrypt_from_zip;
+    use crate::{
+        decryption::ximalaya::{build_scramble_table, XMLY_SCRAMBLE_SIZE},
+        decryptor::DecryptErrorCode,
+        keys::{KeyId, StaticKeyProvider},
+        utils::test_util::test::{generate_test_data, EncryptedBuilder},
+    };
+
+    #[test]
+    fn test_decrypt_x2m_from_zip() {
+        let key = generate_test_data(4, "zip x2m key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "zip x2m seed");
+        let table = build_scramble_table(&seed).unwrap();
+        let plain = generate_test_data(0x2000, "zip x2m data");
+        let encrypted = EncryptedBuilder::x2m(key[..].try_into().unwrap(), table)
+            .body(&plain)
+            .build();
+
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        zip.start_file("album/track.x2m", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(&encrypted).unwrap();
+        let zip_bytes = zip.finish().unwrap().into_inner();
+
+        let keys = StaticKeyProvider::new()
+            .with_key(KeyId::XimalayaX2mKey, &key)
+            .with_key(KeyId::XimalayaScrambleSeed, &seed);
+        let result = decrypt_from_zip(&zip_bytes, "album/track.x2m", &keys).unwrap();
+        assert_eq!(result, plain);
+
+        let err = decrypt_from_zip(&zip_bytes, "missing.x2m", &keys).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::IOError));
+    }
+}
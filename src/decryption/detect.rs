@@ -0,0 +1,379 @@
+use crate::utils::audio::{get_audio_header_metadata_size, AudioType};
+
+/// File formats handled by this crate.
+///
+/// Formats without a magic header (QMCv1, Ximalaya) are never returned by
+/// [`detect_format`], and are only known from the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Joox,
+    Kgm,
+    Vpr,
+    Kwm,
+    Ncm,
+    QmcV1,
+    /// Keyed by the file footer, see [`crate::decryption::new_qmc_v2`].
+    QmcV2,
+    Xiami,
+    X2m,
+    X3m,
+    /// Unencrypted audio.
+    Plain(AudioType),
+}
+
+impl AudioFormat {
+    /// Guess the format from a file extension (without the leading dot).
+    pub fn from_extension(ext: &str) -> Option<AudioFormat> {
+        let format = match ext.to_ascii_lowercase().as_str() {
+            "ofl_en" => AudioFormat::Joox,
+            "kgm" | "kgma" => AudioFormat::Kgm,
+            "vpr" => AudioFormat::Vpr,
+            "kwm" => AudioFormat::Kwm,
+            "ncm" => AudioFormat::Ncm,
+            // Kugou mixer exports (`bkc*`) use the QMCv1 static cipher.
+            "qmc0" | "qmc3" | "qmcflac" | "qmcogg" | "bkcmp3" | "bkcflac" => AudioFormat::QmcV1,
+            "mflac" | "mflac0" | "mgg" | "mgg1" => AudioFormat::QmcV2,
+            "xm" => AudioFormat::Xiami,
+            "x2m" => AudioFormat::X2m,
+            "x3m" => AudioFormat::X3m,
+
+            "ogg" => AudioFormat::Plain(AudioType::OGG),
+            "aac" => AudioFormat::Plain(AudioType::AAC),
+            "mp3" => AudioFormat::Plain(AudioType::MP3),
+            "m4a" => AudioFormat::Plain(AudioType::M4A),
+            "m4b" => AudioFormat::Plain(AudioType::M4B),
+            "mp4" => AudioFormat::Plain(AudioType::MP4),
+            "flac" => AudioFormat::Plain(AudioType::FLAC),
+            "dff" => AudioFormat::Plain(AudioType::DFF),
+            "wav" => AudioFormat::Plain(AudioType::WAV),
+            "wma" => AudioFormat::Plain(AudioType::WMA),
+            "ape" => AudioFormat::Plain(AudioType::APE),
+            _ => return None,
+        };
+        Some(format)
+    }
+}
+
+struct Signature {
+    format: AudioFormat,
+    offset: usize,
+    magic: &'static [u8],
+    /// Bits of `magic` to compare; all bits when absent.
+    mask: Option<&'static [u8]>,
+}
+
+impl Signature {
+    const fn new(format: AudioFormat, magic: &'static [u8]) -> Self {
+        Self {
+            format,
+            offset: 0,
+            magic,
+            mask: None,
+        }
+    }
+
+    /// A signature `offset` bytes into the file.
+    const fn at(format: AudioFormat, offset: usize, magic: &'static [u8]) -> Self {
+        Self {
+            format,
+            offset,
+            magic,
+            mask: None,
+        }
+    }
+
+    const fn masked(format: AudioFormat, magic: &'static [u8], mask: &'static [u8]) -> Self {
+        Self {
+            format,
+            offset: 0,
+            magic,
+            mask: Some(mask),
+        }
+    }
+
+    fn matches(&self, buf: &[u8]) -> bool {
+        let end = self.offset + self.magic.len();
+        if buf.len() < end {
+            return false;
+        }
+
+        buf[self.offset..end]
+            .iter()
+            .enumerate()
+            .all(|(i, v)| v & self.mask_at(i) == self.magic[i])
+    }
+
+    /// Confidence of a match: the number of bits compared.
+    fn confidence(&self) -> u8 {
+        let bits = match self.mask {
+            Some(mask) => mask.iter().map(|m| m.count_ones()).sum(),
+            None => self.magic.len() as u32 * 8,
+        };
+        bits.min(u8::MAX as u32) as u8
+    }
+
+    /// Mask byte for `magic[i]`.
+    fn mask_at(&self, i: usize) -> u8 {
+        self.mask.map_or(0xff, |mask| mask[i])
+    }
+}
+
+// cspell:disable
+const ENCRYPTED_SIGNATURES: &[Signature] = &[
+    Signature::new(AudioFormat::Joox, b"E!04"),
+    Signature::new(
+        AudioFormat::Kgm,
+        &[
+            0x7c, 0xd5, 0x32, 0xeb, 0x86, 0x02, 0x7f, 0x4b, //
+            0xa8, 0xaf, 0xa6, 0x8e, 0x0f, 0xff, 0x99, 0x14, //
+        ],
+    ),
+    Signature::new(
+        AudioFormat::Vpr,
+        &[
+            0x05, 0x28, 0xbc, 0x96, 0xe9, 0xe4, 0x5a, 0x43, //
+            0x91, 0xaa, 0xbd, 0xd0, 0x7a, 0xf5, 0x36, 0x31, //
+        ],
+    ),
+    Signature::new(AudioFormat::Kwm, b"yeelion-kuwo-tme"),
+    Signature::new(AudioFormat::Ncm, b"CTENFDAM"),
+    Signature::new(AudioFormat::Xiami, b"ifmt"),
+];
+
+const PLAIN_SIGNATURES: &[Signature] = &[
+    Signature::new(AudioFormat::Plain(AudioType::FLAC), b"fLaC"),
+    Signature::new(AudioFormat::Plain(AudioType::OGG), b"OggS"),
+    Signature::new(AudioFormat::Plain(AudioType::DFF), b"FRM8"),
+    Signature::new(AudioFormat::Plain(AudioType::WMA), b"\x30\x26\xB2\x75"),
+    Signature::new(AudioFormat::Plain(AudioType::WAV), b"RIFF"),
+    Signature::new(AudioFormat::Plain(AudioType::APE), b"MAC "),
+    // Frame sync: 12 bits + layer for AAC (ADTS), 11 bits for MP3.
+    Signature::masked(
+        AudioFormat::Plain(AudioType::AAC),
+        &[0xff, 0xf0],
+        &[0xff, 0xf6],
+    ),
+    Signature::masked(
+        AudioFormat::Plain(AudioType::MP3),
+        &[0xff, 0xe0],
+        &[0xff, 0xe0],
+    ),
+    // MP4 family: `ftyp` box and its major brand.
+    Signature::at(AudioFormat::Plain(AudioType::MP4), 4, b"ftypisom"),
+    Signature::at(AudioFormat::Plain(AudioType::MP4), 4, b"ftypiso2"),
+    Signature::at(AudioFormat::Plain(AudioType::M4A), 4, b"ftypMSNV"),
+    Signature::at(AudioFormat::Plain(AudioType::M4A), 4, b"ftypNDAS"),
+    Signature::at(AudioFormat::Plain(AudioType::M4A), 4, b"ftypM4A"),
+    Signature::at(AudioFormat::Plain(AudioType::M4B), 4, b"ftypM4B"),
+];
+// cspell:enable
+
+/// All formats matching `header`, most likely first.
+///
+/// Confidence is the number of magic bits matched; on a tie, the signature
+/// found earlier in the file wins.
+pub fn detect_format_ranked<T: AsRef<[u8]>>(header: T) -> Vec<(AudioFormat, u8)> {
+    let header = header.as_ref();
+    let mut candidates: Vec<(AudioFormat, u8, usize)> = ENCRYPTED_SIGNATURES
+        .iter()
+        .filter(|sig| sig.matches(header))
+        .map(|sig| (sig.format, sig.confidence(), sig.offset))
+        .collect();
+
+    // Plain audio may be prefixed by an ID3/APEv2 tag.
+    let audio_offset = get_audio_header_metadata_size(header);
+    if audio_offset <= header.len() {
+        let audio = &header[audio_offset..];
+        candidates.extend(
+            PLAIN_SIGNATURES
+                .iter()
+                .filter(|sig| sig.matches(audio))
+                .map(|sig| (sig.format, sig.confidence(), audio_offset + sig.offset)),
+        );
+    }
+
+    candidates.sort_by_key(|(_, confidence, offset)| (u8::MAX - confidence, *offset));
+    candidates
+        .into_iter()
+        .map(|(format, confidence, _)| (format, confidence))
+        .collect()
+}
+
+/// The most likely format of `header`, if any.
+pub fn detect_format<T: AsRef<[u8]>>(header: T) -> Option<AudioFormat> {
+    detect_format_ranked(header)
+        .first()
+        .map(|(format, _)| *format)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        detect_format, detect_format_ranked, AudioFormat, Signature, ENCRYPTED_SIGNATURES,
+        PLAIN_SIGNATURES,
+    };
+    use crate::utils::audio::AudioType;
+
+    /// Whether the signatures compare the same bytes and some input could
+    /// match both.
+    fn overlaps(a: &Signature, b: &Signature) -> bool {
+        let start = a.offset.max(b.offset);
+        let end = (a.offset + a.magic.len()).min(b.offset + b.magic.len());
+        start < end
+            && (start..end).all(|pos| {
+                let (i, j) = (pos - a.offset, pos - b.offset);
+                let mask = a.mask_at(i) & b.mask_at(j);
+                a.magic[i] & mask == b.magic[j] & mask
+            })
+    }
+
+    #[test]
+    fn test_detect_encrypted() {
+        let mut header = b"yeelion-kuwo-tme".to_vec();
+        header.resize(0x400, 0);
+        assert_eq!(detect_format(&header), Some(AudioFormat::Kwm));
+
+        assert_eq!(detect_format(b"CTENFDAM\x01\x69"), Some(AudioFormat::Ncm));
+        assert_eq!(detect_format(b"E!04"), Some(AudioFormat::Joox));
+        assert_eq!(detect_format(b"\x00\x01\x02\x03"), None);
+    }
+
+    #[test]
+    fn test_detect_plain() {
+        let mut header = b"fLaC".to_vec();
+        header.resize(0x20, 0);
+        assert_eq!(
+            detect_format(&header),
+            Some(AudioFormat::Plain(AudioType::FLAC))
+        );
+
+        let mut header = b"\x00\x00\x00\x20ftypM4A ".to_vec();
+        header.resize(0x20, 0);
+        assert_eq!(
+            detect_format(&header),
+            Some(AudioFormat::Plain(AudioType::M4A))
+        );
+    }
+
+    #[test]
+    fn test_from_extension() {
+        assert_eq!(AudioFormat::from_extension("X2M"), Some(AudioFormat::X2m));
+        assert_eq!(
+            AudioFormat::from_extension("qmcflac"),
+            Some(AudioFormat::QmcV1)
+        );
+        assert_eq!(
+            AudioFormat::from_extension("bkcflac"),
+            Some(AudioFormat::QmcV1)
+        );
+        assert_eq!(
+            AudioFormat::from_extension("mp3"),
+            Some(AudioFormat::Plain(AudioType::MP3))
+        );
+        assert_eq!(AudioFormat::from_extension("txt"), None);
+    }
+
+    #[test]
+    fn test_detect_ambiguous_frame_sync() {
+        // An ADTS frame sync is also a valid MP3 frame sync.
+        let header = [0xff, 0xf1, 0x50, 0x80, 0x00, 0x1f, 0xfc, 0x00];
+        assert_eq!(
+            detect_format_ranked(header),
+            vec![
+                (AudioFormat::Plain(AudioType::AAC), 14),
+                (AudioFormat::Plain(AudioType::MP3), 11),
+            ]
+        );
+        assert_eq!(
+            detect_format(header),
+            Some(AudioFormat::Plain(AudioType::AAC))
+        );
+
+        let header = [0xff, 0xfb, 0x90, 0x64];
+        assert_eq!(
+            detect_format_ranked(header),
+            vec![(AudioFormat::Plain(AudioType::MP3), 11)]
+        );
+    }
+
+    #[test]
+    fn test_signature_samples_match_once() {
+        let kgm = [
+            0x7c, 0xd5, 0x32, 0xeb, 0x86, 0x02, 0x7f, 0x4b, //
+            0xa8, 0xaf, 0xa6, 0x8e, 0x0f, 0xff, 0x99, 0x14, //
+        ];
+        let vpr = [
+            0x05, 0x28, 0xbc, 0x96, 0xe9, 0xe4, 0x5a, 0x43, //
+            0x91, 0xaa, 0xbd, 0xd0, 0x7a, 0xf5, 0x36, 0x31, //
+        ];
+        let encrypted: &[(&[u8], AudioFormat)] = &[
+            (b"E!04\x00\x00\x00\x00", AudioFormat::Joox),
+            (&kgm, AudioFormat::Kgm),
+            (&vpr, AudioFormat::Vpr),
+            (b"yeelion-kuwo-tme", AudioFormat::Kwm),
+            (b"CTENFDAM\x01\x69", AudioFormat::Ncm),
+            (b"ifmt\x20MP3\xfe\xfe\xfe\xfe", AudioFormat::Xiami),
+        ];
+        let plain: &[(&[u8], AudioType)] = &[
+            (b"fLaC\x00\x00\x00\x22", AudioType::FLAC),
+            (b"OggS\x00\x02", AudioType::OGG),
+            (b"FRM8\x00\x00", AudioType::DFF),
+            (b"\x30\x26\xB2\x75\x8E\x66", AudioType::WMA),
+            (b"RIFF\x24\x00\x00\x00WAVE", AudioType::WAV),
+            (b"MAC \x96\x0f", AudioType::APE),
+            (&[0xff, 0xf1, 0x50, 0x80], AudioType::AAC),
+            (&[0xff, 0xfb, 0x90, 0x64], AudioType::MP3),
+            (b"\x00\x00\x00\x20ftypisom", AudioType::MP4),
+            (b"\x00\x00\x00\x20ftypM4A ", AudioType::M4A),
+            (b"\x00\x00\x00\x20ftypM4B ", AudioType::M4B),
+        ];
+
+        for (sample, format) in encrypted {
+            let matched: Vec<_> = ENCRYPTED_SIGNATURES
+                .iter()
+                .filter(|sig| sig.matches(sample))
+                .map(|sig| sig.format)
+                .collect();
+            assert_eq!(matched, vec![*format]);
+        }
+        for (sample, audio_type) in plain {
+            let matched: Vec<_> = PLAIN_SIGNATURES
+                .iter()
+                .filter(|sig| sig.matches(sample))
+                .map(|sig| sig.format)
+                .collect();
+            match audio_type {
+                // ADTS frame syncs are also MP3 frame syncs.
+                AudioType::AAC => assert_eq!(
+                    matched,
+                    vec![
+                        AudioFormat::Plain(AudioType::AAC),
+                        AudioFormat::Plain(AudioType::MP3),
+                    ]
+                ),
+                _ => assert_eq!(matched, vec![AudioFormat::Plain(*audio_type)]),
+            }
+        }
+    }
+
+    #[test]
+    fn test_signatures_do_not_overlap() {
+        for table in [ENCRYPTED_SIGNATURES, PLAIN_SIGNATURES] {
+            for (i, a) in table.iter().enumerate() {
+                for b in &table[i + 1..] {
+                    let known_ambiguous = a.format == AudioFormat::Plain(AudioType::AAC)
+                        && b.format == AudioFormat::Plain(AudioType::MP3);
+                    assert_eq!(
+                        overlaps(a, b),
+                        known_ambiguous,
+                        "{:?} at {} overlaps {:?} at {}",
+                        a.format,
+                        a.offset,
+                        b.format,
+                        b.offset
+                    );
+                }
+            }
+        }
+    }
+}
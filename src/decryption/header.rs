@@ -0,0 +1,122 @@
+use crate::decryptor::{ensure_header_complete, DecryptError};
+
+/// Accumulates a fixed-size header across any number of `write` calls.
+#[derive(Debug, Default)]
+pub struct HeaderAccumulator {
+    buf: Vec<u8>,
+}
+
+impl HeaderAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take bytes from `p` until `needed` bytes are buffered, advancing `p`.
+    ///
+    /// Returns the header once all `needed` bytes are available; bytes past
+    /// the header are left in `p`.
+    pub fn feed(&mut self, p: &mut &[u8], needed: usize) -> Option<&[u8]> {
+        if self.buf.len() < needed {
+            let missing = needed - self.buf.len();
+            let read_size = std::cmp::min(p.len(), missing);
+            if read_size == 0 {
+                return None;
+            }
+
+            self.buf.reserve(missing);
+
+            let (to_buffer, left_over) = p.split_at(read_size);
+            self.buf.extend_from_slice(to_buffer);
+            *p = left_over;
+        }
+
+        if self.buf.len() >= needed {
+            Some(&self.buf[..needed])
+        } else {
+            None
+        }
+    }
+
+    /// Header bytes buffered so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Number of header bytes buffered so far.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Error out with `EmptyInput` or `NotEnoughData` unless `needed` bytes
+    /// have been buffered.
+    pub fn ensure_complete(&self, needed: usize) -> Result<(), DecryptError> {
+        ensure_header_complete(self.buf.len() >= needed, self.buf.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HeaderAccumulator;
+    use crate::decryptor::DecryptErrorCode;
+
+    #[test]
+    fn test_feed_split() {
+        let mut header = HeaderAccumulator::new();
+
+        let mut p: &[u8] = &[1, 2, 3];
+        assert_eq!(header.feed(&mut p, 5), None);
+        assert!(p.is_empty());
+
+        let mut p: &[u8] = &[4, 5, 6, 7];
+        assert_eq!(header.feed(&mut p, 5), Some(&[1u8, 2, 3, 4, 5][..]));
+        assert_eq!(p, &[6, 7]);
+    }
+
+    #[test]
+    fn test_feed_exact_boundary() {
+        let mut header = HeaderAccumulator::new();
+
+        let mut p: &[u8] = &[1, 2, 3, 4];
+        assert_eq!(header.feed(&mut p, 4), Some(&[1u8, 2, 3, 4][..]));
+        assert!(p.is_empty());
+
+        // Once complete, further feeds leave the input alone.
+        let mut p: &[u8] = &[5];
+        assert_eq!(header.feed(&mut p, 4), Some(&[1u8, 2, 3, 4][..]));
+        assert_eq!(p, &[5]);
+    }
+
+    #[test]
+    fn test_feed_byte_by_byte() {
+        let mut header = HeaderAccumulator::new();
+
+        let mut p: &[u8] = &[];
+        assert_eq!(header.feed(&mut p, 3), None);
+        assert!(header.is_empty());
+
+        for i in 0..2u8 {
+            let mut p: &[u8] = &[i];
+            assert_eq!(header.feed(&mut p, 3), None);
+        }
+        let mut p: &[u8] = &[2];
+        assert_eq!(header.feed(&mut p, 3), Some(&[0u8, 1, 2][..]));
+    }
+
+    #[test]
+    fn test_ensure_complete() {
+        let mut header = HeaderAccumulator::new();
+        let err = header.ensure_complete(4).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::EmptyInput));
+
+        header.feed(&mut &[1u8, 2][..], 4);
+        let err = header.ensure_complete(4).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::NotEnoughData));
+
+        header.feed(&mut &[3u8, 4][..], 4);
+        header.ensure_complete(4).unwrap();
+    }
+}
@@ -0,0 +1,95 @@
+use super::AudioFormat;
+use crate::utils::audio::AudioType;
+
+/// What is known about a file before decrypting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecryptorInfo {
+    pub format: AudioFormat,
+    /// The audio container of the decrypted output, when the format (or its
+    /// extension) only ever wraps one. `None` means the output has to be
+    /// sniffed, e.g. Ximalaya files hold either MP3 or M4A.
+    pub output_container: Option<AudioType>,
+}
+
+impl DecryptorInfo {
+    pub fn new(format: AudioFormat) -> Self {
+        let output_container = match format {
+            AudioFormat::Plain(audio_type) => Some(audio_type),
+            _ => None,
+        };
+        Self {
+            format,
+            output_container,
+        }
+    }
+
+    /// Like [`DecryptorInfo::new`] for the format matching `ext`, also using
+    /// the container some extensions name (e.g. `qmcflac`).
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        let ext = ext.strip_prefix('.').unwrap_or(ext).to_ascii_lowercase();
+        let mut info = Self::new(AudioFormat::from_extension(&ext)?);
+
+        let container = match ext.as_str() {
+            "qmc0" | "qmc3" | "bkcmp3" => Some(AudioType::MP3),
+            "qmcflac" | "bkcflac" | "mflac" | "mflac0" => Some(AudioType::FLAC),
+            "qmcogg" | "mgg" | "mgg1" => Some(AudioType::OGG),
+            _ => None,
+        };
+        info.output_container = info.output_container.or(container);
+        Some(info)
+    }
+
+    /// Fill in `output_container` from the start of the file, for formats
+    /// naming their container in the header (Xiami).
+    pub fn with_header(self, header: &[u8]) -> Self {
+        match self.format {
+            AudioFormat::Xiami => Self {
+                output_container: self
+                    .output_container
+                    .or_else(|| super::xiami::container_type_from_header(header)),
+                ..self
+            },
+            _ => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DecryptorInfo;
+    use crate::{decryption::AudioFormat, utils::audio::AudioType};
+
+    #[test]
+    fn test_output_container() {
+        let container = |ext| DecryptorInfo::from_extension(ext).unwrap().output_container;
+
+        assert_eq!(container("qmcflac"), Some(AudioType::FLAC));
+        assert_eq!(container(".qmc0"), Some(AudioType::MP3));
+        assert_eq!(container("bkcflac"), Some(AudioType::FLAC));
+        assert_eq!(container("qmcogg"), Some(AudioType::OGG));
+        assert_eq!(container("m4a"), Some(AudioType::M4A));
+        assert_eq!(container("x2m"), None);
+        assert_eq!(container("ncm"), None);
+        assert_eq!(DecryptorInfo::from_extension("txt"), None);
+
+        assert_eq!(DecryptorInfo::new(AudioFormat::X3m).output_container, None);
+        assert_eq!(
+            DecryptorInfo::new(AudioFormat::Plain(AudioType::WAV)).output_container,
+            Some(AudioType::WAV)
+        );
+    }
+
+    #[test]
+    fn test_output_container_from_header() {
+        let info = DecryptorInfo::from_extension("xm").unwrap();
+        assert_eq!(info.output_container, None);
+
+        let header = b"ifmt A4M\xfe\xfe\xfe\xfe\x00\x00\x00\x00";
+        let info = info.with_header(header);
+        assert_eq!(info.format, AudioFormat::Xiami);
+        assert_eq!(info.output_container, Some(AudioType::M4A));
+
+        let info = DecryptorInfo::new(AudioFormat::Ncm).with_header(header);
+        assert_eq!(info.output_container, None);
+    }
+}
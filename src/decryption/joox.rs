@@ -1,14 +1,18 @@
 mod detail {
-    use std::num::NonZeroU32;
-
     use crate::{
-        decryptor::{BaseDecryptorData, DecryptError, DecryptErrorCode, Decryptor},
-        utils::array_ext::ByteSliceExt,
+        decryptor::{
+            check_invariants, trace_write_span, transition_state, BaseDecryptorData, DecryptError,
+            DecryptErrorCode, DecryptManifest, Decryptor,
+        },
+        utils::{
+            array_ext::ByteSliceExt,
+            pbkdf2::{self, SHA1_OUTPUT_LEN},
+        },
     };
     use aes::Aes128;
     use cipher::{block_padding::Pkcs7, generic_array::GenericArray, BlockDecrypt, KeyInit};
-    use ring::{digest, pbkdf2};
 
+    #[derive(Debug)]
     enum State {
         ReadFileMagic,
         SeekToBody(usize),
@@ -20,6 +24,7 @@ mod detail {
         data: BaseDecryptorData,
         state: State,
 
+        uuid: String,
         aes: Aes128,
         block_count: usize,
     }
@@ -41,19 +46,15 @@ mod detail {
 
     impl JooxDecryptor {
         fn new(uuid: &str) -> Self {
-            let mut pbkdf2_output = [0u8; digest::SHA1_OUTPUT_LEN];
-            pbkdf2::derive(
-                pbkdf2::PBKDF2_HMAC_SHA1,
-                unsafe { NonZeroU32::new_unchecked(1000) },
-                JOOX_UUID_SALT,
-                uuid.as_bytes(),
-                &mut pbkdf2_output,
-            );
+            let mut pbkdf2_output = [0u8; SHA1_OUTPUT_LEN];
+            pbkdf2::derive_key(uuid.as_bytes(), JOOX_UUID_SALT, 1000, &mut pbkdf2_output)
+                .expect("iteration count is not zero");
             let aes_key = &pbkdf2_output[..16];
 
             Self {
                 data: BaseDecryptorData::new("Joox"),
                 state: State::ReadFileMagic,
+                uuid: String::from(uuid),
                 aes: Aes128::new_from_slice(aes_key).ok().unwrap(),
                 block_count: 0,
             }
@@ -95,11 +96,49 @@ mod detail {
     impl Decryptor for JooxDecryptor {
         crate::impl_decryptor_inner_helper! {}
 
+        /// The AES key is derived from the uuid, which must not be empty.
+        fn validate_config(&self) -> Result<(), DecryptError> {
+            if self.uuid.is_empty() {
+                return Err(DecryptError::new(
+                    DecryptErrorCode::InvalidKeyLength,
+                    "joox uuid is empty",
+                ));
+            }
+            Ok(())
+        }
+
+        /// Fingerprints the uuid the AES key is derived from.
+        fn manifest(&self) -> DecryptManifest {
+            self.data
+                .manifest(Some(self.uuid.as_bytes()), JOOX_V04_HEADER_SIZE)
+        }
+
         fn end(&mut self) -> Result<(), DecryptError> {
+            self.data.mark_finalized();
+            self.data.ensure_header_complete(matches!(
+                self.state,
+                State::DecryptOtherBlock | State::DecryptPaddingBlock
+            ))?;
+            if self.data.buf_in.len() != JOOX_V04_AES_BLOCK_SIZE {
+                return Err(DecryptError::new(
+                    DecryptErrorCode::NotEnoughData,
+                    "input ended before the final aes block",
+                ));
+            }
             self.decrypt_aes_padding_block()
         }
 
+        fn reset(&mut self) -> Result<(), DecryptError> {
+            self.data.reset();
+            self.state = State::ReadFileMagic;
+            self.block_count = 0;
+            Ok(())
+        }
+
         fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            trace_write_span!(self, state);
+            self.data.ensure_not_finalized()?;
+            let (prev_offset, prev_output_len) = (self.data.offset, self.data.buf_out.len());
             let mut p = data;
 
             while !p.is_empty() {
@@ -113,13 +152,13 @@ mod detail {
                                     "unsupported joox version",
                                 ));
                             }
-                            self.state = State::SeekToBody(JOOX_V04_HEADER_SIZE);
+                            transition_state!(self, State::SeekToBody(JOOX_V04_HEADER_SIZE));
                         }
                     }
                     State::SeekToBody(n) => {
                         if self.data.read_until_offset(&mut p, n) {
                             self.data.seek_input(n);
-                            self.state = State::DecryptOtherBlock;
+                            transition_state!(self, State::DecryptOtherBlock);
                         }
                     }
                     State::DecryptOtherBlock => {
@@ -127,7 +166,7 @@ mod detail {
                             self.decrypt_aes_block();
                             self.block_count += 1;
                             if self.block_count == JOOX_V04_BLOCK_COUNT_PER_ITERATION {
-                                self.state = State::DecryptPaddingBlock;
+                                transition_state!(self, State::DecryptPaddingBlock);
                                 self.block_count = 0;
                                 break;
                             }
@@ -136,11 +175,13 @@ mod detail {
                     State::DecryptPaddingBlock => {
                         if self.data.read_block(&mut p, JOOX_V04_AES_BLOCK_SIZE) {
                             self.decrypt_aes_padding_block()?;
-                            self.state = State::DecryptOtherBlock;
+                            transition_state!(self, State::DecryptOtherBlock);
                         }
                     }
                 }
             }
+
+            check_invariants(&self.data, prev_offset, prev_output_len);
             Ok(())
         }
     }
@@ -154,8 +195,12 @@ pub use detail::new_joox;
 
 #[cfg(test)]
 pub mod test {
-    use crate::utils::test_util::test::{
-        decrypt_test_content, generate_test_data, TEST_SIZE_1MB, TEST_SIZE_4MB,
+    use crate::{
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::test_util::test::{
+            assert_deterministic_after_reset, decrypt_test_content, generate_test_data,
+            TEST_SIZE_1MB, TEST_SIZE_4MB,
+        },
     };
 
     #[test]
@@ -182,4 +227,91 @@ pub mod test {
             "68feeeb99b826608032811a14dcb8d3f712a5a984a884c1fe487b50220da862c"
         );
     }
+
+    #[test]
+    fn test_joox_reset() {
+        let mut test_data = generate_test_data(12 + 0x4000, "joox test data");
+        let uuid = generate_test_data(32, "joox uuid");
+        test_data[0..4].copy_from_slice(b"E!04");
+        let n = test_data.len();
+        // Last block of `test_joox`, which un-pads cleanly under this uuid.
+        test_data[n - 16..].copy_from_slice(&[
+            0xadu8, 0x15, 0xd5, 0xb0, 0x14, 0xc6, 0xd0, 0x40, //
+            0xc5u8, 0x0f, 0x9a, 0xaf, 0xf9, 0xc0, 0xfe, 0xe2, //
+        ]);
+
+        let uuid = unsafe { std::str::from_utf8_unchecked(uuid.as_slice()) }.to_string();
+        assert_deterministic_after_reset(|| super::new_joox(uuid.clone()), &test_data);
+    }
+
+    #[test]
+    fn test_joox_manifest() {
+        use crate::decryptor::key_fingerprint;
+
+        let uuid = String::from("00000000000000000000000000000000");
+        let decryptor = super::new_joox(uuid.clone());
+        let manifest = decryptor.manifest();
+        assert_eq!(manifest.format, "Joox");
+        assert_eq!(
+            manifest.key_fingerprint,
+            Some(key_fingerprint(uuid.as_bytes()))
+        );
+        assert_eq!(manifest.header_len, 12);
+    }
+
+    #[test]
+    fn test_joox_validate_config() {
+        let decryptor = super::new_joox(String::from("00000000000000000000000000000000"));
+        assert!(decryptor.validate_config().is_ok());
+
+        let err = super::new_joox(String::new())
+            .validate_config()
+            .unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidKeyLength));
+    }
+
+    #[test]
+    fn test_joox_end_without_header() {
+        let uuid = String::from("00000000000000000000000000000000");
+
+        let mut decryptor = super::new_joox(uuid.clone());
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::EmptyInput));
+
+        let mut decryptor = super::new_joox(uuid);
+        decryptor.write(b"E!04").unwrap();
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::NotEnoughData));
+    }
+
+    #[test]
+    fn test_joox_end_without_final_block() {
+        let uuid = String::from("00000000000000000000000000000000");
+        let mut header = [0u8; 12];
+        header[0..4].copy_from_slice(b"E!04");
+
+        for body_len in [0, 5] {
+            let mut decryptor = super::new_joox(uuid.clone());
+            decryptor.write(&header).unwrap();
+            decryptor.write(&vec![0u8; body_len]).unwrap();
+            let err = decryptor.end().unwrap_err();
+            assert!(matches!(err.code(), DecryptErrorCode::NotEnoughData));
+        }
+    }
+
+    #[test]
+    fn test_joox_output_ready() {
+        let mut decryptor = super::new_joox(String::from("00000000000000000000000000000000"));
+
+        let mut header = [0u8; 12];
+        header[0..4].copy_from_slice(b"E!04");
+        decryptor.write(&header).unwrap();
+
+        // A block is held back until the next one arrives, as the last block
+        // of each iteration carries the padding.
+        decryptor.write(&[0u8; 16]).unwrap();
+        assert_eq!(decryptor.output_ready(), 0);
+        decryptor.write(&[0u8; 16]).unwrap();
+        assert_eq!(decryptor.output_ready(), 16);
+    }
 }
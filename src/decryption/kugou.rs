@@ -1,3 +1,5 @@
+use crate::decryptor::{DecryptError, DecryptErrorCode};
+
 pub const KUGOU_INTERNAL_TABLE_SIZE: usize = 17 * 16;
 pub const KUGOU_VPR_KEY_SIZE: usize = 17;
 pub const KUGOU_FILE_KEY_SIZE: usize = 17;
@@ -6,22 +8,77 @@ pub type KugouInternalTable = [u8; KUGOU_INTERNAL_TABLE_SIZE];
 pub type KugouVPRKey = [u8; KUGOU_VPR_KEY_SIZE];
 pub type KugouFileKey = [u8; KUGOU_FILE_KEY_SIZE];
 
+/// The `t1`, `t2` and `v2` tables masking the KGM/VPR body.
+///
+/// No default tables ship with the crate (there is no `v2_default()`): load
+/// the client's tables with [`KgmMaskTable::new`].
+#[derive(Clone)]
+pub struct KgmMaskTable {
+    t1: KugouInternalTable,
+    t2: KugouInternalTable,
+    v2: KugouInternalTable,
+}
+
+impl KgmMaskTable {
+    /// Build from tables of unchecked length, e.g. loaded from a file. Each
+    /// table must be exactly [`KUGOU_INTERNAL_TABLE_SIZE`] bytes.
+    pub fn new(t1: &[u8], t2: &[u8], v2: &[u8]) -> Result<Self, DecryptError> {
+        fn table(name: &str, data: &[u8]) -> Result<KugouInternalTable, DecryptError> {
+            data.try_into().map_err(|_| {
+                DecryptError::new(
+                    DecryptErrorCode::InvalidMaskTable,
+                    &format!(
+                        "mask table {} should be {} bytes, got {}",
+                        name,
+                        KUGOU_INTERNAL_TABLE_SIZE,
+                        data.len()
+                    ),
+                )
+            })
+        }
+
+        Ok(Self {
+            t1: table("t1", t1)?,
+            t2: table("t2", t2)?,
+            v2: table("v2", v2)?,
+        })
+    }
+
+    pub fn from_tables(
+        t1: &KugouInternalTable,
+        t2: &KugouInternalTable,
+        v2: &KugouInternalTable,
+    ) -> Self {
+        Self {
+            t1: *t1,
+            t2: *t2,
+            v2: *v2,
+        }
+    }
+}
+
 mod detail {
     use std::cmp::Ordering;
 
     use crate::{
-        decryptor::{BaseDecryptorData, DecryptError, DecryptErrorCode, Decryptor},
+        decryptor::{
+            check_invariants, invalid_header_field, trace_write_span, transition_state,
+            BaseDecryptorData, DecryptError, DecryptErrorCode, DecryptManifest, Decryptor,
+        },
         impl_decryptor_inner_helper,
         utils::array_ext::{ArrayExtension, ByteSliceExt},
     };
 
-    use super::{KugouFileKey, KugouInternalTable, KugouVPRKey, KUGOU_FILE_KEY_SIZE};
+    use super::{KgmMaskTable, KugouFileKey, KugouInternalTable, KugouVPRKey, KUGOU_FILE_KEY_SIZE};
 
     const KUGOU_MAGIC_HEADER_SIZE: usize = 16;
     const MINIMAL_HEADER_SIZE: usize = 0x2c;
+    // Known files use 0x3c or 0x400; anything past this is treated as corrupt.
+    const MAXIMUM_HEADER_SIZE: usize = 0x10000;
 
     type KugouMagicHeader = [u8; KUGOU_MAGIC_HEADER_SIZE];
 
+    #[derive(Debug)]
     enum State {
         ReadFileMagic,
         SeekToBody(usize),
@@ -87,28 +144,21 @@ mod detail {
         data: BaseDecryptorData,
         state: State,
 
-        t1: KugouInternalTable,
-        t2: KugouInternalTable,
-        v2: KugouInternalTable,
+        tables: KgmMaskTable,
         file_key: KugouFileKey,
+        /// Audio offsets are relative to the end of the header.
+        header_size: usize,
         detail: T,
     }
 
     impl<T: KugouAlgo> Kugou<T> {
-        pub fn new(
-            name: &str,
-            t1: &KugouInternalTable,
-            t2: &KugouInternalTable,
-            v2: &KugouInternalTable,
-            detail: T,
-        ) -> Kugou<T> {
+        pub fn new(name: &str, tables: &KgmMaskTable, detail: T) -> Kugou<T> {
             Kugou {
                 data: BaseDecryptorData::new(name),
                 state: State::ReadFileMagic,
-                t1: *t1,
-                t2: *t2,
-                v2: *v2,
+                tables: tables.clone(),
                 file_key: [0u8; KUGOU_FILE_KEY_SIZE],
+                header_size: 0,
                 detail,
             }
         }
@@ -117,9 +167,9 @@ mod detail {
             let mut value = 0u8;
             let mut offset = offset;
             while offset > 0 {
-                value ^= self.t1.get_mod_n(offset);
+                value ^= self.tables.t1.get_mod_n(offset);
                 offset >>= 4;
-                value ^= self.t2.get_mod_n(offset);
+                value ^= self.tables.t2.get_mod_n(offset);
                 offset >>= 4;
             }
             value
@@ -127,7 +177,7 @@ mod detail {
 
         fn decrypt_byte(&self, byte: u8, offset: usize) -> u8 {
             let mut value = byte;
-            value ^= self.v2.get_mod_n(offset);
+            value ^= self.tables.v2.get_mod_n(offset);
             value ^= self.file_key.get_mod_n(offset);
             value ^= self.get_mask_v2(offset >> 4);
             value = value ^ (value << 4);
@@ -139,7 +189,7 @@ mod detail {
         #[inline]
         fn decrypt(&mut self, data: &[u8]) -> Result<(), DecryptError> {
             let size = data.len();
-            let offset = self.data.offset;
+            let offset = self.data.offset - self.header_size;
 
             let mut out = data
                 .iter()
@@ -157,7 +207,30 @@ mod detail {
     impl<T: KugouAlgo> Decryptor for Kugou<T> {
         impl_decryptor_inner_helper! {}
 
+        /// Fingerprints the mask tables, `t1`, `t2` and `v2` in that order.
+        fn manifest(&self) -> DecryptManifest {
+            let tables = [self.tables.t1, self.tables.t2, self.tables.v2].concat();
+            self.data.manifest(Some(&tables), self.header_size)
+        }
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            self.data.mark_finalized();
+            self.data
+                .ensure_header_complete(matches!(self.state, State::Decrypt))
+        }
+
+        fn reset(&mut self) -> Result<(), DecryptError> {
+            self.data.reset();
+            self.state = State::ReadFileMagic;
+            self.file_key = [0u8; KUGOU_FILE_KEY_SIZE];
+            self.header_size = 0;
+            Ok(())
+        }
+
         fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            trace_write_span!(self, state);
+            self.data.ensure_not_finalized()?;
+            let (prev_offset, prev_output_len) = (self.data.offset, self.data.buf_out.len());
             let mut p = data;
 
             while !p.is_empty() {
@@ -176,27 +249,36 @@ mod detail {
                             }
 
                             let header_size = self.data.buf_in.read_le::<u32>(0x10) as usize;
+                            if !(MINIMAL_HEADER_SIZE..=MAXIMUM_HEADER_SIZE).contains(&header_size) {
+                                return Err(invalid_header_field(
+                                    "header_size",
+                                    header_size as u64,
+                                ));
+                            }
+
                             self.file_key[0..16].copy_from_slice(&self.data.buf_in[0x1c..0x2c]);
                             self.file_key[16] = 0;
 
-                            self.state = State::SeekToBody(header_size);
+                            transition_state!(self, State::SeekToBody(header_size));
                         }
                     }
 
                     State::SeekToBody(n) => {
                         if self.data.read_until_offset(&mut p, n) {
-                            self.state = State::Decrypt;
-                            self.data.offset = 0;
+                            transition_state!(self, State::Decrypt);
+                            self.header_size = n;
                             self.data.seek_input(n);
                         }
                     }
 
                     State::Decrypt => {
-                        return self.decrypt(p);
+                        self.decrypt(p)?;
+                        break;
                     }
                 }
             }
 
+            check_invariants(&self.data, prev_offset, prev_output_len);
             Ok(())
         }
     }
@@ -206,7 +288,11 @@ mod detail {
         t2: &KugouInternalTable,
         v2: &KugouInternalTable,
     ) -> impl Decryptor {
-        Kugou::new("Kugou(KGM)", t1, t2, v2, KugouKGM::new())
+        new_kgm_with_table(&KgmMaskTable::from_tables(t1, t2, v2))
+    }
+
+    pub fn new_kgm_with_table(tables: &KgmMaskTable) -> impl Decryptor {
+        Kugou::new("Kugou(KGM)", tables, KugouKGM::new())
     }
 
     pub fn new_vpr(
@@ -215,21 +301,31 @@ mod detail {
         v2: &KugouInternalTable,
         vpr_key: &KugouVPRKey,
     ) -> impl Decryptor {
-        Kugou::new("Kugou(VPR)", t1, t2, v2, KugouVPR::new(vpr_key))
+        new_vpr_with_table(&KgmMaskTable::from_tables(t1, t2, v2), vpr_key)
+    }
+
+    pub fn new_vpr_with_table(tables: &KgmMaskTable, vpr_key: &KugouVPRKey) -> impl Decryptor {
+        Kugou::new("Kugou(VPR)", tables, KugouVPR::new(vpr_key))
     }
 }
 
 pub use detail::new_kgm;
+pub use detail::new_kgm_with_table;
 pub use detail::new_vpr;
+pub use detail::new_vpr_with_table;
 
 #[cfg(test)]
 pub mod test {
     use crate::{
         decryption::kugou::KUGOU_VPR_KEY_SIZE,
-        utils::test_util::test::{decrypt_test_content, generate_test_data, TEST_SIZE_4MB},
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::test_util::test::{
+            assert_deterministic_after_reset, decrypt_in_chunks, decrypt_test_content,
+            generate_test_data, TEST_SIZE_4MB,
+        },
     };
 
-    use super::KUGOU_INTERNAL_TABLE_SIZE;
+    use super::{KgmMaskTable, KUGOU_INTERNAL_TABLE_SIZE};
 
     #[test]
     fn test_kgm() {
@@ -288,4 +384,138 @@ pub mod test {
             "9f8786693b334d074b0ef5c573672c9cf290fae204b285240c18f93cd7ebaca5"
         );
     }
+
+    #[test]
+    fn test_kgm_reset() {
+        let t1 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_t1");
+        let t2 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_t2");
+        let v2 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_v2");
+        let tables = KgmMaskTable::new(&t1, &t2, &v2).unwrap();
+
+        let mut test_data = generate_test_data(0x4000, "kgm_reset_data");
+        test_data[..20].copy_from_slice(&[
+            0x7C, 0xD5, 0x32, 0xEB, 0x86, 0x02, 0x7F, 0x4B, 0xA8, 0xAF, //
+            0xA6, 0x8E, 0x0F, 0xFF, 0x99, 0x14, 0x00, 0x04, 0x00, 0x00, //
+        ]);
+
+        assert_deterministic_after_reset(|| super::new_kgm_with_table(&tables), &test_data);
+    }
+
+    #[test]
+    fn test_kgm_end_without_header() {
+        let t1 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_t1");
+        let t2 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_t2");
+        let v2 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_v2");
+        let new_kgm = || {
+            super::new_kgm(
+                t1[..].try_into().unwrap(),
+                t2[..].try_into().unwrap(),
+                v2[..].try_into().unwrap(),
+            )
+        };
+
+        let err = new_kgm().end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::EmptyInput));
+
+        let mut decryptor = new_kgm();
+        decryptor.write(&[0x7C, 0xD5, 0x32, 0xEB]).unwrap();
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::NotEnoughData));
+    }
+
+    #[test]
+    fn test_kgm_header_size_out_of_range() {
+        let t1 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_t1");
+        let t2 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_t2");
+        let v2 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_v2");
+
+        for header_size in [0u32, 0xffff_ffff] {
+            let mut header = vec![0u8; 0x2c];
+            header[..16].copy_from_slice(&[
+                0x7C, 0xD5, 0x32, 0xEB, 0x86, 0x02, 0x7F, 0x4B, //
+                0xA8, 0xAF, 0xA6, 0x8E, 0x0F, 0xFF, 0x99, 0x14, //
+            ]);
+            header[0x10..0x14].copy_from_slice(&header_size.to_le_bytes());
+
+            let mut decryptor = super::new_kgm(
+                t1[..].try_into().unwrap(),
+                t2[..].try_into().unwrap(),
+                v2[..].try_into().unwrap(),
+            );
+            let err = decryptor.write(&header).unwrap_err();
+            assert!(matches!(
+                err.code(),
+                DecryptErrorCode::InvalidHeaderField { field: "header_size", value }
+                    if *value == header_size as u64
+            ));
+        }
+    }
+
+    #[test]
+    fn test_kgm_mask_table() {
+        let t1 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_t1");
+        let t2 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_t2");
+        let v2 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_v2");
+
+        let mut test_data = generate_test_data(0x1000, "kgm mask table data");
+        test_data[..20].copy_from_slice(&[
+            0x7C, 0xD5, 0x32, 0xEB, 0x86, 0x02, 0x7F, 0x4B, 0xA8, 0xAF, //
+            0xA6, 0x8E, 0x0F, 0xFF, 0x99, 0x14, 0x3c, 0x00, 0x00, 0x00, //
+        ]);
+
+        let tables = KgmMaskTable::new(&t1, &t2, &v2).unwrap();
+        let mut from_tables = super::new_kgm_with_table(&tables);
+        let mut from_arrays = super::new_kgm(
+            t1[..].try_into().unwrap(),
+            t2[..].try_into().unwrap(),
+            v2[..].try_into().unwrap(),
+        );
+        assert_eq!(
+            decrypt_in_chunks(&mut from_tables, &test_data, 0x100),
+            decrypt_in_chunks(&mut from_arrays, &test_data, 0)
+        );
+
+        let err = KgmMaskTable::new(&t1, &t2[..16], &v2).err().unwrap();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidMaskTable));
+    }
+
+    #[test]
+    fn test_vpr_differs_from_kgm_by_vpr_key() {
+        let t1 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_t1");
+        let t2 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_t2");
+        let v2 = generate_test_data(KUGOU_INTERNAL_TABLE_SIZE, "kgm_test1_v2");
+        let vpr_key = generate_test_data(KUGOU_VPR_KEY_SIZE, "vpr_test1_key");
+        let tables = KgmMaskTable::new(&t1, &t2, &v2).unwrap();
+
+        // Same header fields and body, only the magic differs.
+        let file = |magic: &[u8]| {
+            let mut data = generate_test_data(0x1000, "kgm vpr shared body");
+            data[..16].copy_from_slice(magic);
+            data[0x10..0x14].copy_from_slice(&0x3cu32.to_le_bytes());
+            data
+        };
+        let kgm = decrypt_in_chunks(
+            &mut super::new_kgm_with_table(&tables),
+            &file(&[
+                0x7C, 0xD5, 0x32, 0xEB, 0x86, 0x02, 0x7F, 0x4B, //
+                0xA8, 0xAF, 0xA6, 0x8E, 0x0F, 0xFF, 0x99, 0x14, //
+            ]),
+            0,
+        );
+        let vpr = decrypt_in_chunks(
+            &mut super::new_vpr_with_table(&tables, vpr_key[..].try_into().unwrap()),
+            &file(&[
+                0x05, 0x28, 0xbc, 0x96, 0xe9, 0xe4, 0x5a, 0x43, //
+                0x91, 0xaa, 0xbd, 0xd0, 0x7a, 0xf5, 0x36, 0x31, //
+            ]),
+            0,
+        );
+
+        assert_eq!(kgm.len(), 0x1000 - 0x3c);
+        assert_ne!(kgm, vpr);
+        // VPR is KGM with the VPR key XOR'd on last, aligned to the body.
+        for (i, (k, v)) in kgm.iter().zip(vpr.iter()).enumerate() {
+            assert_eq!(k ^ vpr_key[i % KUGOU_VPR_KEY_SIZE], *v, "offset {}", i);
+        }
+    }
 }
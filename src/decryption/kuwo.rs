@@ -4,12 +4,16 @@ type KuwoKey = [u8; KUWO_DECRYPTION_KEY_SIZE];
 mod detail {
     use super::KuwoKey;
     use crate::{
-        decryptor::{BaseDecryptorData, DecryptError, DecryptErrorCode, Decryptor},
+        decryptor::{
+            check_invariants, trace_write_span, transition_state, BaseDecryptorData, DecryptError,
+            DecryptErrorCode, DecryptManifest, Decryptor,
+        },
         impl_decryptor_inner_helper,
         utils::array_ext::ByteSliceExt,
     };
     use std::cmp::Ordering;
 
+    const VERSION_OFFSET: usize = 0x10;
     const FILE_KEY_OFFSET: usize = 0x18;
     const FULL_HEADER_SIZE: usize = 0x400;
     const KUWO_MAGIC_HEADER: &[u8; 0x10] = &[
@@ -17,6 +21,7 @@ mod detail {
         0x6bu8, 0x75, 0x77, 0x6f, 0x2d, 0x74, 0x6d, 0x65, //
     ];
 
+    #[derive(Debug)]
     enum State {
         WaitForHeader,
         Decrypt,
@@ -24,6 +29,7 @@ mod detail {
 
     struct KuwoDecryptor {
         data: BaseDecryptorData,
+        base_key: KuwoKey,
         key: KuwoKey,
         state: State,
     }
@@ -32,6 +38,7 @@ mod detail {
         fn new(key: &KuwoKey) -> Self {
             Self {
                 data: BaseDecryptorData::new("Kuwo"),
+                base_key: *key,
                 key: *key,
                 state: State::WaitForHeader,
             }
@@ -47,7 +54,29 @@ mod detail {
     impl Decryptor for KuwoDecryptor {
         impl_decryptor_inner_helper! {}
 
+        /// Fingerprints the key as configured, before the resource id is mixed in.
+        fn manifest(&self) -> DecryptManifest {
+            self.data.manifest(Some(&self.base_key), FULL_HEADER_SIZE)
+        }
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            self.data.mark_finalized();
+            self.data
+                .ensure_header_complete(matches!(self.state, State::Decrypt))
+        }
+
+        fn reset(&mut self) -> Result<(), DecryptError> {
+            self.data.reset();
+            // `init_key` mixes the resource id into the key in place.
+            self.key = self.base_key;
+            self.state = State::WaitForHeader;
+            Ok(())
+        }
+
         fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            trace_write_span!(self, state);
+            self.data.ensure_not_finalized()?;
+            let (prev_offset, prev_output_len) = (self.data.offset, self.data.buf_out.len());
             let mut p = data;
 
             while !p.is_empty() {
@@ -63,9 +92,19 @@ mod detail {
                                 ));
                             }
 
+                            // Version 2 files need a per-file key that is not part of
+                            // the header, so only version 1 can be decrypted.
+                            let version = self.data.buf_in.read_le::<u32>(VERSION_OFFSET);
+                            if version != 1 {
+                                return Err(DecryptError::new(
+                                    DecryptErrorCode::UnknownEncryption,
+                                    &format!("unsupported kuwo version {}", version),
+                                ));
+                            }
+
                             self.init_key();
                             self.data.seek_input(FULL_HEADER_SIZE);
-                            self.state = State::Decrypt;
+                            transition_state!(self, State::Decrypt);
                         }
                     }
 
@@ -75,11 +114,12 @@ mod detail {
                         out.xor_key_with_key_offset(self.key, self.data.offset);
                         self.data.buf_out.append(&mut out);
                         self.data.offset += size;
-                        return Ok(());
+                        break;
                     }
                 }
             }
 
+            check_invariants(&self.data, prev_offset, prev_output_len);
             Ok(())
         }
     }
@@ -93,7 +133,13 @@ pub use detail::new_kwm;
 
 #[cfg(test)]
 mod test {
-    use crate::utils::test_util::test::{decrypt_test_content, generate_test_data, TEST_SIZE_4MB};
+    use crate::{
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::test_util::test::{
+            assert_deterministic_after_reset, decrypt_in_chunks, decrypt_test_content,
+            generate_test_data, EncryptedBuilder, TEST_SIZE_4MB,
+        },
+    };
 
     #[test]
     fn test_kwm() {
@@ -113,4 +159,95 @@ mod test {
             "aefad6b6f75ecb915fd0211f02eeacbd9c28e51b22c06c6d1bb3c61c963feaae"
         );
     }
+
+    #[test]
+    fn test_kwm_reset() {
+        let key = generate_test_data(super::KUWO_DECRYPTION_KEY_SIZE, "kuwo-test-key");
+        let body = generate_test_data(0x4000, "kuwo-reset-body");
+        let encrypted = EncryptedBuilder::kuwo(key[..].try_into().unwrap(), 0x1234)
+            .body(&body)
+            .build();
+
+        assert_deterministic_after_reset(
+            || super::new_kwm(key[..].try_into().unwrap()),
+            &encrypted,
+        );
+    }
+
+    #[test]
+    fn test_kwm_manifest() {
+        use crate::decryptor::key_fingerprint;
+
+        let key = generate_test_data(super::KUWO_DECRYPTION_KEY_SIZE, "kuwo-test-key");
+        let encrypted = EncryptedBuilder::kuwo(key[..].try_into().unwrap(), 0x1234)
+            .body([0u8; 0x10])
+            .build();
+
+        let mut decryptor = super::new_kwm(key[..].try_into().unwrap());
+        decrypt_in_chunks(&mut decryptor, &encrypted, 0);
+        let manifest = decryptor.manifest();
+        assert_eq!(manifest.format, "Kuwo");
+        assert_eq!(manifest.key_fingerprint, Some(key_fingerprint(&key)));
+        assert_eq!(manifest.header_len, 0x400);
+        assert_eq!(manifest.bytes_in, encrypted.len() as u64);
+    }
+
+    #[test]
+    fn test_kwm_range_not_supported() {
+        let test_key = generate_test_data(super::KUWO_DECRYPTION_KEY_SIZE, "kuwo-test-key");
+        let decryptor = super::new_kwm(test_key[..].try_into().unwrap());
+
+        let mut buf = vec![0u8; 0x10];
+        let err = decryptor.decrypt_range(0x800..0x810, &mut buf).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::RangeNotSupported));
+    }
+
+    #[test]
+    fn test_kwm_end_without_header() {
+        let test_key = generate_test_data(super::KUWO_DECRYPTION_KEY_SIZE, "kuwo-test-key");
+
+        let mut decryptor = super::new_kwm(test_key[..].try_into().unwrap());
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::EmptyInput));
+
+        let mut decryptor = super::new_kwm(test_key[..].try_into().unwrap());
+        decryptor.write(b"yeelion-kuwo-tme").unwrap();
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::NotEnoughData));
+    }
+
+    #[test]
+    fn test_kwm_body_shorter_than_key() {
+        let key = generate_test_data(super::KUWO_DECRYPTION_KEY_SIZE, "kuwo-test-key");
+        let body = [0x11u8, 0x22, 0x33];
+        let encrypted = EncryptedBuilder::kuwo(key[..].try_into().unwrap(), 0x1234)
+            .body(body)
+            .build();
+
+        let mut decryptor = super::new_kwm(key[..].try_into().unwrap());
+        assert_eq!(decrypt_in_chunks(&mut decryptor, &encrypted, 1), body);
+
+        // A header one byte short errors out instead of reading past the input.
+        let mut decryptor = super::new_kwm(key[..].try_into().unwrap());
+        decryptor.write(&encrypted[..0x3ff]).unwrap();
+        assert!(decryptor.read_all_output().is_empty());
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::NotEnoughData));
+    }
+
+    #[test]
+    fn test_kwm_version() {
+        let test_key = generate_test_data(super::KUWO_DECRYPTION_KEY_SIZE, "kuwo-test-key");
+        let mut header = vec![0u8; 0x400];
+        header[..0x10].copy_from_slice(b"yeelion-kuwo-tme");
+
+        header[0x10] = 1;
+        let mut decryptor = super::new_kwm(test_key[..].try_into().unwrap());
+        decryptor.write(&header).unwrap();
+
+        header[0x10] = 2;
+        let mut decryptor = super::new_kwm(test_key[..].try_into().unwrap());
+        let err = decryptor.write(&header).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::UnknownEncryption));
+    }
 }
@@ -1,27 +1,52 @@
+pub mod detect;
+pub mod header;
+pub mod info;
+#[cfg(feature = "joox")]
 pub mod joox;
+#[cfg(feature = "kugou")]
 pub mod kugou;
+#[cfg(feature = "kuwo")]
 pub mod kuwo;
+#[cfg(feature = "ncm")]
 pub mod ncm;
+pub mod passthrough;
+pub mod registry;
+pub mod xiami;
+#[cfg(feature = "ximalaya")]
 pub mod ximalaya;
 
+#[cfg(feature = "qmc")]
 mod qmc;
+#[cfg(feature = "qmc")]
 mod qmc_v1;
+#[cfg(feature = "qmc")]
 mod qmc_v2;
 
+pub use detect::{detect_format, detect_format_ranked, AudioFormat};
+pub use info::DecryptorInfo;
+pub use registry::{decryptor_from_extension, new_decryptor, required_keys};
+
 // Kugou
-pub use kugou::new_kgm;
-pub use kugou::new_vpr;
+#[cfg(feature = "kugou")]
+pub use kugou::{new_kgm, new_kgm_with_table, new_vpr, new_vpr_with_table, KgmMaskTable};
 
 // Kuwo
+#[cfg(feature = "kuwo")]
 pub use kuwo::new_kwm;
 
 // Netease
-pub use ncm::new_ncm;
+#[cfg(feature = "ncm")]
+pub use ncm::{new_ncm, new_ncm_audio_only, new_ncm_with_metadata};
+
+// Plaintext
+pub use passthrough::new_passthrough;
 
 // Tencent QQMusic (QMC)
-pub use qmc::new_qmc_v2;
+#[cfg(feature = "qmc")]
+pub use qmc::{new_qmc_v2, new_qmc_v2_with_key, new_qmc_v2_with_resolver};
+#[cfg(feature = "qmc")]
 pub use qmc_v1::new_qmc_v1_static as new_qmc_v1;
 
 // Ximalaya
-pub use ximalaya::new_x2m;
-pub use ximalaya::new_x3m;
+#[cfg(feature = "ximalaya")]
+pub use ximalaya::{new_x2m, new_x3m, new_ximalaya, try_new_x2m, try_new_x3m};
@@ -1,6 +1,6 @@
 // AES-128
-const NCM_DECRYPTION_KEY_SIZE: usize = 128 / 8;
-type NCMAudioKey = [u8; NCM_DECRYPTION_KEY_SIZE];
+pub const NCM_DECRYPTION_KEY_SIZE: usize = 128 / 8;
+pub type NCMAudioKey = [u8; NCM_DECRYPTION_KEY_SIZE];
 
 mod detail {
     /**
@@ -11,13 +11,16 @@ mod detail {
      *
      * Followed by 3 blocks:
      *   - Content Key (Encrypted using `NCMAudioKey`)
-     *   - Metadata; (AES-128 Encrypted, ignored by this library)
+     *   - Metadata; (AES-128 Encrypted, only parsed for `new_ncm_with_metadata`)
      *   - Album Cover (prefixed with 5 bytes padding? ignored by this library);
      *   - Audio Data (Encrypted with Content Key);
      */
     use super::NCMAudioKey;
     use crate::{
-        decryptor::{BaseDecryptorData, DecryptError, DecryptErrorCode, Decryptor},
+        decryptor::{
+            check_invariants, invalid_header_field, trace_write_span, transition_state,
+            BaseDecryptorData, DecryptError, DecryptErrorCode, DecryptManifest, Decryptor,
+        },
         impl_decryptor_inner_helper,
         utils::{
             aes_ecb::Aes128EcbDec,
@@ -36,13 +39,33 @@ mod detail {
     const CONTENT_KEY_PREFIX: &[u8; 17] = b"neteasecloudmusic";
     const CONTENT_KEY_PREFIX_LEN: usize = CONTENT_KEY_PREFIX.len();
 
+    const METADATA_PREFIX: &[u8; 22] = b"163 key(Don't modify):";
+
+    // Upper bounds for the sized blocks, so corrupt sizes are rejected before
+    // anything is buffered.
+    const MAX_CONTENT_KEY_SIZE: usize = 0x400;
+    const MAX_METADATA_SIZE: usize = 0x10_0000;
+
+    /// The metadata block of an NCM file.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct NcmMetadata {
+        /// `music` for songs, `dj` for radio programs.
+        pub kind: String,
+        /// The raw JSON document, holding e.g. `musicName`, `artist` and `album`.
+        pub json: String,
+    }
+
+    type MetadataCallback = Box<dyn FnOnce(NcmMetadata) + Send>;
+
+    #[derive(Debug)]
     enum State {
         ReadFileHeader,
         ParseFileKey,
         ReadMetaBlock,
         ReadCoverFrameSize,
+        SkipMetaBlock(usize),
         ReadCoverBlock,
-        SkipCoverPadding(usize),
+        SkipCoverFrame(usize),
         DecryptAudio,
     }
 
@@ -51,6 +74,8 @@ mod detail {
         state: State,
         master_decryption_key: NCMAudioKey,
         audio_decryption_key: [u8; 0x100],
+        skip_metadata: bool,
+        on_metadata: Option<(NCMAudioKey, MetadataCallback)>,
 
         content_key_size: usize,
         metadata_size: usize,
@@ -62,7 +87,53 @@ mod detail {
     impl Decryptor for NeteaseDecryptor {
         impl_decryptor_inner_helper! {}
 
+        /// Fingerprints the core key. The sized blocks are not counted in the
+        /// input offset, so the header length and bytes seen are only known
+        /// once the audio starts.
+        fn manifest(&self) -> DecryptManifest {
+            let manifest = self.data.manifest(Some(&self.master_decryption_key), 0);
+            match self.state {
+                // Magic, then the size-prefixed key, metadata and cover frame.
+                State::DecryptAudio => {
+                    let header_len = INITIAL_HEADER_LEN
+                        + self.content_key_size
+                        + self.metadata_size
+                        + self.cover_frame_size
+                        + 4 * size_of::<u32>();
+                    DecryptManifest {
+                        header_len: header_len as u64,
+                        bytes_in: (header_len + self.audio_offset) as u64,
+                        ..manifest
+                    }
+                }
+                _ => manifest,
+            }
+        }
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            self.data.mark_finalized();
+            self.data
+                .ensure_header_complete(matches!(self.state, State::DecryptAudio))
+        }
+
+        /// The metadata callback runs at most once, so it is not invoked again
+        /// after a reset.
+        fn reset(&mut self) -> Result<(), DecryptError> {
+            self.data.reset();
+            self.state = State::ReadFileHeader;
+            self.audio_decryption_key = [0u8; 0x100];
+            self.content_key_size = 0;
+            self.metadata_size = 0;
+            self.cover_frame_size = 0;
+            self.cover_size = 0;
+            self.audio_offset = 0;
+            Ok(())
+        }
+
         fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            trace_write_span!(self, state);
+            self.data.ensure_not_finalized()?;
+            let (prev_offset, prev_output_len) = (self.data.offset, self.data.buf_out.len());
             let mut p = data;
 
             while !p.is_empty() {
@@ -77,68 +148,103 @@ mod detail {
                             }
 
                             self.data.seek_input(INITIAL_HEADER_LEN);
-                            self.state = State::ParseFileKey;
+                            transition_state!(self, State::ParseFileKey);
                         }
                     }
 
                     State::ParseFileKey => {
                         let ok: bool;
-                        (ok, self.content_key_size) =
-                            self.read_next_sized_block(&mut p, self.content_key_size, 0)?;
+                        (ok, self.content_key_size) = self.read_next_sized_block(
+                            &mut p,
+                            self.content_key_size,
+                            0,
+                            ("content_key_size", MAX_CONTENT_KEY_SIZE),
+                        )?;
                         if ok {
                             self.parse_key()?;
-                            self.state = State::ReadMetaBlock;
+                            transition_state!(self, State::ReadMetaBlock);
+                        }
+                    }
+
+                    State::ReadMetaBlock if self.skip_metadata => {
+                        if let Some(size) =
+                            self.read_block_size(&mut p, "metadata_size", MAX_METADATA_SIZE)?
+                        {
+                            self.metadata_size = size + 5;
+                            transition_state!(self, State::SkipMetaBlock(self.metadata_size));
                         }
                     }
 
                     State::ReadMetaBlock => {
                         let ok: bool;
-                        (ok, self.metadata_size) =
-                            self.read_next_sized_block(&mut p, self.metadata_size, 5)?;
+                        (ok, self.metadata_size) = self.read_next_sized_block(
+                            &mut p,
+                            self.metadata_size,
+                            5,
+                            ("metadata_size", MAX_METADATA_SIZE),
+                        )?;
                         if ok {
-                            self.data.seek_input(self.metadata_size);
-                            self.state = State::ReadCoverFrameSize;
+                            let metadata = self.data.consume_input(self.metadata_size);
+                            if let Some((meta_key, on_metadata)) = self.on_metadata.take() {
+                                // Padding excluded; malformed metadata is not an error.
+                                if let Some(metadata) =
+                                    Self::parse_metadata(&metadata[..metadata.len() - 5], &meta_key)
+                                {
+                                    on_metadata(metadata);
+                                }
+                            }
+                            transition_state!(self, State::ReadCoverFrameSize);
                         }
                     }
 
+                    State::SkipMetaBlock(n) => match Self::skip_input(&mut p, n) {
+                        0 => transition_state!(self, State::ReadCoverFrameSize),
+                        n => self.state = State::SkipMetaBlock(n),
+                    },
+
                     State::ReadCoverFrameSize => {
                         if self.data.read_block(&mut p, size_of::<u32>()) {
                             self.cover_frame_size =
                                 self.data.consume_input(size_of::<u32>()).read_le::<u32>(0)
                                     as usize;
-                            self.state = State::ReadCoverBlock;
+                            transition_state!(self, State::ReadCoverBlock);
+                        }
+                    }
+
+                    State::ReadCoverBlock if self.skip_metadata => {
+                        if let Some(size) =
+                            self.read_block_size(&mut p, "cover_size", self.cover_frame_size)?
+                        {
+                            // Same checks as reading the cover block in full.
+                            Self::check_block_not_empty(size)?;
+                            self.cover_size = size;
+                            transition_state!(self, State::SkipCoverFrame(self.cover_frame_size));
                         }
                     }
 
                     State::ReadCoverBlock => {
+                        // Bounded by the cover frame, before buffering the cover.
                         let ok: bool;
-                        (ok, self.cover_size) =
-                            self.read_next_sized_block(&mut p, self.cover_size, 0)?;
+                        (ok, self.cover_size) = self.read_next_sized_block(
+                            &mut p,
+                            self.cover_size,
+                            0,
+                            ("cover_size", self.cover_frame_size),
+                        )?;
                         if ok {
-                            if self.cover_frame_size < self.cover_size {
-                                return Err(DecryptError::new(
-                                    DecryptErrorCode::NCMCoverFrameTooSmall,
-                                    "cover_frame too small",
-                                ));
-                            }
-
                             self.data.seek_input(self.cover_size);
 
-                            self.state =
-                                State::SkipCoverPadding(self.cover_frame_size - self.cover_size);
+                            transition_state!(
+                                self,
+                                State::SkipCoverFrame(self.cover_frame_size - self.cover_size)
+                            );
                         }
                     }
 
-                    State::SkipCoverPadding(0) => {
-                        self.state = State::DecryptAudio;
-                    }
-
-                    State::SkipCoverPadding(n) => {
-                        if self.data.read_block(&mut p, n) {
-                            self.data.seek_input(n);
-                            self.state = State::DecryptAudio;
-                        }
-                    }
+                    State::SkipCoverFrame(n) => match Self::skip_input(&mut p, n) {
+                        0 => transition_state!(self, State::DecryptAudio),
+                        n => self.state = State::SkipCoverFrame(n),
+                    },
 
                     State::DecryptAudio => {
                         let size = p.len();
@@ -147,20 +253,35 @@ mod detail {
                         self.data.buf_out.append(&mut out);
                         self.data.offset += size;
                         self.audio_offset += size;
-                        return Ok(());
+                        break;
                     }
                 }
             }
 
+            check_invariants(&self.data, prev_offset, prev_output_len);
             Ok(())
         }
     }
 
     impl NeteaseDecryptor {
-        fn new(master_audio_key: &NCMAudioKey) -> Self {
+        fn new(master_audio_key: &NCMAudioKey, skip_metadata: bool) -> Self {
+            Self::new_with_size_hint(master_audio_key, skip_metadata, None)
+        }
+
+        fn new_with_size_hint(
+            master_audio_key: &NCMAudioKey,
+            skip_metadata: bool,
+            size_hint: Option<usize>,
+        ) -> Self {
+            let data = match size_hint {
+                Some(hint) => BaseDecryptorData::new_with_capacity("Netease", hint),
+                None => BaseDecryptorData::new("Netease"),
+            };
             Self {
-                data: BaseDecryptorData::new("Netease"),
+                data,
                 master_decryption_key: *master_audio_key,
+                skip_metadata,
+                on_metadata: None,
                 state: State::ReadFileHeader,
                 audio_decryption_key: [0u8; 0x100],
                 content_key_size: 0,
@@ -171,22 +292,36 @@ mod detail {
             }
         }
 
+        /// Read the `u32` size prefixing a block, once enough data arrived.
+        fn read_block_size(
+            &mut self,
+            data: &mut &[u8],
+            field: &'static str,
+            max_block_size: usize,
+        ) -> Result<Option<usize>, DecryptError> {
+            if !self.data.read_block(data, size_of::<u32>()) {
+                return Ok(None);
+            }
+
+            let block_size = self.data.consume_input(size_of::<u32>()).read_le::<u32>(0) as usize;
+            if block_size > max_block_size {
+                return Err(invalid_header_field(field, block_size as u64));
+            }
+            Ok(Some(block_size))
+        }
+
         fn read_next_sized_block(
             &mut self,
             data: &mut &[u8],
             next_block_size: usize,
             padding: usize,
+            (field, max_block_size): (&'static str, usize),
         ) -> Result<(bool, usize), DecryptError> {
             let mut next_block_size = next_block_size;
-            if next_block_size == 0 && self.data.read_block(data, size_of::<u32>()) {
-                next_block_size = (self.data.buf_in.read_le::<u32>(0) as usize) + padding;
-                self.data.seek_input(size_of::<u32>());
-
-                if next_block_size == 0 {
-                    return Err(DecryptError::new(
-                        DecryptErrorCode::InvalidBlockSize,
-                        "block size is ZERO",
-                    ));
+            if next_block_size == 0 {
+                if let Some(block_size) = self.read_block_size(data, field, max_block_size)? {
+                    next_block_size = block_size + padding;
+                    Self::check_block_not_empty(next_block_size)?;
                 }
             }
 
@@ -194,6 +329,40 @@ mod detail {
             Ok((ok, next_block_size))
         }
 
+        fn check_block_not_empty(block_size: usize) -> Result<(), DecryptError> {
+            if block_size == 0 {
+                return Err(DecryptError::new(
+                    DecryptErrorCode::InvalidBlockSize,
+                    "block size is ZERO",
+                ));
+            }
+            Ok(())
+        }
+
+        /// Drop up to `len` bytes of `data` without buffering them, returning
+        /// how many are left to skip.
+        fn skip_input(data: &mut &[u8], len: usize) -> usize {
+            let n = len.min(data.len());
+            *data = &data[n..];
+            len - n
+        }
+
+        fn parse_metadata(block: &[u8], meta_key: &NCMAudioKey) -> Option<NcmMetadata> {
+            let block: Vec<u8> = block.iter().map(|v| v ^ 0x63).collect();
+            let encrypted = base64::decode(block.strip_prefix(METADATA_PREFIX)?).ok()?;
+            let mut out = encrypted.clone();
+            let metadata = Aes128EcbDec::new_from_slice(meta_key)
+                .ok()?
+                .decrypt_padded_b2b_mut::<Pkcs7>(&encrypted, &mut out)
+                .ok()?;
+
+            let (kind, json) = std::str::from_utf8(metadata).ok()?.split_once(':')?;
+            Some(NcmMetadata {
+                kind: kind.into(),
+                json: json.into(),
+            })
+        }
+
         fn parse_key(&mut self) -> Result<(), DecryptError> {
             let mut encrypted_content_key = self.data.consume_input(self.content_key_size);
             for v in encrypted_content_key.iter_mut() {
@@ -224,33 +393,127 @@ mod detail {
 
             // Init decryption key
             self.audio_offset = 0;
-            let mut rc4 = RC4Netease::new(content_key);
-            for v in self.audio_decryption_key.iter_mut() {
-                *v = rc4.next();
-            }
+            self.audio_decryption_key = build_ncm_keybox(content_key);
 
             Ok(())
         }
     }
 
+    /// The 256 byte keybox the audio is XOR'd with (repeating), derived from
+    /// the content key (past its `neteasecloudmusic` prefix) by the Netease
+    /// RC4 variant.
+    pub(crate) fn build_ncm_keybox(key: &[u8]) -> [u8; 0x100] {
+        let mut keybox = [0u8; 0x100];
+        let mut rc4 = RC4Netease::new(key);
+        for v in keybox.iter_mut() {
+            *v = rc4.next();
+        }
+        keybox
+    }
+
     pub fn new_ncm(key: &NCMAudioKey) -> impl Decryptor {
-        NeteaseDecryptor::new(key)
+        NeteaseDecryptor::new(key, false)
+    }
+
+    /// Like [`new_ncm`], but skips the metadata and cover blocks without
+    /// buffering them, for callers only after the audio.
+    pub fn new_ncm_audio_only(key: &NCMAudioKey) -> impl Decryptor {
+        NeteaseDecryptor::new(key, true)
+    }
+
+    /// Like [`new_ncm`], also decrypting the metadata block with `meta_key`
+    /// and handing it to `on_metadata` as soon as it is parsed, before any
+    /// audio is decrypted. Metadata that fails to decrypt is skipped.
+    pub fn new_ncm_with_metadata<F>(
+        key: &NCMAudioKey,
+        meta_key: &NCMAudioKey,
+        on_metadata: F,
+    ) -> impl Decryptor
+    where
+        F: FnOnce(NcmMetadata) + Send + 'static,
+    {
+        let mut decryptor = NeteaseDecryptor::new(key, false);
+        decryptor.on_metadata = Some((*meta_key, Box::new(on_metadata)));
+        decryptor
+    }
+
+    #[cfg(feature = "default-keys")]
+    const DEFAULT_CORE_KEY: Option<NCMAudioKey> = Some(crate::keys::defaults::NCM_CORE_KEY);
+    #[cfg(not(feature = "default-keys"))]
+    const DEFAULT_CORE_KEY: Option<NCMAudioKey> = None;
+
+    #[derive(Debug, Default, PartialEq, Eq)]
+    pub struct NcmBuilder {
+        core_key: Option<NCMAudioKey>,
+        skip_metadata: bool,
+        size_hint: Option<usize>,
+    }
+
+    impl NcmBuilder {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn with_core_key(mut self, key: NCMAudioKey) -> Self {
+            self.core_key = Some(key);
+            self
+        }
+
+        /// Skip the metadata and cover blocks, see [`new_ncm_audio_only`].
+        pub fn with_skip_metadata(mut self, skip_metadata: bool) -> Self {
+            self.skip_metadata = skip_metadata;
+            self
+        }
+
+        /// Expected input size, to pre-size the output buffer.
+        pub fn with_size_hint(mut self, size_hint: usize) -> Self {
+            self.size_hint = Some(size_hint);
+            self
+        }
+
+        /// Accepts the core key as hex, e.g. `687A4852416D736F356B496E62617857`.
+        pub fn with_core_key_hex(self, key: &str) -> Result<Self, DecryptError> {
+            let key = crate::keys::encoding::parse_hex(key)?;
+            let key: NCMAudioKey = key.try_into().map_err(|_| {
+                DecryptError::new(DecryptErrorCode::InvalidKeyLength, "invalid core key size")
+            })?;
+            Ok(self.with_core_key(key))
+        }
+
+        /// Without a core key, falls back to the well-known one when the
+        /// `default-keys` feature is enabled.
+        pub fn build(self) -> Result<impl Decryptor, DecryptError> {
+            let key = self.core_key.or(DEFAULT_CORE_KEY).ok_or_else(|| {
+                DecryptError::new(DecryptErrorCode::MissingKey, "core key not provided")
+            })?;
+            Ok(NeteaseDecryptor::new_with_size_hint(
+                &key,
+                self.skip_metadata,
+                self.size_hint,
+            ))
+        }
     }
 }
 
 pub use detail::new_ncm;
+pub use detail::new_ncm_audio_only;
+pub use detail::new_ncm_with_metadata;
+pub use detail::NcmBuilder;
+pub use detail::NcmMetadata;
 
 #[cfg(test)]
-mod test {
+pub mod test {
+    use super::NcmBuilder;
     use crate::{
         decryption::ncm::NCM_DECRYPTION_KEY_SIZE,
-        utils::test_util::test::{decrypt_test_content, generate_test_data, TEST_SIZE_4MB},
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::test_util::test::{
+            assert_deterministic_after_reset, decrypt_in_chunks, decrypt_test_content,
+            generate_test_data, TEST_SIZE_4MB,
+        },
     };
 
-    #[test]
-    fn test_ncm() {
-        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
-
+    pub fn ncm_test_data() -> Vec<u8> {
         let mut test_data = generate_test_data(TEST_SIZE_4MB, "ncm-test-data");
         let header_override: &[u8] = &[
             0x43, 0x54, 0x45, 0x4E, 0x46, 0x44, 0x41, 0x4D, // header
@@ -282,6 +545,13 @@ mod test {
             0xff, 0xff, 0xff, // Image Cover data
         ];
         test_data[..header_override.len()].copy_from_slice(header_override);
+        test_data
+    }
+
+    #[test]
+    fn test_ncm() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let test_data = ncm_test_data();
 
         let mut decryptor = super::new_ncm(test_key[..].try_into().unwrap());
         let result = decrypt_test_content(&mut decryptor, test_data);
@@ -290,4 +560,307 @@ mod test {
             "dae77d29821092561702e3cde97add3558f21a1607c9aab0599983632ce0d54b"
         );
     }
+
+    #[test]
+    fn test_ncm_reset() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let test_data = &ncm_test_data()[..0x4000];
+
+        assert_deterministic_after_reset(
+            || super::new_ncm(test_key[..].try_into().unwrap()),
+            test_data,
+        );
+        assert_deterministic_after_reset(
+            || super::new_ncm_audio_only(test_key[..].try_into().unwrap()),
+            test_data,
+        );
+    }
+
+    #[test]
+    fn test_ncm_manifest() {
+        use crate::decryptor::key_fingerprint;
+
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let test_data = &ncm_test_data()[..0x1000];
+
+        let mut decryptor = super::new_ncm(test_key[..].try_into().unwrap());
+        assert_eq!(decryptor.manifest().header_len, 0);
+        decrypt_in_chunks(&mut decryptor, test_data, 0x100);
+        let manifest = decryptor.manifest();
+        assert_eq!(manifest.format, "Netease");
+        assert_eq!(manifest.key_fingerprint, Some(key_fingerprint(&test_key)));
+        // Magic, key block, metadata block and cover frame of `ncm_test_data`.
+        assert_eq!(manifest.header_len, 10 + 4 + 0x90 + 4 + 3 + 5 + 4 + 4 + 3);
+        assert_eq!(manifest.bytes_in, test_data.len() as u64);
+
+        let mut audio_only = super::new_ncm_audio_only(test_key[..].try_into().unwrap());
+        decrypt_in_chunks(&mut audio_only, test_data, 7);
+        assert_eq!(audio_only.manifest(), manifest);
+    }
+
+    #[test]
+    fn test_ncm_audio_only_matches_full_path() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let test_data = ncm_test_data();
+
+        let mut full = super::new_ncm(test_key[..].try_into().unwrap());
+        let expected = decrypt_in_chunks(&mut full, &test_data, 0x1000);
+        for chunk_size in [3, 7, 0x1000] {
+            let mut audio_only = super::new_ncm_audio_only(test_key[..].try_into().unwrap());
+            assert_eq!(
+                decrypt_in_chunks(&mut audio_only, &test_data, chunk_size),
+                expected
+            );
+        }
+
+        let mut built = NcmBuilder::new()
+            .with_core_key(test_key[..].try_into().unwrap())
+            .with_skip_metadata(true)
+            .build()
+            .unwrap();
+        assert_eq!(decrypt_in_chunks(&mut built, &test_data, 0x1000), expected);
+    }
+
+    #[test]
+    fn test_ncm_builder_size_hint() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let test_data = ncm_test_data();
+
+        let mut plain = super::new_ncm(test_key[..].try_into().unwrap());
+        let expected = decrypt_in_chunks(&mut plain, &test_data, 0x1000);
+
+        let mut hinted = NcmBuilder::new()
+            .with_core_key(test_key[..].try_into().unwrap())
+            .with_size_hint(test_data.len())
+            .build()
+            .unwrap();
+        assert_eq!(decrypt_in_chunks(&mut hinted, &test_data, 0x1000), expected);
+    }
+
+    #[test]
+    fn test_ncm_metadata_callback() {
+        use super::NcmMetadata;
+        use aes::{
+            cipher::{BlockEncrypt, KeyInit},
+            Aes128,
+        };
+        use std::sync::{Arc, Mutex};
+
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let meta_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm meta key");
+        let mut test_data = ncm_test_data();
+
+        // Swap the 3 byte metadata block of the test file for a real one.
+        let mut metadata = br#"music:{"musicName":"Parakeet"}"#.to_vec();
+        let padding = 16 - metadata.len() % 16;
+        metadata.resize(metadata.len() + padding, padding as u8);
+        let aes = Aes128::new_from_slice(&meta_key).unwrap();
+        for block in metadata.chunks_mut(16) {
+            aes.encrypt_block(block.into());
+        }
+        let mut block = b"163 key(Don't modify):".to_vec();
+        block.extend_from_slice(base64::encode(metadata).as_bytes());
+        block.iter_mut().for_each(|v| *v ^= 0x63);
+
+        let metadata_offset = 10 + 4 + 0x90;
+        let mut header = test_data[..metadata_offset].to_vec();
+        header.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        header.extend_from_slice(&block);
+        test_data.splice(..metadata_offset + 4 + 3, header.iter().copied());
+        let header_len = header.len() + 5;
+
+        let received: Arc<Mutex<Option<NcmMetadata>>> = Arc::default();
+        let sink = received.clone();
+        let mut decryptor = super::new_ncm_with_metadata(
+            test_key[..].try_into().unwrap(),
+            meta_key[..].try_into().unwrap(),
+            move |metadata| *sink.lock().unwrap() = Some(metadata),
+        );
+
+        decryptor.write(&test_data[..header_len - 1]).unwrap();
+        assert!(received.lock().unwrap().is_none());
+        decryptor
+            .write(&test_data[header_len - 1..header_len])
+            .unwrap();
+        assert!(decryptor.read_all_output().is_empty());
+        assert_eq!(
+            received.lock().unwrap().take(),
+            Some(NcmMetadata {
+                kind: "music".into(),
+                json: r#"{"musicName":"Parakeet"}"#.into(),
+            })
+        );
+
+        // Only the metadata block changed, the audio is the same.
+        let mut reference = super::new_ncm(test_key[..].try_into().unwrap());
+        let expected = decrypt_in_chunks(&mut reference, &ncm_test_data(), 0x1000);
+        let result = decrypt_in_chunks(&mut decryptor, &test_data[header_len..], 0x1000);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_ncm_builder_hex_core_key() {
+        let builder = NcmBuilder::new()
+            .with_core_key_hex("687A4852416D736F356B496E62617857")
+            .unwrap();
+        assert_eq!(
+            builder,
+            NcmBuilder::new().with_core_key(*b"hzHRAmso5kInbaxW")
+        );
+        assert!(builder.build().is_ok());
+    }
+
+    #[test]
+    fn test_ncm_builder_malformed_key() {
+        let err = NcmBuilder::new()
+            .with_core_key_hex("687A4852416D736F356B496E626178zz")
+            .unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidKeyEncoding));
+
+        let err = NcmBuilder::new().with_core_key_hex("687A4852").unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidKeyLength));
+
+        #[cfg(not(feature = "default-keys"))]
+        {
+            let err = NcmBuilder::new().build().err().unwrap();
+            assert!(matches!(err.code(), DecryptErrorCode::MissingKey));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "default-keys")]
+    fn test_ncm_builder_default_core_key() {
+        use crate::keys::defaults::NCM_CORE_KEY;
+        use aes::{
+            cipher::{BlockEncrypt, KeyInit},
+            Aes128,
+        };
+
+        // Content key block: PKCS#7 padded, AES-128-ECB with the core key,
+        // XOR 0x64.
+        let mut content_key = b"neteasecloudmusic".to_vec();
+        content_key.extend_from_slice(&generate_test_data(0x20, "ncm default content key"));
+        let padding = 16 - content_key.len() % 16;
+        content_key.resize(content_key.len() + padding, padding as u8);
+        let aes = Aes128::new(&NCM_CORE_KEY.into());
+        for block in content_key.chunks_mut(16) {
+            aes.encrypt_block(block.into());
+        }
+        content_key.iter_mut().for_each(|v| *v ^= 0x64);
+
+        let mut file = b"CTENFDAM\xff\xff".to_vec();
+        file.extend_from_slice(&(content_key.len() as u32).to_le_bytes());
+        file.extend_from_slice(&content_key);
+        file.extend_from_slice(&[0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff]); // metadata
+        file.extend_from_slice(&[1, 0, 0, 0, 1, 0, 0, 0, 0xff]); // cover
+        file.extend_from_slice(&generate_test_data(0x1000, "ncm default audio"));
+
+        let mut default_keyed = NcmBuilder::new().build().unwrap();
+        let mut explicit = super::new_ncm(&NCM_CORE_KEY);
+        assert_eq!(
+            decrypt_in_chunks(&mut default_keyed, &file, 7),
+            decrypt_in_chunks(&mut explicit, &file, 7)
+        );
+
+        let mut wrong_key = super::new_ncm(b"0123456789abcdef");
+        assert!(wrong_key.write(&file).is_err());
+    }
+
+    #[test]
+    fn test_ncm_end_without_header() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+
+        let mut decryptor = super::new_ncm(test_key[..].try_into().unwrap());
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::EmptyInput));
+
+        let mut decryptor = super::new_ncm(test_key[..].try_into().unwrap());
+        decryptor.write(b"CTENFDAM").unwrap();
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::NotEnoughData));
+    }
+
+    #[test]
+    fn test_ncm_content_key_size_out_of_range() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+
+        let mut decryptor = super::new_ncm(test_key[..].try_into().unwrap());
+        let err = decryptor
+            .write(b"CTENFDAM\xff\xff\xff\xff\xff\xff")
+            .unwrap_err();
+        assert!(matches!(
+            err.code(),
+            DecryptErrorCode::InvalidHeaderField {
+                field: "content_key_size",
+                value: 0xffff_ffff
+            }
+        ));
+    }
+
+    #[test]
+    fn test_ncm_empty_cover_block() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let mut test_data = ncm_test_data()[..0x400].to_vec();
+        // The cover size field, after the key and metadata blocks and the
+        // cover frame size.
+        let cover_size_offset = 10 + 4 + 0x90 + 4 + 3 + 5 + 4;
+        test_data[cover_size_offset..cover_size_offset + 4].fill(0);
+
+        // Rejected alike whether or not the metadata is parsed.
+        let decryptors: [Box<dyn Decryptor>; 2] = [
+            Box::new(super::new_ncm(test_key[..].try_into().unwrap())),
+            Box::new(super::new_ncm_audio_only(test_key[..].try_into().unwrap())),
+        ];
+        for mut decryptor in decryptors {
+            let err = decryptor.write(&test_data).unwrap_err();
+            assert!(matches!(err.code(), DecryptErrorCode::InvalidBlockSize));
+        }
+    }
+
+    #[test]
+    fn test_ncm_cover_larger_than_frame() {
+        let test_key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let mut test_data = ncm_test_data()[..0x400].to_vec();
+        let cover_size_offset = 10 + 4 + 0x90 + 4 + 3 + 5 + 4;
+        test_data[cover_size_offset..cover_size_offset + 4]
+            .copy_from_slice(&0x0100_0000u32.to_le_bytes());
+
+        // Rejected as soon as the size is read, without buffering the cover.
+        let decryptors: [Box<dyn Decryptor>; 2] = [
+            Box::new(super::new_ncm(test_key[..].try_into().unwrap())),
+            Box::new(super::new_ncm_audio_only(test_key[..].try_into().unwrap())),
+        ];
+        for mut decryptor in decryptors {
+            let err = decryptor.write(&test_data).unwrap_err();
+            assert!(matches!(
+                err.code(),
+                DecryptErrorCode::InvalidHeaderField {
+                    field: "cover_size",
+                    value: 0x0100_0000
+                }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_build_ncm_keybox() {
+        // cspell:disable-next-line
+        let key =
+            b"E7fT49x7dof9OKCgg9cdvhEuezy3iZCL1nFvBFd1T4uSktAJKmwZXsijPbijliionVUXXg9plTbXEclAE9Lb";
+        let keybox = super::detail::build_ncm_keybox(key);
+        assert_eq!(
+            keybox[..16],
+            [
+                0x01, 0x62, 0x87, 0x2c, 0x7c, 0x27, 0xef, 0x53, //
+                0xb5, 0x1a, 0xdd, 0x1d, 0xdf, 0xf5, 0x54, 0x90, //
+            ]
+        );
+        assert_eq!(
+            keybox[0xf0..],
+            [
+                0xe8, 0xdd, 0x45, 0x92, 0x8e, 0x6e, 0x54, 0xcd, //
+                0x93, 0x68, 0xf0, 0xb7, 0x9b, 0x26, 0xc4, 0x51, //
+            ]
+        );
+    }
 }
@@ -0,0 +1,158 @@
+mod detail {
+    use crate::{
+        decryptor::{
+            check_invariants, trace_write_span, BaseDecryptorData, DecryptError, DecryptManifest,
+            Decryptor, SeekableDecryptor,
+        },
+        impl_decryptor_inner_helper,
+    };
+
+    /// Copies input to output unchanged.
+    struct PassThrough {
+        data: BaseDecryptorData,
+    }
+
+    impl PassThrough {
+        fn new() -> Self {
+            Self {
+                data: BaseDecryptorData::new("PassThrough"),
+            }
+        }
+    }
+
+    impl SeekableDecryptor for PassThrough {
+        fn decrypt_at(&self, _offset: u64, _buf: &mut [u8]) -> Result<(), DecryptError> {
+            Ok(())
+        }
+    }
+
+    impl Decryptor for PassThrough {
+        impl_decryptor_inner_helper! {}
+
+        fn as_seekable(&self) -> Option<&dyn SeekableDecryptor> {
+            Some(self)
+        }
+
+        fn manifest(&self) -> DecryptManifest {
+            self.data.manifest(None, 0)
+        }
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            self.data.mark_finalized();
+            Ok(())
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            trace_write_span!(self);
+            self.data.ensure_not_finalized()?;
+            let (prev_offset, prev_output_len) = (self.data.offset, self.data.buf_out.len());
+            self.data.buf_out.extend_from_slice(data);
+            self.data.offset += data.len();
+            check_invariants(&self.data, prev_offset, prev_output_len);
+            Ok(())
+        }
+
+        fn reset(&mut self) -> Result<(), DecryptError> {
+            self.data.reset();
+            Ok(())
+        }
+
+        fn write_owned(&mut self, mut chunk: Vec<u8>) -> Result<(), DecryptError> {
+            trace_write_span!(self);
+            self.data.ensure_not_finalized()?;
+            let (prev_offset, prev_output_len) = (self.data.offset, self.data.buf_out.len());
+            self.data.offset += chunk.len();
+            if self.data.buf_out.is_empty() {
+                self.data.buf_out = chunk;
+            } else {
+                self.data.buf_out.append(&mut chunk);
+            }
+            check_invariants(&self.data, prev_offset, prev_output_len);
+            Ok(())
+        }
+    }
+
+    pub fn new_passthrough() -> impl Decryptor {
+        PassThrough::new()
+    }
+}
+
+pub use detail::new_passthrough;
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        adapters::chain,
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::test_util::test::{
+            assert_deterministic_after_reset, decrypt_in_chunks, generate_test_data, TEST_SIZE_1MB,
+        },
+    };
+
+    #[test]
+    fn test_passthrough_deterministic_after_reset() {
+        let test_data = generate_test_data(0x3000, "passthrough reset data");
+        assert_deterministic_after_reset(super::new_passthrough, &test_data);
+    }
+
+    #[test]
+    fn test_passthrough_write_owned() {
+        let test_data = generate_test_data(0x3000, "passthrough owned data");
+
+        let mut decryptor = super::new_passthrough();
+        for chunk in test_data.chunks(0x1000) {
+            decryptor.write_owned(chunk.to_vec()).unwrap();
+        }
+        decryptor.end().unwrap();
+        assert_eq!(decryptor.read_all_output(), test_data);
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let test_data = generate_test_data(TEST_SIZE_1MB, "passthrough data");
+
+        let mut decryptor = super::new_passthrough();
+        assert_eq!(decrypt_in_chunks(&mut decryptor, &test_data, 7), test_data);
+
+        let mut buf = test_data[100..200].to_vec();
+        decryptor.decrypt_range(100..200, &mut buf).unwrap();
+        assert_eq!(buf, &test_data[100..200]);
+    }
+
+    #[test]
+    fn test_passthrough_in_chain() {
+        let test_data = generate_test_data(TEST_SIZE_1MB, "passthrough data");
+
+        let mut decryptor = chain(super::new_passthrough(), super::new_passthrough());
+        assert_eq!(
+            decrypt_in_chunks(&mut decryptor, &test_data, 4099),
+            test_data
+        );
+    }
+
+    #[test]
+    fn test_passthrough_empty_input() {
+        let mut decryptor = super::new_passthrough();
+        decryptor.end().unwrap();
+        assert!(decryptor.read_all_output().is_empty());
+    }
+
+    #[test]
+    fn test_passthrough_write_after_end() {
+        let mut decryptor = super::new_passthrough();
+        decryptor.write(b"data").unwrap();
+        decryptor.end().unwrap();
+
+        for err in [
+            decryptor.write(b"more").unwrap_err(),
+            decryptor.write_owned(b"more".to_vec()).unwrap_err(),
+        ] {
+            assert!(matches!(err.code(), DecryptErrorCode::WriteAfterFinalize));
+        }
+        assert_eq!(decryptor.read_all_output(), b"data");
+
+        decryptor.reset().unwrap();
+        decryptor.write(b"again").unwrap();
+        assert_eq!(decryptor.read_all_output(), b"again");
+    }
+}
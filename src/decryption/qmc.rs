@@ -1,23 +1,233 @@
-use super::{qmc_v1, qmc_v2};
-use crate::decryptor::Decryptor;
+use super::{qmc_v1, qmc_v2, AudioFormat};
+use crate::{
+    decryptor::{DecryptError, DecryptErrorCode, Decryptor},
+    keys::{KeyResolver, ResolveContext},
+    tencent::qmc_footer,
+};
 
+/// Keys at least this long use the RC4 cipher, shorter ones the map cipher.
+const RC4_KEY_MIN_LEN: usize = 300;
+
+fn new_qmc_v2_cipher(key: Vec<u8>, footer_len: usize) -> Result<Box<dyn Decryptor>, DecryptError> {
+    let decryptor: Box<dyn Decryptor> = if key.len() < RC4_KEY_MIN_LEN {
+        Box::new(qmc_v1::new_qmc_v1_map(key, footer_len))
+    } else {
+        Box::new(qmc_v2::new_qmc_v2_rc4(key, footer_len))
+    };
+    decryptor.validate_config()?;
+    Ok(decryptor)
+}
+
+/// Create a QMCv2 decryptor from the end of the file holding its `footer`.
+/// The whole file, footer included, is then written to the decryptor; the
+/// footer is left out of the output. `None` if the footer carries no usable
+/// key.
 pub fn new_qmc_v2<T: AsRef<[u8]>>(footer: T) -> Option<Box<dyn Decryptor>> {
-    let parsed = crate::tencent::qmc_footer::parse(footer)?;
-    let key_len = parsed.key.len();
+    let parsed = qmc_footer::parse(footer)?;
 
-    if key_len == 0 {
+    if parsed.key.is_empty() {
         return None;
     }
 
-    if parsed.key.len() < 300 {
-        Some(Box::new(qmc_v1::new_qmc_v1_map(
-            parsed.key,
-            parsed.eof_bytes_ignore,
-        )))
-    } else {
-        Some(Box::new(qmc_v2::new_qmc_v2_rc4(
-            parsed.key,
-            parsed.eof_bytes_ignore,
-        )))
+    new_qmc_v2_cipher(parsed.key, parsed.eof_bytes_ignore).ok()
+}
+
+/// Like [`new_qmc_v2`], asking `resolver` for the key when the footer does not
+/// carry one (`STag`).
+pub fn new_qmc_v2_with_resolver<T: AsRef<[u8]>>(
+    footer: T,
+    resolver: &dyn KeyResolver,
+) -> Result<Box<dyn Decryptor>, DecryptError> {
+    let footer = footer.as_ref();
+    if let Some(parsed) = qmc_footer::parse(footer).filter(|parsed| !parsed.key.is_empty()) {
+        return new_qmc_v2_cipher(parsed.key, parsed.eof_bytes_ignore);
+    }
+
+    let stag = qmc_footer::parse_stag(footer).ok_or_else(|| {
+        DecryptError::new(DecryptErrorCode::UnknownEncryption, "unknown qmc v2 footer")
+    })?;
+    let ctx = ResolveContext {
+        file_id: stag.song_id,
+        resource_id: Some(stag.song_mid),
+    };
+    let key = resolver.resolve(AudioFormat::QmcV2, &ctx)?;
+    if key.is_empty() {
+        return Err(DecryptError::new(
+            DecryptErrorCode::InvalidKeyLength,
+            "qmc v2 key is empty",
+        ));
+    }
+
+    new_qmc_v2_cipher(key, stag.eof_bytes_ignore)
+}
+
+/// Create a QMCv2 decryptor from an already decoded file key, picking the
+/// cipher from the key length. The input must not include the footer.
+pub fn new_qmc_v2_with_key(key: Vec<u8>) -> Result<Box<dyn Decryptor>, DecryptError> {
+    if key.is_empty() {
+        return Err(DecryptError::new(
+            DecryptErrorCode::InvalidKeyLength,
+            "qmc v2 key is empty",
+        ));
+    }
+
+    new_qmc_v2_cipher(key, 0)
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use super::{new_qmc_v2_with_key, new_qmc_v2_with_resolver};
+    use crate::{
+        decryption::{qmc_v1::new_qmc_v1_map, qmc_v2::new_qmc_v2_rc4, AudioFormat},
+        decryptor::{DecryptError, DecryptErrorCode, Decryptor},
+        keys::{KeyResolver, ResolveContext},
+        utils::test_util::test::{
+            decrypt_in_chunks, decrypt_test_content, generate_test_data, TEST_SIZE_4MB,
+        },
+    };
+
+    #[test]
+    fn test_qmc_v2_with_short_key_uses_map() {
+        let key = generate_test_data(128, "qmcv2 short key");
+        let test_data = generate_test_data(0x10000, "qmcv2 short key data");
+        let expected = decrypt_in_chunks(&mut new_qmc_v1_map(&key, 0), &test_data, 0);
+
+        let mut decryptor = new_qmc_v2_with_key(key).unwrap();
+        assert_eq!(decryptor.get_name(), "QMCv1");
+        assert_eq!(
+            decrypt_in_chunks(decryptor.as_mut(), &test_data, 0x1000),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_qmc_v2_with_long_key_uses_rc4() {
+        // Same vector as `qmc_v2::test::test_qmc_v2_rc4`.
+        let mut key = generate_test_data(512, "qmcv2 rc4 cipher key");
+        key[0..8].fill(b'4');
+        let test_data = generate_test_data(TEST_SIZE_4MB, "qmcv2 rc4 cipher data");
+
+        let mut decryptor = new_qmc_v2_with_key(key).unwrap();
+        assert_eq!(decryptor.get_name(), "QMCv2(RC4)");
+        assert_eq!(
+            decrypt_test_content(decryptor.as_mut(), test_data),
+            "757fc9aa94ab48295b106a16452b7da7b90395be8e3132a077b6d2a9ea216838"
+        );
+    }
+
+    #[test]
+    fn test_qmc_v2_with_short_rc4_key() {
+        let err = new_qmc_v2_with_key(vec![7; 300]).err().unwrap();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidKeyLength));
+
+        let resolver = MockResolver {
+            song_id: "12345",
+            key: vec![7; 300],
+            requests: RefCell::new(vec![]),
+        };
+        let footer = b"12345,004abcdE,2\x00\x00\x00\x18STag";
+        let err = new_qmc_v2_with_resolver(footer, &resolver).err().unwrap();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidKeyLength));
+    }
+
+    #[test]
+    fn test_qmc_v2_with_empty_key() {
+        let err = new_qmc_v2_with_key(vec![]).err().unwrap();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidKeyLength));
+    }
+
+    #[test]
+    fn test_qmc_v2_footer_stripped() {
+        let footer = b"ekey,songid,2\x00\x00\x00\x18QTag";
+        let audio = generate_test_data(0x8000, "qmcv2 footer audio");
+        let mut file = audio.clone();
+        file.extend_from_slice(footer);
+
+        for key_len in [128, 512] {
+            let key = generate_test_data(key_len, "qmcv2 footer key");
+            let new_decryptor = |footer_len| -> Box<dyn Decryptor> {
+                match key_len {
+                    128 => Box::new(new_qmc_v1_map(&key, footer_len)),
+                    _ => Box::new(new_qmc_v2_rc4(&key, footer_len)),
+                }
+            };
+            let expected = decrypt_in_chunks(new_decryptor(0).as_mut(), &audio, 0);
+
+            let mut decryptor = new_decryptor(footer.len());
+            assert_eq!(decryptor.get_eof_reserve(), 0);
+            // Small chunks, so the footer is held back across many writes.
+            let result = decrypt_in_chunks(decryptor.as_mut(), &file, 7);
+            assert_eq!(result, expected);
+        }
+    }
+
+    /// Serves a fixed key for one song, recording the requests.
+    struct MockResolver {
+        song_id: &'static str,
+        key: Vec<u8>,
+        requests: RefCell<Vec<(AudioFormat, ResolveContext)>>,
+    }
+
+    impl KeyResolver for MockResolver {
+        fn resolve(
+            &self,
+            format: AudioFormat,
+            ctx: &ResolveContext,
+        ) -> Result<Vec<u8>, DecryptError> {
+            self.requests.borrow_mut().push((format, ctx.clone()));
+            if ctx.file_id == self.song_id {
+                Ok(self.key.clone())
+            } else {
+                Err(DecryptError::new(
+                    DecryptErrorCode::MissingKey,
+                    "unknown song",
+                ))
+            }
+        }
+    }
+
+    #[test]
+    fn test_qmc_v2_stag_key_from_resolver() {
+        let resolver = MockResolver {
+            song_id: "12345",
+            key: generate_test_data(512, "qmcv2 resolver key"),
+            requests: RefCell::new(vec![]),
+        };
+        let audio = generate_test_data(0x8000, "qmcv2 resolver audio");
+        let expected = decrypt_in_chunks(
+            new_qmc_v2_with_key(resolver.key.clone()).unwrap().as_mut(),
+            &audio,
+            0,
+        );
+
+        let footer = b"12345,004abcdE,2\x00\x00\x00\x18STag";
+        let file = [&audio[..], footer].concat();
+        let mut decryptor =
+            new_qmc_v2_with_resolver(&file[file.len() - 0x40..], &resolver).unwrap();
+        assert_eq!(
+            decrypt_in_chunks(decryptor.as_mut(), &file, 0x1000),
+            expected
+        );
+        assert_eq!(
+            resolver.requests.borrow()[..],
+            [(
+                AudioFormat::QmcV2,
+                ResolveContext {
+                    file_id: "12345".to_string(),
+                    resource_id: Some("004abcdE".to_string()),
+                }
+            )]
+        );
+
+        let err = new_qmc_v2_with_resolver(b"54321,004abcdE,2\x00\x00\x00\x18STag", &resolver)
+            .err()
+            .unwrap();
+        assert!(matches!(err.code(), DecryptErrorCode::MissingKey));
+        let err = new_qmc_v2_with_resolver([0u8; 0x40], &resolver)
+            .err()
+            .unwrap();
+        assert!(matches!(err.code(), DecryptErrorCode::UnknownEncryption));
     }
 }
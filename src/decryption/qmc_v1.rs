@@ -1,6 +1,9 @@
 mod detail {
     use crate::{
-        decryptor::{BaseDecryptorData, DecryptError, Decryptor},
+        decryptor::{
+            check_invariants, trace_write_span, write_holding_back_footer, BaseDecryptorData,
+            DecryptError, DecryptErrorCode, DecryptManifest, Decryptor, SeekableDecryptor,
+        },
         impl_decryptor_inner_helper,
         utils::array_ext::{ArrayExtension, ByteSliceExt, VecExtension},
     };
@@ -42,6 +45,7 @@ mod detail {
 
     struct QMCv1<T: QmcV1Algo> {
         data: BaseDecryptorData,
+        key: Vec<u8>,
         extra_cache_value: u8,
         cache: [u8; STATIC_CIPHER_PAGE_SIZE],
         _algo: T,
@@ -49,15 +53,19 @@ mod detail {
 
     impl<T: QmcV1Algo> QMCv1<T> {
         #[inline(always)]
-        pub fn new<K: AsRef<[u8]>>(key: K, eof_reserve: usize) -> Self {
+        pub fn new<K: AsRef<[u8]>>(key: K, footer_len: usize) -> Self {
             let mut result = Self {
-                data: BaseDecryptorData::new_with_eof_reserve("QMCv1", eof_reserve),
+                data: BaseDecryptorData::new_with_footer("QMCv1", footer_len),
+                key: Vec::from(key.as_ref()),
                 extra_cache_value: 0,
                 cache: [0u8; STATIC_CIPHER_PAGE_SIZE],
                 _algo: T::new(),
             };
 
-            result.init_cache(key.as_ref());
+            // An empty key is reported by `validate_config`.
+            if !result.key.is_empty() {
+                result.init_cache(key.as_ref());
+            }
             result
         }
 
@@ -68,23 +76,84 @@ mod detail {
             }
             self.extra_cache_value = self.cache[0] ^ T::get_mask(key, STATIC_CIPHER_PAGE_SIZE);
         }
+
+        fn write_body(&mut self, data: &[u8]) {
+            let offset = self.data.offset;
+            self.data.offset += data.len();
+
+            let buf = self.data.buf_out.append_data(data);
+            Self::decrypt_buffer(&self.cache, self.extra_cache_value, buf, offset);
+        }
+
+        #[inline(always)]
+        fn decrypt_buffer(
+            cache: &[u8; STATIC_CIPHER_PAGE_SIZE],
+            extra_cache_value: u8,
+            buf: &mut [u8],
+            offset: usize,
+        ) {
+            let new_offset = offset + buf.len();
+            buf.xor_key_with_key_offset(cache.as_slice(), offset);
+
+            if offset <= STATIC_CIPHER_PAGE_SIZE && STATIC_CIPHER_PAGE_SIZE < new_offset {
+                buf[STATIC_CIPHER_PAGE_SIZE - offset] ^= extra_cache_value;
+            }
+        }
+    }
+
+    impl<T: QmcV1Algo> SeekableDecryptor for QMCv1<T> {
+        fn decrypt_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), DecryptError> {
+            let offset = usize::try_from(offset)
+                .ok()
+                .filter(|offset| offset.checked_add(buf.len()).is_some())
+                .ok_or_else(|| {
+                    DecryptError::new(
+                        DecryptErrorCode::RangeNotSupported,
+                        &format!("offset {} out of range", offset),
+                    )
+                })?;
+            Self::decrypt_buffer(&self.cache, self.extra_cache_value, buf, offset);
+            Ok(())
+        }
     }
 
     impl<T: QmcV1Algo> Decryptor for QMCv1<T> {
         impl_decryptor_inner_helper! {}
 
-        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
-            let n = data.len();
-            let offset = self.data.offset;
-            let new_offset = offset + n;
+        fn as_seekable(&self) -> Option<&dyn SeekableDecryptor> {
+            Some(self)
+        }
 
-            let buf = self.data.buf_out.append_data(data);
-            buf.xor_key_with_key_offset(&self.cache, offset);
+        fn manifest(&self) -> DecryptManifest {
+            self.data.manifest(Some(&self.key), 0)
+        }
 
-            if offset <= STATIC_CIPHER_PAGE_SIZE && STATIC_CIPHER_PAGE_SIZE < new_offset {
-                buf[STATIC_CIPHER_PAGE_SIZE - offset] ^= self.extra_cache_value;
+        fn validate_config(&self) -> Result<(), DecryptError> {
+            if self.key.is_empty() {
+                return Err(DecryptError::new(
+                    DecryptErrorCode::InvalidKeyLength,
+                    "qmc v1 key is empty",
+                ));
             }
-            self.data.offset = new_offset;
+            Ok(())
+        }
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            self.data.mark_finalized();
+            Ok(())
+        }
+
+        fn reset(&mut self) -> Result<(), DecryptError> {
+            self.data.reset();
+            Ok(())
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            trace_write_span!(self);
+            self.data.ensure_not_finalized()?;
+            let (prev_offset, prev_output_len) = (self.data.offset, self.data.buf_out.len());
+            write_holding_back_footer(self, data, |this| &mut this.data, Self::write_body);
+            check_invariants(&self.data, prev_offset, prev_output_len);
             Ok(())
         }
     }
@@ -93,8 +162,9 @@ mod detail {
         QMCv1::<QmcV1StaticAlgo<80923>>::new(key, 0)
     }
 
-    pub fn new_qmc_v1_map<T: AsRef<[u8]>>(key: T, eof_reserve: usize) -> impl Decryptor {
-        QMCv1::<QmcV2MapAlgo<71214>>::new(key, eof_reserve)
+    /// `footer_len` trailing bytes of the input are a footer, and not decrypted.
+    pub fn new_qmc_v1_map<T: AsRef<[u8]>>(key: T, footer_len: usize) -> impl Decryptor {
+        QMCv1::<QmcV2MapAlgo<71214>>::new(key, footer_len)
     }
 }
 
@@ -103,7 +173,13 @@ pub use detail::new_qmc_v1_static;
 
 #[cfg(test)]
 mod test {
-    use crate::utils::test_util::test::{decrypt_test_content, generate_test_data, TEST_SIZE_4MB};
+    use crate::{
+        decryptor::{DecryptErrorCode, Decryptor},
+        utils::test_util::test::{
+            assert_deterministic_after_reset, decrypt_in_chunks, decrypt_test_content,
+            generate_test_data, TEST_SIZE_4MB,
+        },
+    };
 
     #[test]
     fn test_qmc_v1_static() {
@@ -118,6 +194,114 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_qmc_v1_reset() {
+        let test_key = generate_test_data(256, "qmcv1 static key");
+        let test_data = generate_test_data(0x9000, "qmcv1 reset data");
+
+        assert_deterministic_after_reset(
+            || super::new_qmc_v1_static(test_key.as_slice()),
+            &test_data,
+        );
+        assert_deterministic_after_reset(
+            || super::new_qmc_v1_map(test_key.as_slice(), 0x10),
+            &test_data,
+        );
+    }
+
+    #[test]
+    fn test_qmc_v1_manifest() {
+        use crate::decryptor::key_fingerprint;
+
+        let test_key = generate_test_data(256, "qmcv1 static key");
+        let mut decryptor = super::new_qmc_v1_map(test_key.as_slice(), 0);
+        decrypt_in_chunks(&mut decryptor, &[0u8; 0x100], 0);
+        let manifest = decryptor.manifest();
+        assert_eq!(manifest.format, "QMCv1");
+        assert_eq!(manifest.key_fingerprint, Some(key_fingerprint(&test_key)));
+        assert_eq!(manifest.bytes_in, 0x100);
+    }
+
+    #[test]
+    fn test_qmc_v1_validate_config() {
+        let test_key = generate_test_data(256, "qmcv1 static key");
+        assert!(super::new_qmc_v1_static(test_key.as_slice())
+            .validate_config()
+            .is_ok());
+
+        for decryptor in [
+            super::new_qmc_v1_map([], 0).validate_config(),
+            super::new_qmc_v1_static(&[]).validate_config(),
+        ] {
+            let err = decryptor.unwrap_err();
+            assert!(matches!(err.code(), DecryptErrorCode::InvalidKeyLength));
+        }
+    }
+
+    #[test]
+    fn test_qmc_v1_decrypt_at_out_of_range() {
+        let test_key = generate_test_data(256, "qmcv1 static key");
+        let decryptor = super::new_qmc_v1_static(test_key.as_slice());
+        let seekable = decryptor.as_seekable().unwrap();
+
+        let mut buf = [0u8; 0x10];
+        let err = seekable.decrypt_at(u64::MAX - 4, &mut buf).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::RangeNotSupported));
+        assert_eq!(buf, [0u8; 0x10]);
+    }
+
+    #[test]
+    fn test_qmc_v1_key_longer_than_file() {
+        let test_key = generate_test_data(256, "qmcv1 static key");
+        let plain = [0x11u8, 0x22, 0x33];
+        let expected: Vec<u8> = (0..plain.len())
+            .map(|i| plain[i] ^ test_key[(i * i + 80923) % test_key.len()])
+            .collect();
+
+        let mut decryptor = super::new_qmc_v1_static(test_key.as_slice());
+        assert_eq!(decrypt_in_chunks(&mut decryptor, &plain, 1), expected);
+
+        let mut decryptor = super::new_qmc_v1_static(test_key.as_slice());
+        assert_eq!(decrypt_in_chunks(&mut decryptor, &[], 1), [0u8; 0]);
+    }
+
+    #[test]
+    fn test_qmc_v1_page_boundary() {
+        let test_key = generate_test_data(256, "qmcv1 static key");
+        let test_data = generate_test_data(0x9000, "qmcv1 boundary data");
+        // Reference: the index wraps modulo 0x7fff above 0x7fff, so 0x7fff
+        // itself keeps its own mask and 0x8000 reuses the mask of 1.
+        let expected: Vec<u8> = (0..test_data.len())
+            .map(|offset| {
+                let i = if offset > 0x7fff {
+                    offset % 0x7fff
+                } else {
+                    offset
+                };
+                test_data[offset] ^ test_key[(i * i + 80923) % test_key.len()]
+            })
+            .collect();
+
+        let mut decryptor = super::new_qmc_v1_static(test_key.as_slice());
+        assert_eq!(decrypt_in_chunks(&mut decryptor, &test_data, 0), expected);
+        for chunk_size in [1, 7, 0x100, 0x7fff, 0x8000] {
+            let mut decryptor = super::new_qmc_v1_static(test_key.as_slice());
+            assert_eq!(
+                decrypt_in_chunks(&mut decryptor, &test_data, chunk_size),
+                expected
+            );
+        }
+
+        // Writes ending and starting right at the boundary bytes.
+        let mut decryptor = super::new_qmc_v1_static(test_key.as_slice());
+        let mut result = vec![];
+        for split in [0..0x7ffe, 0x7ffe..0x7fff, 0x7fff..0x8000, 0x8000..0x9000] {
+            decryptor.write(&test_data[split]).unwrap();
+            result.extend(decryptor.read_all_output());
+        }
+        assert_eq!(result, expected);
+    }
+
     #[test]
     fn test_qmc_v2_map() {
         let test_key = generate_test_data(256, "qmcv1 map cipher derived key");
@@ -130,4 +314,22 @@ mod test {
             "ce84e9ac24ef5b2f02a11f74ffa8eb7008fe2898855617596c5ee217139fc214"
         );
     }
+
+    #[test]
+    fn test_qmc_v1_decrypt_range() {
+        let test_key = generate_test_data(256, "qmcv1 static key");
+        let test_data = generate_test_data(0x10000, "qmcv1 static data");
+
+        let mut decryptor = super::new_qmc_v1_static(test_key.as_slice());
+        decryptor.write(&test_data).unwrap();
+        let expected = decryptor.read_all_output();
+
+        for range in [0..0x100, 0x7f00..0x8100, 0x7fff..0x8000, 0x8000..0xffff] {
+            let mut buf = Vec::from(&test_data[range.clone()]);
+            decryptor
+                .decrypt_range(range.start as u64..range.end as u64, &mut buf)
+                .unwrap();
+            assert_eq!(buf, &expected[range]);
+        }
+    }
 }
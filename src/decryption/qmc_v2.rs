@@ -1,9 +1,12 @@
 mod detail {
     use crate::{
-        decryptor::{BaseDecryptorData, DecryptError, Decryptor},
+        decryptor::{
+            check_invariants, trace_write_span, transition_state, write_holding_back_footer,
+            BaseDecryptorData, DecryptError, DecryptErrorCode, DecryptManifest, Decryptor,
+        },
         impl_decryptor_inner_helper,
         utils::{
-            array_ext::ArrayExtension,
+            array_ext::{ArrayExtension, ByteSliceExt},
             rc4::{RC4Derive, RC4TencentQmcV2},
         },
     };
@@ -11,7 +14,10 @@ mod detail {
 
     const FIRST_SEGMENT_SIZE: usize = 0x0080;
     const OTHER_SEGMENT_SIZE: usize = 0x1400;
+    /// Each segment seeds its RC4 discard count from `key[segment_id & 0x1FF]`.
+    const MIN_KEY_LEN: usize = 0x200;
 
+    #[derive(Debug)]
     enum State {
         DecryptFirstSegment,
         DecryptOtherSegment,
@@ -24,19 +30,21 @@ mod detail {
         key: Vec<u8>,
         key_hash: u32,
         rc4: RC4TencentQmcV2,
+        first_segment_mask: Option<[u8; FIRST_SEGMENT_SIZE]>,
 
         segment_id: usize,
         segment_bytes_left: usize,
     }
 
     impl QMCv2 {
-        pub fn new<T: AsRef<[u8]>>(key: T, reserved_eof: usize) -> Self {
+        pub fn new<T: AsRef<[u8]>>(key: T, footer_len: usize) -> Self {
             Self {
-                data: BaseDecryptorData::new_with_eof_reserve("QMCv2(RC4)", reserved_eof),
+                data: BaseDecryptorData::new_with_footer("QMCv2(RC4)", footer_len),
                 state: State::DecryptFirstSegment,
                 key: Vec::from(key.as_ref()),
                 key_hash: Self::calculate_key_hash(key.as_ref()),
                 rc4: RC4TencentQmcV2::new(key.as_ref()),
+                first_segment_mask: None,
 
                 segment_bytes_left: 0,
                 segment_id: 0,
@@ -74,15 +82,25 @@ mod detail {
             }
         }
 
+        fn build_first_segment_mask(&self) -> [u8; FIRST_SEGMENT_SIZE] {
+            let mut mask = [0u8; FIRST_SEGMENT_SIZE];
+            for (i, v) in mask.iter_mut().enumerate() {
+                let seed = self.key.get_mod_n(i) as u64;
+                let key_idx = self.get_segment_key(i as u64, seed);
+                *v = self.key.get_mod_n(key_idx);
+            }
+            mask
+        }
+
         #[inline(always)]
         fn decrypt_first_segment(&mut self) {
             let mut output = self.data.consume_input(FIRST_SEGMENT_SIZE);
 
-            for (i, v) in output.iter_mut().enumerate() {
-                let seed = self.key.get_mod_n(i) as u64;
-                let key_idx = self.get_segment_key(i as u64, seed);
-                *v ^= self.key.get_mod_n(key_idx);
-            }
+            let mask = match self.first_segment_mask.take() {
+                Some(mask) => mask,
+                None => self.build_first_segment_mask(),
+            };
+            output.xor_key(mask);
             self.data.buf_out.append(&mut output);
             self.reset_other_segment_rc4(FIRST_SEGMENT_SIZE);
         }
@@ -112,6 +130,23 @@ mod detail {
             self.data.buf_out.append(&mut output);
         }
 
+        fn write_body(&mut self, mut p: &[u8]) {
+            while !p.is_empty() {
+                match self.state {
+                    State::DecryptFirstSegment => {
+                        if self.data.read_until_offset(&mut p, FIRST_SEGMENT_SIZE) {
+                            self.decrypt_first_segment();
+                            transition_state!(self, State::DecryptOtherSegment);
+                        }
+                    }
+                    State::DecryptOtherSegment => {
+                        self.decrypt_other_segment_data(p);
+                        break;
+                    }
+                }
+            }
+        }
+
         #[inline(always)]
         fn reset_other_segment_rc4(&mut self, skip: usize) {
             self.segment_bytes_left = OTHER_SEGMENT_SIZE - skip;
@@ -128,30 +163,55 @@ mod detail {
     impl Decryptor for QMCv2 {
         impl_decryptor_inner_helper! {}
 
-        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
-            let mut p = data;
+        fn manifest(&self) -> DecryptManifest {
+            self.data.manifest(Some(&self.key), 0)
+        }
 
-            while !p.is_empty() {
-                match self.state {
-                    State::DecryptFirstSegment => {
-                        if self.data.read_until_offset(&mut p, FIRST_SEGMENT_SIZE) {
-                            self.decrypt_first_segment();
-                            self.state = State::DecryptOtherSegment;
-                        }
-                    }
-                    State::DecryptOtherSegment => {
-                        self.decrypt_other_segment_data(p);
-                        return Ok(());
-                    }
+        fn validate_config(&self) -> Result<(), DecryptError> {
+            if self.key.len() < MIN_KEY_LEN {
+                return Err(DecryptError::new(
+                    DecryptErrorCode::InvalidKeyLength,
+                    "qmc v2 rc4 key is shorter than 512 bytes",
+                ));
+            }
+            Ok(())
+        }
+
+        fn warmup(&mut self) {
+            if let State::DecryptFirstSegment = self.state {
+                if self.first_segment_mask.is_none() {
+                    self.first_segment_mask = Some(self.build_first_segment_mask());
                 }
             }
+        }
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            self.data.mark_finalized();
+            Ok(())
+        }
+
+        fn reset(&mut self) -> Result<(), DecryptError> {
+            self.data.reset();
+            self.state = State::DecryptFirstSegment;
+            self.rc4.reset();
+            self.segment_id = 0;
+            self.segment_bytes_left = 0;
+            Ok(())
+        }
 
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            trace_write_span!(self, state);
+            self.data.ensure_not_finalized()?;
+            let (prev_offset, prev_output_len) = (self.data.offset, self.data.buf_out.len());
+            write_holding_back_footer(self, data, |this| &mut this.data, Self::write_body);
+            check_invariants(&self.data, prev_offset, prev_output_len);
             Ok(())
         }
     }
 
-    pub fn new_qmc_v2_rc4<T: AsRef<[u8]>>(key: T, reserved_eof: usize) -> impl Decryptor {
-        QMCv2::new(key, reserved_eof)
+    /// `footer_len` trailing bytes of the input are a footer, and not decrypted.
+    pub fn new_qmc_v2_rc4<T: AsRef<[u8]>>(key: T, footer_len: usize) -> impl Decryptor {
+        QMCv2::new(key, footer_len)
     }
 }
 
@@ -159,7 +219,13 @@ pub use detail::new_qmc_v2_rc4;
 
 #[cfg(test)]
 mod test {
-    use crate::utils::test_util::test::{decrypt_test_content, generate_test_data, TEST_SIZE_4MB};
+    use crate::{
+        decryptor::Decryptor,
+        utils::test_util::test::{
+            assert_deterministic_after_reset, decrypt_in_chunks, decrypt_test_content,
+            generate_test_data, TEST_SIZE_1MB, TEST_SIZE_4MB,
+        },
+    };
 
     #[test]
     fn test_qmc_v2_rc4() {
@@ -174,4 +240,55 @@ mod test {
             "757fc9aa94ab48295b106a16452b7da7b90395be8e3132a077b6d2a9ea216838"
         );
     }
+
+    #[test]
+    fn test_qmc_v2_rc4_warmup() {
+        let mut test_key = generate_test_data(512, "qmcv2 rc4 cipher key");
+        let test_data = generate_test_data(TEST_SIZE_1MB, "qmcv2 rc4 cipher data");
+        test_key[0..8].fill(b'4');
+
+        let mut cold = super::new_qmc_v2_rc4(&test_key, 0);
+        let mut warm = super::new_qmc_v2_rc4(&test_key, 0);
+        warm.warmup();
+
+        assert_eq!(
+            decrypt_in_chunks(&mut warm, &test_data, 7),
+            decrypt_in_chunks(&mut cold, &test_data, 7)
+        );
+    }
+
+    #[test]
+    fn test_qmc_v2_rc4_reset() {
+        let test_key = generate_test_data(512, "qmcv2 rc4 cipher key");
+        let test_data = generate_test_data(0x4000, "qmcv2 rc4 cipher data");
+
+        assert_deterministic_after_reset(|| super::new_qmc_v2_rc4(&test_key, 0), &test_data);
+    }
+
+    #[test]
+    fn test_qmc_v2_rc4_manifest() {
+        use crate::decryptor::key_fingerprint;
+
+        let test_key = generate_test_data(512, "qmcv2 rc4 cipher key");
+        let decryptor = super::new_qmc_v2_rc4(&test_key, 0);
+        let manifest = decryptor.manifest();
+        assert_eq!(manifest.format, "QMCv2(RC4)");
+        assert_eq!(manifest.key_fingerprint, Some(key_fingerprint(&test_key)));
+        assert_eq!(manifest.header_len, 0);
+    }
+
+    #[test]
+    fn test_qmc_v2_rc4_output_ready() {
+        let test_key = generate_test_data(512, "qmcv2 rc4 cipher key");
+        let test_data = generate_test_data(0x100, "qmcv2 rc4 cipher data");
+
+        // The first segment (0x80 bytes) is only emitted once complete.
+        let mut decryptor = super::new_qmc_v2_rc4(&test_key, 0);
+        decryptor.write(&test_data[..0x40]).unwrap();
+        assert_eq!(decryptor.output_ready(), 0);
+        decryptor.write(&test_data[0x40..0x90]).unwrap();
+        assert_eq!(decryptor.output_ready(), 0x90);
+        assert_eq!(decryptor.read_all_output().len(), 0x90);
+        assert_eq!(decryptor.output_ready(), 0);
+    }
 }
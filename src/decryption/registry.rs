@@ -0,0 +1,323 @@
+use super::{new_passthrough, AudioFormat};
+use crate::{
+    decryptor::{DecryptError, DecryptErrorCode, Decryptor},
+    keys::{KeyFormat, KeyId, KeyProvider, RequiredKey},
+};
+
+const JOOX_KEYS: &[RequiredKey] = &[RequiredKey::new(KeyId::JooxUuid, KeyFormat::Text)];
+
+const KGM_KEYS: &[RequiredKey] = &[
+    RequiredKey::new(KeyId::KugouT1, KeyFormat::Bytes(17 * 16)),
+    RequiredKey::new(KeyId::KugouT2, KeyFormat::Bytes(17 * 16)),
+    RequiredKey::new(KeyId::KugouV2, KeyFormat::Bytes(17 * 16)),
+];
+
+const VPR_KEYS: &[RequiredKey] = &[
+    KGM_KEYS[0],
+    KGM_KEYS[1],
+    KGM_KEYS[2],
+    RequiredKey::new(KeyId::KugouVprKey, KeyFormat::Bytes(17)),
+];
+
+const KWM_KEYS: &[RequiredKey] = &[RequiredKey::new(KeyId::KuwoKey, KeyFormat::Bytes(0x20))];
+
+const NCM_KEYS: &[RequiredKey] = &[RequiredKey::new(KeyId::NcmCoreKey, KeyFormat::Bytes(16))];
+
+const QMC_V1_KEYS: &[RequiredKey] = &[RequiredKey::new(
+    KeyId::QmcStaticKey,
+    KeyFormat::MinBytes(1),
+)];
+
+/// The scramble table holds 1024 `u16` entries.
+const XIMALAYA_SCRAMBLE_SEED: RequiredKey =
+    RequiredKey::new(KeyId::XimalayaScrambleSeed, KeyFormat::MinBytes(2048));
+
+const X2M_KEYS: &[RequiredKey] = &[
+    RequiredKey::new(KeyId::XimalayaX2mKey, KeyFormat::Bytes(4)),
+    XIMALAYA_SCRAMBLE_SEED,
+];
+
+const X3M_KEYS: &[RequiredKey] = &[
+    RequiredKey::new(KeyId::XimalayaX3mKey, KeyFormat::Bytes(0x20)),
+    XIMALAYA_SCRAMBLE_SEED,
+];
+
+/// Keys [`new_decryptor`] looks up for `format`, e.g. to prompt the user for
+/// them. Formats without key material return an empty list.
+///
+/// The list does not depend on which formats are compiled in.
+pub fn required_keys(format: AudioFormat) -> &'static [RequiredKey] {
+    match format {
+        AudioFormat::Joox => JOOX_KEYS,
+        AudioFormat::Kgm => KGM_KEYS,
+        AudioFormat::Vpr => VPR_KEYS,
+        AudioFormat::Kwm => KWM_KEYS,
+        AudioFormat::Ncm => NCM_KEYS,
+        AudioFormat::QmcV1 => QMC_V1_KEYS,
+        AudioFormat::X2m => X2M_KEYS,
+        AudioFormat::X3m => X3M_KEYS,
+        // The key comes from the file footer (or a `KeyResolver`).
+        AudioFormat::QmcV2 | AudioFormat::Xiami | AudioFormat::Plain(_) => &[],
+    }
+}
+
+#[allow(dead_code)] // Unused with only keyless formats enabled.
+fn require_key(keys: &dyn KeyProvider, id: KeyId) -> Result<Vec<u8>, DecryptError> {
+    keys.get_key(id).ok_or_else(|| {
+        DecryptError::new(
+            DecryptErrorCode::MissingKey,
+            &format!("missing key {}", id.env_name()),
+        )
+    })
+}
+
+#[allow(dead_code)]
+fn require_key_array<const N: usize>(
+    keys: &dyn KeyProvider,
+    id: KeyId,
+) -> Result<[u8; N], DecryptError> {
+    require_key(keys, id)?.try_into().map_err(|_| {
+        DecryptError::new(
+            DecryptErrorCode::InvalidKeyLength,
+            &format!("key {} should be {} bytes", id.env_name(), N),
+        )
+    })
+}
+
+fn not_compiled_in(format: AudioFormat) -> DecryptError {
+    DecryptError::new(
+        DecryptErrorCode::UnknownEncryption,
+        &format!("support for {:?} is not compiled in", format),
+    )
+}
+
+/// Create a decryptor for `format`, taking its keys from `keys`.
+#[cfg_attr(
+    not(any(
+        feature = "joox",
+        feature = "kugou",
+        feature = "kuwo",
+        feature = "ncm",
+        feature = "qmc",
+        feature = "ximalaya"
+    )),
+    allow(unused_variables)
+)]
+pub fn new_decryptor(
+    format: AudioFormat,
+    keys: &dyn KeyProvider,
+) -> Result<Box<dyn Decryptor>, DecryptError> {
+    match format {
+        #[cfg(feature = "joox")]
+        AudioFormat::Joox => {
+            let uuid = String::from_utf8(require_key(keys, KeyId::JooxUuid)?).map_err(|_| {
+                DecryptError::new(DecryptErrorCode::InvalidKeyEncoding, "uuid is not utf-8")
+            })?;
+            Ok(Box::new(super::joox::new_joox(uuid)))
+        }
+
+        #[cfg(feature = "kugou")]
+        AudioFormat::Kgm => Ok(Box::new(super::new_kgm(
+            &require_key_array(keys, KeyId::KugouT1)?,
+            &require_key_array(keys, KeyId::KugouT2)?,
+            &require_key_array(keys, KeyId::KugouV2)?,
+        ))),
+
+        #[cfg(feature = "kugou")]
+        AudioFormat::Vpr => Ok(Box::new(super::new_vpr(
+            &require_key_array(keys, KeyId::KugouT1)?,
+            &require_key_array(keys, KeyId::KugouT2)?,
+            &require_key_array(keys, KeyId::KugouV2)?,
+            &require_key_array(keys, KeyId::KugouVprKey)?,
+        ))),
+
+        #[cfg(feature = "kuwo")]
+        AudioFormat::Kwm => Ok(Box::new(super::new_kwm(&require_key_array(
+            keys,
+            KeyId::KuwoKey,
+        )?))),
+
+        #[cfg(feature = "ncm")]
+        AudioFormat::Ncm => Ok(Box::new(super::new_ncm(&require_key_array(
+            keys,
+            KeyId::NcmCoreKey,
+        )?))),
+
+        #[cfg(feature = "qmc")]
+        AudioFormat::QmcV1 => {
+            let key = require_key(keys, KeyId::QmcStaticKey)?;
+            if key.is_empty() {
+                return Err(DecryptError::new(
+                    DecryptErrorCode::InvalidKeyLength,
+                    "qmc static key is empty",
+                ));
+            }
+            Ok(Box::new(super::new_qmc_v1(&key)))
+        }
+
+        AudioFormat::QmcV2 => Err(DecryptError::new(
+            DecryptErrorCode::UnsupportedFormat,
+            "qmc v2 is keyed by its footer, see new_qmc_v2",
+        )),
+
+        AudioFormat::Xiami => Err(DecryptError::new(
+            DecryptErrorCode::UnsupportedFormat,
+            "xiami decryption is not supported",
+        )),
+
+        #[cfg(feature = "ximalaya")]
+        AudioFormat::X2m => {
+            let seed = require_key(keys, KeyId::XimalayaScrambleSeed)?;
+            Ok(Box::new(super::new_x2m(
+                require_key_array(keys, KeyId::XimalayaX2mKey)?,
+                super::ximalaya::build_scramble_table(&seed)?,
+            )))
+        }
+
+        #[cfg(feature = "ximalaya")]
+        AudioFormat::X3m => {
+            let seed = require_key(keys, KeyId::XimalayaScrambleSeed)?;
+            Ok(Box::new(super::new_x3m(
+                require_key_array(keys, KeyId::XimalayaX3mKey)?,
+                super::ximalaya::build_scramble_table(&seed)?,
+            )))
+        }
+
+        AudioFormat::Plain(_) => Ok(Box::new(new_passthrough())),
+
+        #[allow(unreachable_patterns)]
+        _ => Err(not_compiled_in(format)),
+    }
+}
+
+/// Create a decryptor from a trusted file extension (e.g. `ncm` or `.x2m`),
+/// without looking at the file content.
+pub fn decryptor_from_extension(
+    ext: &str,
+    keys: &dyn KeyProvider,
+) -> Result<Box<dyn Decryptor>, DecryptError> {
+    let ext = ext.strip_prefix('.').unwrap_or(ext);
+    let format = AudioFormat::from_extension(ext).ok_or_else(|| {
+        DecryptError::new(
+            DecryptErrorCode::UnsupportedFormat,
+            &format!("unsupported extension: {}", ext),
+        )
+    })?;
+    new_decryptor(format, keys)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decryptor_from_extension, new_decryptor, required_keys};
+    #[cfg(feature = "qmc")]
+    use crate::{
+        decryption::new_qmc_v1,
+        utils::{
+            audio::detect_audio_type,
+            test_util::test::{decrypt_in_chunks, generate_test_data},
+        },
+    };
+    use crate::{
+        decryption::AudioFormat,
+        decryptor::DecryptErrorCode,
+        keys::{KeyFormat, KeyId, RequiredKey, StaticKeyProvider},
+        utils::audio::AudioType,
+    };
+
+    #[test]
+    fn test_new_decryptor_plain() {
+        let decryptor = new_decryptor(
+            AudioFormat::Plain(AudioType::MP3),
+            &StaticKeyProvider::new(),
+        )
+        .unwrap();
+        assert_eq!(decryptor.get_name(), "PassThrough");
+    }
+
+    #[test]
+    fn test_required_keys() {
+        assert_eq!(
+            required_keys(AudioFormat::Ncm),
+            [RequiredKey::new(KeyId::NcmCoreKey, KeyFormat::Bytes(16))]
+        );
+        assert_eq!(
+            required_keys(AudioFormat::X2m),
+            [
+                RequiredKey::new(KeyId::XimalayaX2mKey, KeyFormat::Bytes(4)),
+                RequiredKey::new(KeyId::XimalayaScrambleSeed, KeyFormat::MinBytes(2048)),
+            ]
+        );
+        assert_eq!(
+            required_keys(AudioFormat::X3m),
+            [
+                RequiredKey::new(KeyId::XimalayaX3mKey, KeyFormat::Bytes(32)),
+                RequiredKey::new(KeyId::XimalayaScrambleSeed, KeyFormat::MinBytes(2048)),
+            ]
+        );
+        assert!(required_keys(AudioFormat::Plain(AudioType::MP3)).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "ximalaya")]
+    fn test_new_decryptor_keys() {
+        let keys = StaticKeyProvider::new().with_key(KeyId::XimalayaX2mKey, [0u8; 4]);
+        let err = new_decryptor(AudioFormat::X2m, &keys).err().unwrap();
+        assert!(matches!(err.code(), DecryptErrorCode::MissingKey));
+
+        let keys = keys.with_key(KeyId::XimalayaScrambleSeed, [0u8; 2048]);
+        let decryptor = new_decryptor(AudioFormat::X2m, &keys).unwrap();
+        assert_eq!(decryptor.get_name(), "Ximalaya(X2M)");
+
+        let keys = keys.with_key(KeyId::XimalayaX2mKey, [0u8; 3]);
+        let err = new_decryptor(AudioFormat::X2m, &keys).err().unwrap();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidKeyLength));
+    }
+
+    #[test]
+    fn test_decryptor_from_extension() {
+        let keys = StaticKeyProvider::new()
+            .with_key(KeyId::NcmCoreKey, [0u8; 16])
+            .with_key(KeyId::XimalayaX2mKey, [0u8; 4])
+            .with_key(KeyId::XimalayaScrambleSeed, [0u8; 2048]);
+
+        let decryptor = decryptor_from_extension("flac", &keys).unwrap();
+        assert_eq!(decryptor.get_name(), "PassThrough");
+
+        #[cfg(feature = "ximalaya")]
+        {
+            let decryptor = decryptor_from_extension(".X2M", &keys).unwrap();
+            assert_eq!(decryptor.get_name(), "Ximalaya(X2M)");
+        }
+
+        #[cfg(feature = "ncm")]
+        {
+            let decryptor = decryptor_from_extension("ncm", &keys).unwrap();
+            assert_eq!(decryptor.get_name(), "Netease");
+        }
+
+        let err = decryptor_from_extension("txt", &keys).err().unwrap();
+        assert!(matches!(err.code(), DecryptErrorCode::UnsupportedFormat));
+    }
+
+    #[test]
+    #[cfg(feature = "qmc")]
+    fn test_decryptor_from_extension_kugou_mixer() {
+        let key = generate_test_data(256, "bkc static key");
+        let keys = StaticKeyProvider::new().with_key(KeyId::QmcStaticKey, &key);
+
+        for (ext, magic, audio_type) in [
+            ("bkcmp3", &b"\xff\xfb\x90\x00"[..], AudioType::MP3),
+            ("bkcflac", &b"fLaC"[..], AudioType::FLAC),
+        ] {
+            let mut plain = generate_test_data(0x10000, "bkc data");
+            plain[..magic.len()].copy_from_slice(magic);
+            // The static cipher is a plain XOR, so decrypting also encrypts.
+            let encrypted = decrypt_in_chunks(&mut new_qmc_v1(&key), &plain, 0);
+
+            let mut decryptor = decryptor_from_extension(ext, &keys).unwrap();
+            let result = decrypt_in_chunks(decryptor.as_mut(), &encrypted, 0x1000);
+            assert_eq!(result, plain);
+            assert_eq!(detect_audio_type(&result), audio_type);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::utils::audio::AudioType;
+
+const XIAMI_MAGIC: &[u8; 4] = b"ifmt";
+const XIAMI_MAGIC_2: &[u8; 4] = &[0xfe, 0xfe, 0xfe, 0xfe];
+
+// cspell:disable
+/// Type tags found at offset 4 of the header, and the matching extension.
+const CONTAINER_TAGS: &[(&[u8; 4], &str)] = &[
+    (b" MP3", "mp3"),
+    (b" A4M", "m4a"),
+    (b"FLAC", "flac"),
+    (b" WAV", "wav"),
+];
+// cspell:enable
+
+fn has_magic(header: &[u8]) -> bool {
+    header.len() >= 12 && &header[..4] == XIAMI_MAGIC && &header[8..12] == XIAMI_MAGIC_2
+}
+
+/// Extension (without the leading dot) of the audio wrapped by a Xiami file,
+/// read from the type tag of its `header`.
+pub fn container_from_header(header: &[u8]) -> Option<&'static str> {
+    if !has_magic(header) {
+        return None;
+    }
+
+    CONTAINER_TAGS
+        .iter()
+        .find(|(tag, _)| &header[4..8] == *tag)
+        .map(|(_, ext)| *ext)
+}
+
+/// Like [`container_from_header`], as an [`AudioType`].
+pub(crate) fn container_type_from_header(header: &[u8]) -> Option<AudioType> {
+    match container_from_header(header)? {
+        "mp3" => Some(AudioType::MP3),
+        "m4a" => Some(AudioType::M4A),
+        "flac" => Some(AudioType::FLAC),
+        "wav" => Some(AudioType::WAV),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{container_from_header, container_type_from_header};
+    use crate::utils::audio::AudioType;
+
+    fn header(tag: &[u8; 4], plain_size: u32, key: u8) -> Vec<u8> {
+        let mut header = b"ifmt".to_vec();
+        header.extend_from_slice(tag);
+        header.extend_from_slice(&[0xfe; 4]);
+        header.extend_from_slice(&plain_size.to_le_bytes()[..3]);
+        header.push(key);
+        header
+    }
+
+    #[test]
+    fn test_container_from_header() {
+        assert_eq!(container_from_header(&header(b" MP3", 0, 0)), Some("mp3"));
+        assert_eq!(container_from_header(&header(b" A4M", 0, 0)), Some("m4a"));
+        assert_eq!(container_from_header(&header(b"FLAC", 0, 0)), Some("flac"));
+        assert_eq!(container_from_header(&header(b" WAV", 0, 0)), Some("wav"));
+        assert_eq!(container_from_header(&header(b" OGG", 0, 0)), None);
+
+        let mut bad_magic = header(b" MP3", 0, 0);
+        bad_magic[8] = 0;
+        assert_eq!(container_from_header(&bad_magic), None);
+        assert_eq!(container_from_header(b"ifmt MP3"), None);
+
+        assert_eq!(
+            container_type_from_header(&header(b" A4M", 0, 0)),
+            Some(AudioType::M4A)
+        );
+    }
+}
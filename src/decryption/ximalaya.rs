@@ -6,63 +6,439 @@ pub type X2MContentKey = [u8; X2M_CONTENT_KEY_SIZE];
 pub type X3MContentKey = [u8; X3M_CONTENT_KEY_SIZE];
 pub type ScrambleTable = [u16; XMLY_SCRAMBLE_SIZE];
 
+/// How the header bytes were combined with the content key. X2M and X3M
+/// use [`CombineOp::Xor`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CombineOp {
+    #[default]
+    Xor,
+    /// Encrypted as `plain + key` (mod 256), so decrypted by subtraction.
+    AddMod256,
+}
+
+impl CombineOp {
+    #[inline(always)]
+    fn decrypt(self, value: u8, key: u8) -> u8 {
+        match self {
+            CombineOp::Xor => value ^ key,
+            CombineOp::AddMod256 => value.wrapping_sub(key),
+        }
+    }
+}
+
 mod detail {
-    use super::{ScrambleTable, X2MContentKey, X3MContentKey, XMLY_SCRAMBLE_SIZE};
+    use super::{
+        CombineOp, ScrambleTable, X2MContentKey, X3MContentKey, X2M_CONTENT_KEY_SIZE,
+        X3M_CONTENT_KEY_SIZE, XMLY_SCRAMBLE_SIZE,
+    };
     use crate::{
-        decryptor::{BaseDecryptorData, DecryptError, Decryptor},
-        impl_decryptor_inner_helper,
-        utils::array_ext::ArrayExtension,
+        decryption::header::HeaderAccumulator,
+        decryptor::{
+            check_invariants, consume_output_buffer, trace_write_span, transition_state,
+            BaseDecryptorData, DecryptError, DecryptErrorCode, DecryptManifest, Decryptor,
+            ResumableDecryptor, SeekableDecryptor,
+        },
+        utils::{
+            array_ext::{ArrayExtension, ByteSliceExt},
+            audio::{detect_audio_type, AudioType},
+        },
+    };
+    use std::{
+        any::Any,
+        mem::{size_of, size_of_val},
     };
 
+    #[derive(Debug)]
     enum State {
         DecryptHeader,
         PassThrough,
+        /// Header-only mode: the rest of the input is dropped.
+        Done,
+    }
+
+    /// Decrypt the scrambled `header` into `out`, with a key of any length.
+    fn decrypt_header_to(
+        header: &[u8],
+        key: &[u8],
+        scramble_table: &ScrambleTable,
+        is_identity: bool,
+        combine: CombineOp,
+        out: &mut [u8],
+    ) {
+        if is_identity {
+            out.copy_from_slice(header);
+            for chunk in out.chunks_mut(key.len()) {
+                for (v, k) in chunk.iter_mut().zip(key.iter()) {
+                    *v = combine.decrypt(*v, *k);
+                }
+            }
+            return;
+        }
+
+        for (i, (v, idx)) in out.iter_mut().zip(scramble_table.iter()).enumerate() {
+            debug_assert!(
+                (*idx as usize) < XMLY_SCRAMBLE_SIZE,
+                "scramble index {} out of header",
+                idx
+            );
+            *v = combine.decrypt(header.get_value_unchecked(*idx), key.get_mod_n(i));
+        }
     }
 
     pub struct Ximalaya<T> {
         data: BaseDecryptorData,
         state: State,
+        header: HeaderAccumulator,
         key: T,
         scramble_table: ScrambleTable,
+        is_identity: bool,
+        combine: CombineOp,
+        lazy_header: bool,
+        /// Lazy mode: the header is complete, but not decrypted yet.
+        header_pending: bool,
+        header_only: bool,
     }
 
     impl<const KEY_SIZE: usize> Ximalaya<[u8; KEY_SIZE]> {
+        /// # Panics
+        ///
+        /// If `scramble_table` holds an index out of the header, see
+        /// [`Ximalaya::try_new`] for a checked version.
         pub fn new(name: &str, key: [u8; KEY_SIZE], scramble_table: ScrambleTable) -> Self {
+            Self::try_new(name, key, scramble_table).expect("invalid scramble table")
+        }
+
+        pub fn try_new(
+            name: &str,
+            key: [u8; KEY_SIZE],
+            scramble_table: ScrambleTable,
+        ) -> Result<Self, DecryptError> {
+            Self::try_new_with_combine(name, key, scramble_table, CombineOp::Xor)
+        }
+
+        /// Like [`Ximalaya::try_new`], for variants combining the header with
+        /// the key by `combine` rather than XOR.
+        pub fn try_new_with_combine(
+            name: &str,
+            key: [u8; KEY_SIZE],
+            scramble_table: ScrambleTable,
+            combine: CombineOp,
+        ) -> Result<Self, DecryptError> {
+            validate_scramble_table(&scramble_table)?;
+
             let data = BaseDecryptorData::new(name);
-            Ximalaya {
+            Ok(Ximalaya {
                 data,
                 key,
                 state: State::DecryptHeader,
+                header: HeaderAccumulator::new(),
+                is_identity: Self::is_identity_table(&scramble_table),
                 scramble_table,
-            }
+                combine,
+                lazy_header: false,
+                header_pending: false,
+                header_only: false,
+            })
         }
 
-        fn do_header_decryption(&mut self) {
-            let mut output = self
-                .scramble_table
+        fn is_identity_table(scramble_table: &ScrambleTable) -> bool {
+            scramble_table
                 .iter()
                 .enumerate()
-                .map(|(i, idx)| -> u8 {
-                    self.data.buf_in.get_value_unchecked(*idx) ^ self.key.get_mod_n(i)
-                })
-                .collect();
-            self.data.buf_out.append(&mut output);
-            self.data.seek_input(XMLY_SCRAMBLE_SIZE);
+                .all(|(i, idx)| *idx as usize == i)
+        }
+
+        /// The key byte combined with each (unscrambled) header position:
+        /// XOR'd with [`CombineOp::Xor`], subtracted (mod 256) with
+        /// [`CombineOp::AddMod256`]. For checking a suspect key against a
+        /// reference; independent of input and of the combine mode.
+        pub fn debug_keystream(&self) -> [u8; XMLY_SCRAMBLE_SIZE] {
+            let mut keystream = [0u8; XMLY_SCRAMBLE_SIZE];
+            for (i, v) in keystream.iter_mut().enumerate() {
+                *v = self.key.get_mod_n(i);
+            }
+            keystream
+        }
+
+        /// Defer the header decryption until the output is first read, e.g. for
+        /// detection-only callers that may never read it. Output is unaffected.
+        pub fn with_lazy_header(mut self, lazy_header: bool) -> Self {
+            self.lazy_header = lazy_header;
+            self
+        }
+
+        /// Only output the decrypted header, e.g. to read embedded metadata:
+        /// input past it is dropped instead of copied to the output.
+        pub fn with_header_only(mut self, header_only: bool) -> Self {
+            self.header_only = header_only;
+            self
+        }
+
+        /// Expected input size, to pre-size the output buffer. Set before the
+        /// first write.
+        pub fn with_size_hint(mut self, size_hint: usize) -> Self {
+            self.data = BaseDecryptorData::new_with_capacity(&self.data.name, size_hint);
+            self
+        }
+
+        fn state_after_header(&self) -> State {
+            match self.header_only {
+                true => State::Done,
+                false => State::PassThrough,
+            }
+        }
+
+        #[cfg(test)]
+        pub(super) fn is_header_pending(&self) -> bool {
+            self.header_pending
+        }
+
+        /// Put the decrypted header in front of any pass-through output
+        /// buffered after it.
+        fn flush_pending_header(&mut self) {
+            if self.header_pending {
+                let mut output = Self::decrypt_header(
+                    &self.header.as_bytes()[..XMLY_SCRAMBLE_SIZE],
+                    &self.key,
+                    &self.scramble_table,
+                    self.is_identity,
+                    self.combine,
+                );
+                output.append(&mut self.data.buf_out);
+                self.data.buf_out = output;
+                self.header_pending = false;
+            }
+        }
+
+        /// Force the permuted header path, even for an identity table.
+        #[cfg(test)]
+        pub(super) fn without_identity_fast_path(mut self) -> Self {
+            self.is_identity = false;
+            self
+        }
+
+        /// Decrypt a complete header into `out` without allocating, independent
+        /// of the streaming state. Matches the first 1024 bytes of the output.
+        pub fn decrypt_header_into(
+            &self,
+            input: &[u8; XMLY_SCRAMBLE_SIZE],
+            out: &mut [u8; XMLY_SCRAMBLE_SIZE],
+        ) {
+            decrypt_header_to(
+                input,
+                &self.key,
+                &self.scramble_table,
+                self.is_identity,
+                self.combine,
+                out,
+            );
+        }
+
+        pub(super) fn decrypt_header(
+            header: &[u8],
+            key: &[u8; KEY_SIZE],
+            scramble_table: &ScrambleTable,
+            is_identity: bool,
+            combine: CombineOp,
+        ) -> Vec<u8> {
+            let mut output = vec![0u8; XMLY_SCRAMBLE_SIZE];
+            decrypt_header_to(
+                header,
+                key,
+                scramble_table,
+                is_identity,
+                combine,
+                &mut output,
+            );
+            output
+        }
+    }
+
+    impl<const KEY_SIZE: usize> SeekableDecryptor for Ximalaya<[u8; KEY_SIZE]> {
+        fn decrypt_at(&self, offset: u64, _buf: &mut [u8]) -> Result<(), DecryptError> {
+            // Only the pass-through region can be decrypted on its own, as the
+            // header is scrambled across all of its 1024 bytes.
+            if offset < XMLY_SCRAMBLE_SIZE as u64 {
+                return Err(DecryptError::new(
+                    DecryptErrorCode::RangeNotSupported,
+                    "header region requires the full header",
+                ));
+            }
+
+            Ok(())
+        }
+
+        fn first_seekable_offset(&self) -> u64 {
+            XMLY_SCRAMBLE_SIZE as u64
+        }
+    }
+
+    /// Snapshot layout: state (`u8`), offset (`(le)u64`), then the header
+    /// bytes buffered so far while still in the header.
+    impl<const KEY_SIZE: usize> ResumableDecryptor for Ximalaya<[u8; KEY_SIZE]> {
+        fn snapshot(&self) -> Vec<u8> {
+            let mut snapshot = vec![];
+            match self.state {
+                State::DecryptHeader => snapshot.push(0),
+                State::PassThrough => snapshot.push(1),
+                State::Done => snapshot.push(2),
+            }
+            snapshot.extend_from_slice(&(self.data.offset as u64).to_le_bytes());
+            if let State::DecryptHeader = self.state {
+                snapshot.extend_from_slice(self.header.as_bytes());
+            }
+            snapshot
+        }
+
+        fn restore(&mut self, snapshot: &[u8]) -> Result<(), DecryptError> {
+            let invalid = || DecryptError::new(DecryptErrorCode::InvalidSnapshot, "bad snapshot");
+
+            let (&state, rest) = snapshot.split_first().ok_or_else(invalid)?;
+            if rest.len() < size_of::<u64>() {
+                return Err(invalid());
+            }
+            let (offset, mut header) = rest.split_at(size_of::<u64>());
+            let offset = usize::try_from(u64::from_le_bytes(offset.try_into().unwrap()))
+                .map_err(|_| invalid())?;
+
+            let next = match state {
+                0 if offset == header.len() && header.len() < XMLY_SCRAMBLE_SIZE => {
+                    State::DecryptHeader
+                }
+                1 if offset >= XMLY_SCRAMBLE_SIZE && header.is_empty() => State::PassThrough,
+                2 if self.header_only && offset >= XMLY_SCRAMBLE_SIZE && header.is_empty() => {
+                    State::Done
+                }
+                _ => return Err(invalid()),
+            };
+
+            // Drop everything left from the stream the instance was used for.
+            self.data.reset();
+            self.header = HeaderAccumulator::new();
+            self.header.feed(&mut header, XMLY_SCRAMBLE_SIZE);
+            self.header_pending = false;
+            self.state = next;
+            self.data.offset = offset;
+            Ok(())
         }
     }
 
     impl<const KEY_SIZE: usize> Decryptor for Ximalaya<[u8; KEY_SIZE]> {
-        impl_decryptor_inner_helper! {}
+        fn get_name(&self) -> &str {
+            &self.data.name
+        }
+
+        fn read_all_output(&mut self) -> Vec<u8> {
+            self.flush_pending_header();
+            self.data.read_all_output()
+        }
+
+        fn get_eof_reserve(&self) -> usize {
+            self.data.reserve_eof
+        }
+
+        fn output_ready(&self) -> usize {
+            match self.header_pending {
+                true => XMLY_SCRAMBLE_SIZE + self.data.buf_out.len(),
+                false => self.data.buf_out.len(),
+            }
+        }
+
+        fn consume_output(&mut self, len: usize) -> Vec<u8> {
+            self.flush_pending_header();
+            consume_output_buffer(&mut self.data.buf_out, len, self.data.output_shrink_factor)
+        }
+
+        fn set_output_shrink_factor(&mut self, factor: usize) {
+            self.data.output_shrink_factor = factor;
+        }
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            self.data.mark_finalized();
+            match self.state {
+                State::DecryptHeader => self.header.ensure_complete(XMLY_SCRAMBLE_SIZE),
+                State::PassThrough | State::Done => Ok(()),
+            }
+        }
+
+        fn as_seekable(&self) -> Option<&dyn SeekableDecryptor> {
+            Some(self)
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn manifest(&self) -> DecryptManifest {
+            self.data.manifest(Some(&self.key), XMLY_SCRAMBLE_SIZE)
+        }
+
+        fn validate_config(&self) -> Result<(), DecryptError> {
+            validate_scramble_permutation(&self.scramble_table)
+        }
+
+        fn bytes_until_next_phase(&self) -> Option<usize> {
+            match self.state {
+                State::DecryptHeader => Some(XMLY_SCRAMBLE_SIZE - self.header.len()),
+                State::PassThrough | State::Done => None,
+            }
+        }
+
+        fn reset(&mut self) -> Result<(), DecryptError> {
+            self.data.reset();
+            self.state = State::DecryptHeader;
+            self.header = HeaderAccumulator::new();
+            self.header_pending = false;
+            Ok(())
+        }
+
+        fn write_owned(&mut self, mut chunk: Vec<u8>) -> Result<(), DecryptError> {
+            if !matches!(self.state, State::PassThrough) {
+                return self.write(&chunk);
+            }
+
+            trace_write_span!(self, state);
+            self.data.ensure_not_finalized()?;
+            let (prev_offset, prev_output_len) = (self.data.offset, self.data.buf_out.len());
+            self.data.offset += chunk.len();
+            if self.data.buf_out.is_empty() && !self.header_pending {
+                self.data.buf_out = chunk;
+            } else {
+                self.data.buf_out.append(&mut chunk);
+            }
+            check_invariants(&self.data, prev_offset, prev_output_len);
+            Ok(())
+        }
 
         fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            trace_write_span!(self, state);
+            self.data.ensure_not_finalized()?;
+            let (prev_offset, prev_output_len) = (self.data.offset, self.data.buf_out.len());
             let mut p = data;
 
             while !p.is_empty() {
                 match self.state {
                     State::DecryptHeader => {
-                        if self.data.read_until_offset(&mut p, XMLY_SCRAMBLE_SIZE) {
-                            self.do_header_decryption();
-                            self.state = State::PassThrough;
+                        let len = p.len();
+                        let header = self.header.feed(&mut p, XMLY_SCRAMBLE_SIZE);
+                        // Buffered header bytes count as read.
+                        self.data.offset += len - p.len();
+                        if let Some(header) = header {
+                            if self.lazy_header {
+                                self.header_pending = true;
+                                transition_state!(self, self.state_after_header());
+                                continue;
+                            }
+
+                            let mut output = Self::decrypt_header(
+                                header,
+                                &self.key,
+                                &self.scramble_table,
+                                self.is_identity,
+                                self.combine,
+                            );
+                            self.data.buf_out.append(&mut output);
+                            transition_state!(self, self.state_after_header());
                         }
                     }
                     State::PassThrough => {
@@ -70,13 +446,112 @@ mod detail {
                         self.data.offset += p.len();
                         break;
                     }
+                    State::Done => {
+                        self.data.offset += p.len();
+                        break;
+                    }
                 }
             }
 
+            check_invariants(&self.data, prev_offset, prev_output_len);
             Ok(())
         }
     }
 
+    /// Check every entry of `scramble_table` indexes into the header, as the
+    /// header decryption reads them unchecked.
+    fn validate_scramble_table(scramble_table: &ScrambleTable) -> Result<(), DecryptError> {
+        match scramble_table
+            .iter()
+            .find(|idx| **idx as usize >= XMLY_SCRAMBLE_SIZE)
+        {
+            Some(idx) => Err(DecryptError::new(
+                DecryptErrorCode::XimalayaInvalidScrambleTable,
+                &format!("scramble index {} out of header", idx),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Check `scramble_table` is a permutation of the header, as built by
+    /// [`build_scramble_table`]. Construction only checks the bounds, so a
+    /// table repeating an index still decrypts, just not correctly.
+    fn validate_scramble_permutation(scramble_table: &ScrambleTable) -> Result<(), DecryptError> {
+        validate_scramble_table(scramble_table)?;
+
+        let mut seen = [false; XMLY_SCRAMBLE_SIZE];
+        for &idx in scramble_table.iter() {
+            if std::mem::replace(&mut seen[idx as usize], true) {
+                return Err(DecryptError::new(
+                    DecryptErrorCode::XimalayaInvalidScrambleTable,
+                    &format!("scramble index {} used twice", idx),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Shuffle `table` into a permutation driven by `seed`.
+    ///
+    /// Starting from the identity permutation, entry `i` is swapped with the
+    /// entry addressed by the `(le)u16` read from `seed[i * 2..]`. As both the
+    /// seed reads and the table entries are `u16`, the table can not hold more
+    /// than `u16::MAX + 1` entries.
+    pub fn build_scramble_table_into(table: &mut [u16], seed: &[u8]) -> Result<(), DecryptError> {
+        let table_size = table.len();
+        if table_size > u16::MAX as usize + 1 {
+            return Err(DecryptError::new(
+                DecryptErrorCode::XimalayaInvalidScrambleTable,
+                "scramble table too large to be addressed by u16 seed",
+            ));
+        }
+
+        if seed.len() < size_of_val(table) {
+            return Err(DecryptError::new(
+                DecryptErrorCode::XimalayaScrambleSeedTooSmall,
+                "scramble seed too small",
+            ));
+        }
+
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = i as u16;
+        }
+
+        for i in 0..table_size {
+            let n = seed.read_le::<u16>(i * size_of::<u16>()) as usize;
+            table.swap(i, n % table_size);
+        }
+
+        Ok(())
+    }
+
+    pub fn build_scramble_table(seed: &[u8]) -> Result<ScrambleTable, DecryptError> {
+        let mut table: ScrambleTable = [0u16; XMLY_SCRAMBLE_SIZE];
+        build_scramble_table_into(&mut table, seed)?;
+        Ok(table)
+    }
+
+    /// Check a candidate content key of any length: decrypt `header` with it,
+    /// combined by `combine`, and see whether the result starts like a known
+    /// audio format.
+    ///
+    /// Meant for key recovery tooling; a wrong key can still pass by chance,
+    /// so confirm with a full decryption.
+    pub fn validate_key(
+        key: &[u8],
+        scramble_table: &ScrambleTable,
+        header: &[u8; XMLY_SCRAMBLE_SIZE],
+        combine: CombineOp,
+    ) -> bool {
+        if key.is_empty() || validate_scramble_table(scramble_table).is_err() {
+            return false;
+        }
+
+        let mut plain = [0u8; XMLY_SCRAMBLE_SIZE];
+        decrypt_header_to(header, key, scramble_table, false, combine, &mut plain);
+        detect_audio_type(plain) != AudioType::UnknownType
+    }
+
     pub fn new_x2m(key: X2MContentKey, scramble_table: ScrambleTable) -> impl Decryptor {
         Ximalaya::new("Ximalaya(X2M)", key, scramble_table)
     }
@@ -84,35 +559,175 @@ mod detail {
     pub fn new_x3m(key: X3MContentKey, scramble_table: ScrambleTable) -> impl Decryptor {
         Ximalaya::new("Ximalaya(X3M)", key, scramble_table)
     }
+
+    pub fn try_new_x2m(
+        key: X2MContentKey,
+        scramble_table: ScrambleTable,
+    ) -> Result<impl Decryptor, DecryptError> {
+        Ximalaya::try_new("Ximalaya(X2M)", key, scramble_table)
+    }
+
+    pub fn try_new_x3m(
+        key: X3MContentKey,
+        scramble_table: ScrambleTable,
+    ) -> Result<impl Decryptor, DecryptError> {
+        Ximalaya::try_new("Ximalaya(X3M)", key, scramble_table)
+    }
+
+    /// X2M or X3M, picked by the length of `key`.
+    pub fn new_ximalaya(
+        key: &[u8],
+        scramble_table: ScrambleTable,
+    ) -> Result<Box<dyn Decryptor>, DecryptError> {
+        match key.len() {
+            X2M_CONTENT_KEY_SIZE => {
+                Ok(try_new_x2m(key.try_into().unwrap(), scramble_table)?.into_boxed())
+            }
+            X3M_CONTENT_KEY_SIZE => {
+                Ok(try_new_x3m(key.try_into().unwrap(), scramble_table)?.into_boxed())
+            }
+            len => Err(DecryptError::new(
+                DecryptErrorCode::InvalidKeyLength,
+                &format!("unexpected ximalaya content key size: {}", len),
+            )),
+        }
+    }
 }
 
+pub use detail::build_scramble_table;
+pub use detail::build_scramble_table_into;
 pub use detail::new_x2m;
 pub use detail::new_x3m;
+pub use detail::new_ximalaya;
+pub use detail::try_new_x2m;
+pub use detail::try_new_x3m;
+pub use detail::validate_key;
+pub use detail::Ximalaya;
 
 #[cfg(test)]
 pub mod test {
-    use super::{ScrambleTable, XMLY_SCRAMBLE_SIZE};
+    use super::{detail::Ximalaya, new_x2m, new_x3m, CombineOp, ScrambleTable, XMLY_SCRAMBLE_SIZE};
     use crate::{
         decryption::ximalaya::{X2M_CONTENT_KEY_SIZE, X3M_CONTENT_KEY_SIZE},
-        utils::array_ext::ByteSliceExt,
-        utils::test_util::test::{decrypt_test_content, generate_test_data, TEST_SIZE_1MB},
+        decryptor::{DecryptErrorCode, Decryptor, ResumableDecryptor},
+        utils::array_ext::ArrayExtension,
+        utils::test_util::test::{
+            assert_deterministic_after_reset, decrypt_in_chunks, decrypt_test_content,
+            decryptor_conformance, generate_test_data, verify_roundtrip, TEST_SIZE_1MB,
+        },
     };
 
+    /// Inverse of the header decryption: scatter the XOR'd plaintext header
+    /// back to the positions the scramble table reads them from.
+    pub fn encrypt(key: &[u8], scramble_table: &ScrambleTable, plain: &[u8]) -> Vec<u8> {
+        let mut result = Vec::from(plain);
+        if plain.len() >= XMLY_SCRAMBLE_SIZE {
+            for (i, idx) in scramble_table.iter().enumerate() {
+                result[*idx as usize] = plain[i] ^ key.get_mod_n(i);
+            }
+        }
+        result
+    }
+
+    /// Like [`encrypt`], adding the key instead of XOR'ing it.
+    fn encrypt_additive(key: &[u8], scramble_table: &ScrambleTable, plain: &[u8]) -> Vec<u8> {
+        let mut result = Vec::from(plain);
+        for (i, idx) in scramble_table.iter().enumerate() {
+            result[*idx as usize] = plain[i].wrapping_add(key.get_mod_n(i));
+        }
+        result
+    }
+
+    fn conformance_table() -> ScrambleTable {
+        super::build_scramble_table(&generate_test_data(2048, "conformance seed")).unwrap()
+    }
+
+    fn conformance_key<const N: usize>() -> [u8; N] {
+        generate_test_data(N, "conformance key").try_into().unwrap()
+    }
+
+    decryptor_conformance!(
+        x2m_conformance,
+        || new_x2m(conformance_key(), conformance_table()),
+        |plain: &[u8]| encrypt(
+            &conformance_key::<X2M_CONTENT_KEY_SIZE>(),
+            &conformance_table(),
+            plain
+        ),
+        Err(DecryptErrorCode::EmptyInput)
+    );
+
+    decryptor_conformance!(
+        x3m_conformance,
+        || new_x3m(conformance_key(), conformance_table()),
+        |plain: &[u8]| encrypt(
+            &conformance_key::<X3M_CONTENT_KEY_SIZE>(),
+            &conformance_table(),
+            plain
+        ),
+        Err(DecryptErrorCode::EmptyInput)
+    );
+
+    #[test]
+    fn test_additive_combine_roundtrip() {
+        let key = generate_test_data(X3M_CONTENT_KEY_SIZE, "additive key");
+        let key: [u8; X3M_CONTENT_KEY_SIZE] = key.try_into().unwrap();
+        let plain = generate_test_data(XMLY_SCRAMBLE_SIZE + 0x100, "additive plain");
+        let mut identity: ScrambleTable = [0u16; XMLY_SCRAMBLE_SIZE];
+        for (i, v) in identity.iter_mut().enumerate() {
+            *v = i as u16;
+        }
+        let scrambled =
+            super::build_scramble_table(&generate_test_data(2048, "additive seed")).unwrap();
+
+        for table in [identity, scrambled] {
+            let encrypted = encrypt_additive(&key, &table, &plain);
+            let mut decryptor =
+                Ximalaya::try_new_with_combine("additive", key, table, CombineOp::AddMod256)
+                    .unwrap();
+            assert_eq!(decrypt_in_chunks(&mut decryptor, &encrypted, 7), plain);
+
+            // The default XOR does not undo an additive header.
+            let mut xor = Ximalaya::new("xor", key, table);
+            assert_ne!(decrypt_in_chunks(&mut xor, &encrypted, 0), plain);
+        }
+    }
+
+    #[test]
+    fn test_manifest_has_fingerprint_not_key() {
+        use crate::decryptor::key_fingerprint;
+
+        let key = generate_test_data(X3M_CONTENT_KEY_SIZE, "manifest key");
+        let table =
+            super::build_scramble_table(&generate_test_data(2048, "manifest seed")).unwrap();
+        let test_data = generate_test_data(0x3000, "manifest data");
+
+        let mut decryptor = super::new_x3m(key[..].try_into().unwrap(), table);
+        decrypt_in_chunks(&mut decryptor, &test_data, 0x100);
+
+        let manifest = decryptor.manifest();
+        assert_eq!(manifest.format, "Ximalaya(X3M)");
+        assert_eq!(manifest.key_fingerprint, Some(key_fingerprint(&key)));
+        assert_eq!(manifest.header_len, XMLY_SCRAMBLE_SIZE as u64);
+        assert_eq!(manifest.bytes_in, test_data.len() as u64);
+
+        let key_hex = data_encoding::HEXLOWER.encode(&key);
+        assert!(!format!("{:?}", manifest).contains(&key_hex));
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&manifest).unwrap();
+            assert!(json.contains(&key_fingerprint(&key)));
+            assert!(!json.contains(&key_hex));
+        }
+    }
+
     #[test]
     fn test_x2m() {
         let test_data = generate_test_data(TEST_SIZE_1MB, "x2m-test-data");
         let x2m_content_key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m content key");
 
-        let mut x2m_scramble_table: ScrambleTable = [0u16; XMLY_SCRAMBLE_SIZE];
-        for (i, v) in x2m_scramble_table.iter_mut().enumerate() {
-            *v = i as u16;
-        }
-        let table_size = x2m_scramble_table.len();
-        let x2m_scramble_seed = generate_test_data(table_size * 2, "x2m seed");
-        for i in 0..table_size {
-            let n = x2m_scramble_seed.read_le::<u16>(i * 2) as usize;
-            x2m_scramble_table.swap(i, n % table_size);
-        }
+        let x2m_scramble_seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m seed");
+        let x2m_scramble_table = super::build_scramble_table(&x2m_scramble_seed).unwrap();
 
         assert_eq!(x2m_content_key.len(), X2M_CONTENT_KEY_SIZE);
 
@@ -134,16 +749,8 @@ pub mod test {
         let test_data = generate_test_data(TEST_SIZE_1MB, "x3m-test-data");
         let x3m_content_key = generate_test_data(X3M_CONTENT_KEY_SIZE, "x3m content key");
 
-        let mut x3m_scramble_table: ScrambleTable = [0u16; XMLY_SCRAMBLE_SIZE];
-        for (i, v) in x3m_scramble_table.iter_mut().enumerate() {
-            *v = i as u16;
-        }
-        let table_size = x3m_scramble_table.len();
-        let x3m_scramble_seed = generate_test_data(table_size * 2, "x3m seed");
-        for i in 0..table_size {
-            let n = x3m_scramble_seed.read_le::<u16>(i * 2) as usize;
-            x3m_scramble_table.swap(i, n % table_size);
-        }
+        let x3m_scramble_seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x3m seed");
+        let x3m_scramble_table = super::build_scramble_table(&x3m_scramble_seed).unwrap();
 
         assert_eq!(x3m_content_key.len(), X3M_CONTENT_KEY_SIZE);
 
@@ -159,4 +766,498 @@ pub mod test {
             "a10bbfdcdbd388373361da6baf35c80b725f7310c3eca29d7dcf228e397a8c5a"
         );
     }
+
+    #[test]
+    fn test_identity_table_fast_path_matches_permuted_path() {
+        let test_data = generate_test_data(XMLY_SCRAMBLE_SIZE + 0x100, "x3m identity data");
+        let key = generate_test_data(X3M_CONTENT_KEY_SIZE, "x3m identity key");
+        let key: [u8; X3M_CONTENT_KEY_SIZE] = key.try_into().unwrap();
+        let mut table: ScrambleTable = [0u16; XMLY_SCRAMBLE_SIZE];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = i as u16;
+        }
+
+        let mut fast = Ximalaya::new("fast", key, table);
+        let mut slow = Ximalaya::new("slow", key, table).without_identity_fast_path();
+        assert_eq!(
+            decrypt_in_chunks(&mut fast, &test_data, 7),
+            decrypt_in_chunks(&mut slow, &test_data, 7)
+        );
+    }
+
+    #[test]
+    fn test_downcast_boxed_decryptor() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m downcast key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m downcast seed");
+        let table = super::build_scramble_table(&seed).unwrap();
+
+        let decryptor: Box<dyn Decryptor> =
+            Box::new(super::new_x2m(key.try_into().unwrap(), table));
+        let any = decryptor.as_any();
+        assert!(any
+            .downcast_ref::<Ximalaya<super::X2MContentKey>>()
+            .is_some());
+        assert!(any
+            .downcast_ref::<Ximalaya<super::X3MContentKey>>()
+            .is_none());
+    }
+
+    #[test]
+    fn test_scramble_table_too_large_for_seed_width() {
+        let mut table = vec![0u16; u16::MAX as usize + 2];
+        let seed = generate_test_data(table.len() * 2, "oversized seed");
+
+        let err = super::build_scramble_table_into(&mut table, &seed).unwrap_err();
+        assert!(matches!(
+            err.code(),
+            DecryptErrorCode::XimalayaInvalidScrambleTable
+        ));
+    }
+
+    #[test]
+    fn test_corrupted_scramble_table_rejected() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m corrupted key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m corrupted seed");
+        let mut table = super::build_scramble_table(&seed).unwrap();
+        table[0x10] = XMLY_SCRAMBLE_SIZE as u16;
+
+        let err = super::try_new_x2m(key.try_into().unwrap(), table)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.code(),
+            DecryptErrorCode::XimalayaInvalidScrambleTable
+        ));
+    }
+
+    #[test]
+    fn test_validate_config_scramble_permutation() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m validate key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m validate seed");
+        let mut table = super::build_scramble_table(&seed).unwrap();
+        let decryptor = new_x2m(key[..].try_into().unwrap(), table);
+        assert!(decryptor.validate_config().is_ok());
+
+        // In bounds, so accepted on construction, but not a permutation.
+        table[0x10] = table[0x11];
+        let decryptor = super::try_new_x2m(key[..].try_into().unwrap(), table).unwrap();
+        let err = decryptor.validate_config().unwrap_err();
+        assert!(matches!(
+            err.code(),
+            DecryptErrorCode::XimalayaInvalidScrambleTable
+        ));
+    }
+
+    #[test]
+    fn test_header_only_drops_body() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m header only key");
+        let data = generate_test_data(0x3000, "x2m header only data");
+        let expected = decrypt_in_chunks(
+            &mut new_x2m(key[..].try_into().unwrap(), conformance_table()),
+            &data,
+            0,
+        );
+
+        let mut decryptor = Ximalaya::new(
+            "Ximalaya(X2M)",
+            <[u8; X2M_CONTENT_KEY_SIZE]>::try_from(key).unwrap(),
+            conformance_table(),
+        )
+        .with_header_only(true);
+        let (header, body) = data.split_at(XMLY_SCRAMBLE_SIZE + 0x10);
+        decryptor.write(header).unwrap();
+        decryptor.write_owned(Vec::from(body)).unwrap();
+        decryptor.end().unwrap();
+        assert_eq!(decryptor.read_all_output(), &expected[..XMLY_SCRAMBLE_SIZE]);
+        assert_eq!(decryptor.manifest().bytes_in, data.len() as u64);
+    }
+
+    #[test]
+    fn test_bytes_until_next_phase() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m phase key");
+        let data = generate_test_data(XMLY_SCRAMBLE_SIZE + 0x10, "x2m phase data");
+        let mut decryptor = new_x2m(key.try_into().unwrap(), conformance_table());
+        assert_eq!(decryptor.bytes_until_next_phase(), Some(XMLY_SCRAMBLE_SIZE));
+
+        decryptor.write(&data[..0x100]).unwrap();
+        assert_eq!(
+            decryptor.bytes_until_next_phase(),
+            Some(XMLY_SCRAMBLE_SIZE - 0x100)
+        );
+        decryptor
+            .write(&data[0x100..XMLY_SCRAMBLE_SIZE - 1])
+            .unwrap();
+        assert_eq!(decryptor.bytes_until_next_phase(), Some(1));
+
+        decryptor.write(&data[XMLY_SCRAMBLE_SIZE - 1..]).unwrap();
+        assert_eq!(decryptor.bytes_until_next_phase(), None);
+        decryptor.reset().unwrap();
+        assert_eq!(decryptor.bytes_until_next_phase(), Some(XMLY_SCRAMBLE_SIZE));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "scramble index 1024 out of header")]
+    fn test_corrupted_scramble_table_asserts() {
+        let mut table: ScrambleTable = [0u16; XMLY_SCRAMBLE_SIZE];
+        table[0x10] = XMLY_SCRAMBLE_SIZE as u16;
+
+        Ximalaya::<[u8; X2M_CONTENT_KEY_SIZE]>::decrypt_header(
+            &[0u8; XMLY_SCRAMBLE_SIZE],
+            &[0u8; X2M_CONTENT_KEY_SIZE],
+            &table,
+            false,
+            CombineOp::Xor,
+        );
+    }
+
+    #[test]
+    fn test_decrypt_header_into_matches_streaming() {
+        let key = generate_test_data(X3M_CONTENT_KEY_SIZE, "x3m header key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x3m header seed");
+        let table = super::build_scramble_table(&seed).unwrap();
+        let encrypted = generate_test_data(XMLY_SCRAMBLE_SIZE + 0x100, "x3m header data");
+        let key: super::X3MContentKey = key.try_into().unwrap();
+
+        let mut decryptor = Ximalaya::new("streaming", key, table);
+        let expected = decrypt_in_chunks(&mut decryptor, &encrypted, 0x100);
+
+        let mut out = [0u8; XMLY_SCRAMBLE_SIZE];
+        decryptor.decrypt_header_into(
+            encrypted[..XMLY_SCRAMBLE_SIZE].try_into().unwrap(),
+            &mut out,
+        );
+        assert_eq!(out[..], expected[..XMLY_SCRAMBLE_SIZE]);
+    }
+
+    #[test]
+    fn test_x2m_snapshot_restore() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m resume key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m resume seed");
+        let table = super::build_scramble_table(&seed).unwrap();
+        let plain = generate_test_data(0x8000, "x2m resume data");
+        let encrypted = encrypt(&key, &table, &plain);
+        let key: super::X2MContentKey = key.try_into().unwrap();
+
+        // Mid header and past it.
+        for split in [500, 0x4000] {
+            let mut first = Ximalaya::new("first", key, table);
+            first.write(&encrypted[..split]).unwrap();
+            let mut result = first.read_all_output();
+            let snapshot = first.snapshot();
+
+            let mut resumed = Ximalaya::new("resumed", key, table);
+            resumed.restore(&snapshot).unwrap();
+            result.append(&mut decrypt_in_chunks(&mut resumed, &encrypted[split..], 7));
+            assert_eq!(result, plain);
+        }
+    }
+
+    #[test]
+    fn test_x2m_restore_into_used_instance() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m resume key");
+        let table = conformance_table();
+        let plain = generate_test_data(0x4000, "x2m reused resume data");
+        let encrypted = encrypt(&key, &table, &plain);
+        let key: super::X2MContentKey = key.try_into().unwrap();
+
+        for split in [500, 0x2000] {
+            let mut first = Ximalaya::new("first", key, table);
+            first.write(&encrypted[..split]).unwrap();
+            let mut result = first.read_all_output();
+            let snapshot = first.snapshot();
+
+            // Ended, and lazily holding back a complete header of another
+            // stream.
+            let mut resumed = Ximalaya::new("resumed", key, table).with_lazy_header(true);
+            resumed
+                .write(&encrypted[..XMLY_SCRAMBLE_SIZE + 0x10])
+                .unwrap();
+            resumed.end().unwrap();
+            assert!(resumed.is_header_pending());
+
+            resumed.restore(&snapshot).unwrap();
+            assert!(!resumed.is_header_pending());
+            result.append(&mut decrypt_in_chunks(&mut resumed, &encrypted[split..], 7));
+            assert_eq!(result, plain);
+        }
+    }
+
+    #[test]
+    fn test_header_bytes_count_as_read() {
+        let key: super::X2MContentKey = *b"xmly";
+        let table = conformance_table();
+        let encrypted = generate_test_data(0x800, "x2m offset data");
+
+        let mut decryptor = Ximalaya::new("offset", key, table);
+        decryptor.write(&encrypted[..500]).unwrap();
+        assert_eq!(decryptor.manifest().bytes_in, 500);
+
+        // A snapshot taken inside the header records the bytes buffered.
+        let snapshot = decryptor.snapshot();
+        assert_eq!(snapshot[1..9], 500u64.to_le_bytes());
+        let mut resumed = Ximalaya::new("resumed", key, table);
+        resumed.restore(&snapshot).unwrap();
+        assert_eq!(resumed.manifest().bytes_in, 500);
+
+        let mut truncated = snapshot.clone();
+        truncated.pop();
+        assert!(resumed.restore(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_size_hint() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m hint key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m hint seed");
+        let table = super::build_scramble_table(&seed).unwrap();
+        let encrypted = generate_test_data(0x3000, "x2m hint data");
+        let key: super::X2MContentKey = key.try_into().unwrap();
+
+        let expected = decrypt_in_chunks(&mut new_x2m(key, table), &encrypted, 0x100);
+
+        let mut hinted = Ximalaya::new("hinted", key, table).with_size_hint(encrypted.len());
+        assert_eq!(hinted.get_name(), "hinted");
+        assert_eq!(decrypt_in_chunks(&mut hinted, &encrypted, 0x100), expected);
+    }
+
+    #[test]
+    fn test_lazy_header_matches_eager() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m lazy key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m lazy seed");
+        let table = super::build_scramble_table(&seed).unwrap();
+        let encrypted = generate_test_data(0x3000, "x2m lazy data");
+        let key: super::X2MContentKey = key.try_into().unwrap();
+
+        let mut eager = Ximalaya::new("eager", key, table);
+        let expected = decrypt_in_chunks(&mut eager, &encrypted, 0x100);
+
+        let mut lazy = Ximalaya::new("lazy", key, table).with_lazy_header(true);
+        lazy.write(&encrypted[..0x800]).unwrap();
+        assert!(lazy.is_header_pending());
+        assert_eq!(lazy.output_ready(), 0x800);
+
+        let mut result = lazy.read_all_output();
+        assert!(!lazy.is_header_pending());
+        result.append(&mut decrypt_in_chunks(&mut lazy, &encrypted[0x800..], 7));
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_write_owned_matches_write() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m owned key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m owned seed");
+        let table = super::build_scramble_table(&seed).unwrap();
+        let encrypted = generate_test_data(0x3000, "x2m owned data");
+        let key: super::X2MContentKey = key.try_into().unwrap();
+
+        let mut decryptor = Ximalaya::new("x2m", key, table);
+        let expected = decrypt_in_chunks(&mut decryptor, &encrypted, 0x300);
+
+        for lazy in [false, true] {
+            let mut decryptor = Ximalaya::new("x2m", key, table).with_lazy_header(lazy);
+            for chunk in encrypted.chunks(0x300) {
+                decryptor.write_owned(chunk.to_vec()).unwrap();
+            }
+            decryptor.end().unwrap();
+            assert_eq!(decryptor.read_all_output(), expected);
+        }
+    }
+
+    #[test]
+    fn test_deterministic_after_reset() {
+        let x2m_key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m reset key");
+        let x3m_key = generate_test_data(X3M_CONTENT_KEY_SIZE, "x3m reset key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "ximalaya reset seed");
+        let table = super::build_scramble_table(&seed).unwrap();
+        let data = generate_test_data(0x3000, "ximalaya reset data");
+
+        assert_deterministic_after_reset(
+            || super::new_x2m(x2m_key[..].try_into().unwrap(), table),
+            &data,
+        );
+        assert_deterministic_after_reset(
+            || super::new_x3m(x3m_key[..].try_into().unwrap(), table),
+            &data,
+        );
+        assert_deterministic_after_reset(
+            || {
+                Ximalaya::<super::X2MContentKey>::new(
+                    "lazy",
+                    x2m_key[..].try_into().unwrap(),
+                    table,
+                )
+                .with_lazy_header(true)
+            },
+            &data,
+        );
+    }
+
+    #[test]
+    fn test_debug_keystream() {
+        let decryptor = Ximalaya::new("x2m", *b"\x01\x02\x03\x04", [0; XMLY_SCRAMBLE_SIZE]);
+        let keystream = decryptor.debug_keystream();
+        for chunk in keystream.chunks(X2M_CONTENT_KEY_SIZE) {
+            assert_eq!(chunk, [1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn test_x2m_restore_invalid_snapshot() {
+        let mut decryptor =
+            Ximalaya::new("x2m", [0u8; X2M_CONTENT_KEY_SIZE], [0; XMLY_SCRAMBLE_SIZE]);
+        for snapshot in [&[][..], &[1, 0, 0], &[1, 0, 0, 0, 0, 0, 0, 0, 0], &[2; 9]] {
+            let err = decryptor.restore(snapshot).unwrap_err();
+            assert!(matches!(err.code(), DecryptErrorCode::InvalidSnapshot));
+        }
+    }
+
+    #[test]
+    fn test_scramble_seed_too_small() {
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2 - 1, "short seed");
+
+        let err = super::build_scramble_table(&seed).unwrap_err();
+        assert!(matches!(
+            err.code(),
+            DecryptErrorCode::XimalayaScrambleSeedTooSmall
+        ));
+    }
+
+    #[test]
+    fn test_x2m_roundtrip() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m roundtrip key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m roundtrip seed");
+        let table = super::build_scramble_table(&seed).unwrap();
+
+        verify_roundtrip(
+            |plain| encrypt(&key, &table, plain),
+            || super::new_x2m(key[..].try_into().unwrap(), table),
+            &[XMLY_SCRAMBLE_SIZE, XMLY_SCRAMBLE_SIZE + 1, 0x8000 + 17],
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    #[tracing_test::traced_test]
+    fn test_x2m_traces_state_transition() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m tracing key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m tracing seed");
+        let table = super::build_scramble_table(&seed).unwrap();
+
+        let mut decryptor = super::new_x2m(key[..].try_into().unwrap(), table);
+        decryptor.write(&[0u8; XMLY_SCRAMBLE_SIZE + 1]).unwrap();
+
+        assert!(logs_contain("DecryptHeader -> PassThrough"));
+    }
+
+    #[test]
+    fn test_x3m_roundtrip() {
+        let key = generate_test_data(X3M_CONTENT_KEY_SIZE, "x3m roundtrip key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x3m roundtrip seed");
+        let table = super::build_scramble_table(&seed).unwrap();
+
+        verify_roundtrip(
+            |plain| encrypt(&key, &table, plain),
+            || super::new_x3m(key[..].try_into().unwrap(), table),
+            &[XMLY_SCRAMBLE_SIZE, XMLY_SCRAMBLE_SIZE + 1, 0x8000 + 17],
+        );
+    }
+
+    #[test]
+    fn test_x2m_decrypt_range() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m range key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m range seed");
+        let table = super::build_scramble_table(&seed).unwrap();
+        let plain = generate_test_data(TEST_SIZE_1MB, "x2m range data");
+        let encrypted = encrypt(&key, &table, &plain);
+
+        let decryptor = super::new_x2m(key[..].try_into().unwrap(), table);
+        let mut buf = Vec::from(&encrypted[0x8000..0x9000]);
+        decryptor.decrypt_range(0x8000..0x9000, &mut buf).unwrap();
+        assert_eq!(buf, &plain[0x8000..0x9000]);
+
+        let mut buf = Vec::from(&encrypted[0x100..0x500]);
+        let err = decryptor.decrypt_range(0x100..0x500, &mut buf).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::RangeNotSupported));
+    }
+
+    #[test]
+    fn test_validate_key() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "validate key");
+        let table =
+            super::build_scramble_table(&generate_test_data(2048, "validate seed")).unwrap();
+        let mut plain = generate_test_data(XMLY_SCRAMBLE_SIZE, "validate plain");
+        plain[..4].copy_from_slice(b"fLaC");
+        let header: [u8; XMLY_SCRAMBLE_SIZE] = encrypt(&key, &table, &plain).try_into().unwrap();
+
+        assert!(super::validate_key(&key, &table, &header, CombineOp::Xor));
+
+        let mut wrong_key = key.clone();
+        wrong_key[0] ^= 0xff;
+        assert!(!super::validate_key(
+            &wrong_key,
+            &table,
+            &header,
+            CombineOp::Xor
+        ));
+        assert!(!super::validate_key(&[], &table, &header, CombineOp::Xor));
+
+        let header: [u8; XMLY_SCRAMBLE_SIZE] =
+            encrypt_additive(&key, &table, &plain).try_into().unwrap();
+        assert!(super::validate_key(
+            &key,
+            &table,
+            &header,
+            CombineOp::AddMod256
+        ));
+        assert!(!super::validate_key(&key, &table, &header, CombineOp::Xor));
+    }
+
+    #[test]
+    fn test_new_ximalaya_by_key_length() {
+        let table = super::build_scramble_table(&generate_test_data(2048, "x2m seed")).unwrap();
+        let test_data = generate_test_data(0x3000, "new ximalaya data");
+
+        let x2m_key = generate_test_data(X2M_CONTENT_KEY_SIZE, "new ximalaya key");
+        let mut decryptor = super::new_ximalaya(&x2m_key, table).unwrap();
+        assert_eq!(decryptor.get_name(), "Ximalaya(X2M)");
+        assert_eq!(
+            decrypt_in_chunks(decryptor.as_mut(), &test_data, 0x100),
+            decrypt_in_chunks(
+                &mut new_x2m(x2m_key.try_into().unwrap(), table),
+                &test_data,
+                0
+            )
+        );
+
+        let x3m_key = generate_test_data(X3M_CONTENT_KEY_SIZE, "new ximalaya key");
+        let mut decryptor = super::new_ximalaya(&x3m_key, table).unwrap();
+        assert_eq!(decryptor.get_name(), "Ximalaya(X3M)");
+        assert_eq!(
+            decrypt_in_chunks(decryptor.as_mut(), &test_data, 0x100),
+            decrypt_in_chunks(
+                &mut new_x3m(x3m_key.try_into().unwrap(), table),
+                &test_data,
+                0
+            )
+        );
+
+        let err = super::new_ximalaya(&[0u8; 16], table).err().unwrap();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidKeyLength));
+    }
+
+    #[test]
+    fn test_x2m_end_without_header() {
+        let key = generate_test_data(X2M_CONTENT_KEY_SIZE, "x2m content key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "x2m seed");
+        let table = super::build_scramble_table(&seed).unwrap();
+
+        let mut decryptor = super::new_x2m(key[..].try_into().unwrap(), table);
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::EmptyInput));
+
+        let mut decryptor = super::new_x2m(key[..].try_into().unwrap(), table);
+        decryptor.write(&[0u8; XMLY_SCRAMBLE_SIZE - 1]).unwrap();
+        let err = decryptor.end().unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::NotEnoughData));
+    }
 }
@@ -1,3 +1,7 @@
+use std::{any::Any, ops::Range};
+
+use crate::utils::{audio::AudioType, md5::Md5};
+
 #[derive(Debug)]
 pub struct BaseDecryptorData {
     pub(crate) name: String,
@@ -5,9 +9,48 @@ pub struct BaseDecryptorData {
     pub(crate) buf_in: Vec<u8>,
     pub(crate) buf_out: Vec<u8>,
     pub(crate) reserve_eof: usize,
+    /// Length of a trailing footer the decryptor drops itself, see
+    /// [`write_holding_back_footer`].
+    #[cfg_attr(not(feature = "qmc"), allow(dead_code))]
+    pub(crate) footer_len: usize,
+    /// The last (up to) `footer_len` bytes of input seen so far.
+    pub(crate) footer: Vec<u8>,
+    pub(crate) output_shrink_factor: usize,
+    /// Set by `end`; further writes are refused until a `reset`.
+    pub(crate) finalized: bool,
 }
 
-#[derive(Debug)]
+/// `consume_output` gives memory back once the output buffer capacity exceeds
+/// this many times its length.
+pub const DEFAULT_OUTPUT_SHRINK_FACTOR: usize = 4;
+
+/// Capacity the output buffer may keep regardless of the shrink factor, so
+/// small streams do not reallocate on every drain.
+const OUTPUT_SHRINK_MIN_CAPACITY: usize = 64 * 1024;
+/// Input buffer reserved by [`BaseDecryptorData::new_with_capacity`]: the
+/// largest fixed header (Kugou, Kuwo and Ximalaya use up to 0x400 bytes).
+#[cfg_attr(not(any(feature = "ncm", feature = "ximalaya")), allow(dead_code))]
+const MIN_HEADER_BYTES: usize = 0x400;
+
+/// Take up to `len` bytes from the front of `buf`, then shrink `buf` if its
+/// capacity exceeds `shrink_factor` times its length (`0` never shrinks).
+pub(crate) fn consume_output_buffer(
+    buf: &mut Vec<u8>,
+    len: usize,
+    shrink_factor: usize,
+) -> Vec<u8> {
+    let len = len.min(buf.len());
+    let result = buf.drain(..len).collect();
+    if shrink_factor != 0
+        && buf.capacity() > OUTPUT_SHRINK_MIN_CAPACITY
+        && buf.capacity() / shrink_factor > buf.len()
+    {
+        buf.shrink_to(buf.len().max(OUTPUT_SHRINK_MIN_CAPACITY));
+    }
+    result
+}
+
+#[derive(Debug, Clone)]
 pub enum DecryptErrorCode {
     UnknownEncryption,
     UnknownMagicHeader,
@@ -15,10 +58,48 @@ pub enum DecryptErrorCode {
     AESParamError,
     NCMInvalidContentKey,
     NCMCoverFrameTooSmall,
+    XimalayaInvalidScrambleTable,
+    XimalayaScrambleSeedTooSmall,
+    IOError,
+    InvalidKeyEncoding,
+    InvalidKeyLength,
+    MissingKey,
+    RangeNotSupported,
+    /// `end` was reached without any input.
+    EmptyInput,
+    /// `end` was reached before the header was complete.
+    NotEnoughData,
+    /// The decrypted output is not the expected audio type, e.g. because the
+    /// key is wrong.
+    UnexpectedAudioFormat {
+        expected: AudioType,
+        got: AudioType,
+    },
+    /// The file format (e.g. an extension) is not known.
+    UnsupportedFormat,
+    /// A size or offset parsed from the header is out of range.
+    InvalidHeaderField {
+        field: &'static str,
+        value: u64,
+    },
+    /// A `ResumableDecryptor` snapshot is malformed.
+    InvalidSnapshot,
+    /// The decryptor can not be `reset`.
+    ResetNotSupported,
+    /// More than `limit` bytes of input were written.
+    InputTooLarge {
+        limit: u64,
+    },
+    /// A Kugou mask table does not have the expected length.
+    InvalidMaskTable,
+    /// `write` was called after `end`, without a `reset` in between.
+    WriteAfterFinalize,
+    /// A key derivation parameter, e.g. the PBKDF2 iteration count, is out of
+    /// range.
+    InvalidKeyDerivation,
 }
 
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Debug, Clone)]
 pub struct DecryptError {
     code: DecryptErrorCode,
     str: String,
@@ -31,6 +112,62 @@ impl DecryptError {
             str: str.into(),
         }
     }
+
+    pub fn code(&self) -> &DecryptErrorCode {
+        &self.code
+    }
+}
+
+impl std::fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.str)
+    }
+}
+
+impl std::error::Error for DecryptError {}
+
+/// Surfaces decryption failures from `Read`/`Write` adapters as
+/// `InvalidData`, keeping the `DecryptError` as the inner error.
+impl From<DecryptError> for std::io::Error {
+    fn from(err: DecryptError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// A record of what a decryptor did, e.g. for a batch job log.
+///
+/// Only ever holds a fingerprint of the key, never the key itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DecryptManifest {
+    pub format: String,
+    /// See [`key_fingerprint`]. `None` for formats without a key.
+    pub key_fingerprint: Option<String>,
+    /// Bytes of input that are header rather than audio, once known.
+    pub header_len: u64,
+    /// Bytes of input processed so far.
+    pub bytes_in: u64,
+}
+
+impl DecryptManifest {
+    pub fn new(format: &str) -> Self {
+        Self {
+            format: String::from(format),
+            key_fingerprint: None,
+            header_len: 0,
+            bytes_in: 0,
+        }
+    }
+}
+
+/// Short identifier of a key: the first 8 bytes of its MD5, hex encoded.
+///
+/// Tells keys apart without revealing them, though very short keys (e.g. the
+/// 4 byte X2M key) can still be brute-forced from it.
+pub fn key_fingerprint(key: &[u8]) -> String {
+    let mut md5 = Md5::new();
+    md5.update(key);
+    data_encoding::HEXLOWER.encode(&md5.finish()[..8])
 }
 
 impl BaseDecryptorData {
@@ -42,21 +179,66 @@ impl BaseDecryptorData {
             offset: 0,
             name: String::from(name),
             reserve_eof: 0,
+            footer_len: 0,
+            footer: vec![],
+            output_shrink_factor: DEFAULT_OUTPUT_SHRINK_FACTOR,
+            finalized: false,
+        }
+    }
+
+    /// Like [`BaseDecryptorData::new`], with the output buffer pre-sized for
+    /// `input_hint` bytes of input and the input buffer for a header, to avoid
+    /// reallocating on a known-size decrypt.
+    ///
+    /// Offered by `NcmBuilder::with_size_hint` and `Ximalaya::with_size_hint`.
+    /// The Kuwo, Kugou and QMC factories take no options and do not take a
+    /// hint.
+    #[cfg_attr(not(any(feature = "ncm", feature = "ximalaya")), allow(dead_code))]
+    pub(crate) fn new_with_capacity(name: &str, input_hint: usize) -> Self {
+        BaseDecryptorData {
+            buf_in: Vec::with_capacity(MIN_HEADER_BYTES),
+            buf_out: Vec::with_capacity(input_hint),
+            ..Self::new(name)
+        }
+    }
+
+    /// A manifest for this decryptor, with the input offset as bytes seen.
+    pub(crate) fn manifest(&self, key: Option<&[u8]>, header_len: usize) -> DecryptManifest {
+        DecryptManifest {
+            key_fingerprint: key.map(key_fingerprint),
+            header_len: header_len as u64,
+            bytes_in: self.offset as u64,
+            ..DecryptManifest::new(&self.name)
         }
     }
 
+    /// For files ending with a `footer_len` bytes footer (e.g. QMC), which is
+    /// not part of the stream and is left out of the output.
     #[inline(always)]
-    pub(crate) fn new_with_eof_reserve(name: &str, reserve_len: usize) -> Self {
+    #[cfg_attr(not(feature = "qmc"), allow(dead_code))]
+    pub(crate) fn new_with_footer(name: &str, footer_len: usize) -> Self {
         BaseDecryptorData {
-            buf_in: vec![],
-            buf_out: vec![],
-            offset: 0,
-            name: String::from(name),
-            reserve_eof: reserve_len,
+            footer_len,
+            ..Self::new(name)
         }
     }
 
+    /// Buffer input until `offset` bytes have been read.
+    ///
+    /// The remaining bytes up to `offset` are reserved up front, so filling a
+    /// header through many tiny writes costs a single allocation and is linear
+    /// in the header size.
     #[inline(always)]
+    #[cfg_attr(
+        not(any(
+            feature = "joox",
+            feature = "kugou",
+            feature = "kuwo",
+            feature = "ncm",
+            feature = "qmc"
+        )),
+        allow(dead_code)
+    )]
     pub(crate) fn read_until_offset(&mut self, data: &mut &[u8], offset: usize) -> bool {
         if self.offset < offset {
             let read_size = std::cmp::min(data.len(), offset - self.offset);
@@ -64,6 +246,8 @@ impl BaseDecryptorData {
                 return false;
             }
 
+            self.buf_in.reserve(offset - self.offset);
+
             let (to_buffer, left_over) = data.split_at(read_size);
             self.buf_in.extend_from_slice(to_buffer);
             *data = left_over;
@@ -74,6 +258,7 @@ impl BaseDecryptorData {
     }
 
     #[inline(always)]
+    #[cfg_attr(not(any(feature = "joox", feature = "ncm")), allow(dead_code))]
     pub(crate) fn read_block(&mut self, data: &mut &[u8], size: usize) -> bool {
         if self.buf_in.len() < size {
             let read_size = std::cmp::min(data.len(), size - self.buf_in.len());
@@ -90,17 +275,62 @@ impl BaseDecryptorData {
     }
 
     #[inline(always)]
+    #[cfg_attr(
+        not(any(
+            feature = "joox",
+            feature = "kugou",
+            feature = "kuwo",
+            feature = "ncm",
+            feature = "qmc"
+        )),
+        allow(dead_code)
+    )]
     pub(crate) fn seek_input(&mut self, len: usize) {
         self.buf_in.drain(..len);
     }
 
     #[inline(always)]
+    #[cfg_attr(not(any(feature = "ncm", feature = "qmc")), allow(dead_code))]
     pub(crate) fn consume_input(&mut self, len: usize) -> Vec<u8> {
         let result = Vec::from(&self.buf_in[..len]);
         self.seek_input(len);
         result
     }
 
+    /// Error out at `end` unless `header_complete`, telling an empty input
+    /// apart from a truncated header.
+    #[cfg_attr(
+        not(any(feature = "joox", feature = "kugou", feature = "kuwo", feature = "ncm")),
+        allow(dead_code)
+    )]
+    pub(crate) fn ensure_header_complete(&self, header_complete: bool) -> Result<(), DecryptError> {
+        ensure_header_complete(header_complete, self.offset + self.buf_in.len())
+    }
+
+    pub(crate) fn mark_finalized(&mut self) {
+        self.finalized = true;
+    }
+
+    /// Refuse input once the stream ended.
+    pub(crate) fn ensure_not_finalized(&self) -> Result<(), DecryptError> {
+        if self.finalized {
+            return Err(DecryptError::new(
+                DecryptErrorCode::WriteAfterFinalize,
+                "write after end",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Back to the state of a fresh decryptor, keeping the name and settings.
+    pub(crate) fn reset(&mut self) {
+        self.finalized = false;
+        self.offset = 0;
+        self.buf_in.clear();
+        self.buf_out.clear();
+        self.footer.clear();
+    }
+
     #[inline(always)]
     pub(crate) fn read_all_output(&mut self) -> Vec<u8> {
         let mut result: Vec<u8> = vec![];
@@ -109,6 +339,96 @@ impl BaseDecryptorData {
     }
 }
 
+/// Pass `data` to `write_body`, holding back the last `footer_len` bytes of
+/// input seen so far as they may be the footer; the footer itself is dropped
+/// once the stream ends.
+///
+/// Held-back bytes now known to come before the footer are written first,
+/// straight from the footer buffer, then the borrowed part of `data`, so
+/// nothing is copied for the call.
+#[cfg_attr(not(feature = "qmc"), allow(dead_code))]
+pub(crate) fn write_holding_back_footer<D>(
+    decryptor: &mut D,
+    data: &[u8],
+    base: fn(&mut D) -> &mut BaseDecryptorData,
+    mut write_body: impl FnMut(&mut D, &[u8]),
+) {
+    let footer_len = base(decryptor).footer_len;
+    if footer_len == 0 {
+        write_body(decryptor, data);
+        return;
+    }
+
+    let mut footer = std::mem::take(&mut base(decryptor).footer);
+    let release = (footer.len() + data.len()).saturating_sub(footer_len);
+    let from_footer = release.min(footer.len());
+    let (from_data, rest) = data.split_at(release - from_footer);
+
+    if from_footer > 0 {
+        write_body(decryptor, &footer[..from_footer]);
+        footer.drain(..from_footer);
+    }
+    if !from_data.is_empty() {
+        write_body(decryptor, from_data);
+    }
+    footer.extend_from_slice(rest);
+    base(decryptor).footer = footer;
+}
+
+/// Debug checks for the end of a successful `write`, given the offset and
+/// output length from its start: output only grows until it is drained, and
+/// the input offset never moves back.
+#[inline(always)]
+pub(crate) fn check_invariants(
+    data: &BaseDecryptorData,
+    prev_offset: usize,
+    prev_output_len: usize,
+) {
+    debug_assert!(
+        data.offset >= prev_offset,
+        "{}: offset moved back from {} to {}",
+        data.name,
+        prev_offset,
+        data.offset
+    );
+    debug_assert!(
+        data.buf_out.len() >= prev_output_len,
+        "{}: output shrank from {} to {} bytes",
+        data.name,
+        prev_output_len,
+        data.buf_out.len()
+    );
+}
+
+/// Error out at `end` unless `header_complete`, telling an empty input
+/// (`received == 0`) apart from a truncated header.
+pub(crate) fn ensure_header_complete(
+    header_complete: bool,
+    received: usize,
+) -> Result<(), DecryptError> {
+    if header_complete {
+        Ok(())
+    } else if received == 0 {
+        Err(DecryptError::new(
+            DecryptErrorCode::EmptyInput,
+            "no data was written",
+        ))
+    } else {
+        Err(DecryptError::new(
+            DecryptErrorCode::NotEnoughData,
+            "input ended before the header was complete",
+        ))
+    }
+}
+
+#[cfg_attr(not(any(feature = "kugou", feature = "ncm")), allow(dead_code))]
+pub(crate) fn invalid_header_field(field: &'static str, value: u64) -> DecryptError {
+    DecryptError::new(
+        DecryptErrorCode::InvalidHeaderField { field, value },
+        &format!("header field {} out of range: {}", field, value),
+    )
+}
+
 pub trait Decryptor {
     fn write(&mut self, data: &[u8]) -> Result<(), DecryptError>;
     #[inline(always)]
@@ -116,11 +436,248 @@ pub trait Decryptor {
         Ok(())
     }
 
+    /// Like `write`, for input owned by the caller. Decryptors that pass data
+    /// through unchanged can move `chunk` into the output instead of copying.
+    fn write_owned(&mut self, chunk: Vec<u8>) -> Result<(), DecryptError> {
+        self.write(&chunk)
+    }
+
     fn get_name(&self) -> &str;
     fn read_all_output(&mut self) -> Vec<u8>;
     fn get_eof_reserve(&self) -> usize;
+
+    /// Number of bytes `read_all_output` would return that are final and safe
+    /// to forward. Input held back until a block or segment boundary is not
+    /// counted: it only reaches the output once complete.
+    ///
+    /// The default reports nothing ready, so callers fall back to draining
+    /// the output with `read_all_output` after `end`.
+    fn output_ready(&self) -> usize {
+        0
+    }
+
+    /// Take up to `len` bytes of output, keeping the rest buffered.
+    ///
+    /// The default takes nothing, matching the default `output_ready`.
+    fn consume_output(&mut self, _len: usize) -> Vec<u8> {
+        vec![]
+    }
+
+    /// Tune when `consume_output` returns buffer memory: once its capacity
+    /// exceeds `factor` times the bytes still buffered. `0` never shrinks.
+    fn set_output_shrink_factor(&mut self, _factor: usize) {}
+
+    /// Precompute any derived tables up front, so the first `write` does not
+    /// pay for them. Output is unaffected.
+    #[inline(always)]
+    fn warmup(&mut self) {}
+
+    /// Check the key material the decryptor was built with (e.g. scramble
+    /// table shape), so obviously wrong keys fail before any input is read.
+    /// Problems only visible in the input (e.g. a header checksum) are still
+    /// reported by `write`.
+    fn validate_config(&self) -> Result<(), DecryptError> {
+        Ok(())
+    }
+
+    /// Input bytes still needed to finish the current phase (e.g. a header
+    /// decrypted as a whole), so callers can size their next read to match.
+    /// `None` once input is decrypted as it arrives.
+    fn bytes_until_next_phase(&self) -> Option<usize> {
+        None
+    }
+
+    fn as_seekable(&self) -> Option<&dyn SeekableDecryptor> {
+        None
+    }
+
+    /// Offset in the output at which the audio itself begins. Only decryptors
+    /// emitting something ahead of the audio (e.g. a recovered container
+    /// header) report a non-zero value; every format in this crate outputs
+    /// audio from the first byte.
+    fn audio_start_offset(&self) -> u64 {
+        0
+    }
+
+    /// Describe the job so far, for reproducibility and audit logs. Never
+    /// contains key material, only a [`key_fingerprint`].
+    fn manifest(&self) -> DecryptManifest {
+        DecryptManifest::new(self.get_name())
+    }
+
+    /// Return to the initial state, dropping buffered input and output, so the
+    /// decryptor can be reused for another file with the same keys.
+    fn reset(&mut self) -> Result<(), DecryptError> {
+        Err(DecryptError::new(
+            DecryptErrorCode::ResetNotSupported,
+            "decryptor can not be reset",
+        ))
+    }
+
+    /// Box an (often opaque) decryptor into a trait object.
+    ///
+    /// ```
+    /// use parakeet_core::{decryption::new_passthrough, decryptor::Decryptor};
+    ///
+    /// let decryptors: Vec<Box<dyn Decryptor>> = vec![new_passthrough().into_boxed()];
+    /// assert_eq!(decryptors[0].get_name(), "PassThrough");
+    /// ```
+    fn into_boxed(self) -> Box<dyn Decryptor>
+    where
+        Self: Sized + 'static,
+    {
+        Box::new(self)
+    }
+
+    /// Access the concrete decryptor behind a `dyn Decryptor`, for use with
+    /// `downcast_ref`. Decryptors not exposing their type return `&()`.
+    fn as_any(&self) -> &dyn Any {
+        &()
+    }
+
+    /// Decrypt the bytes at `range` of the encrypted file, independent of the
+    /// streaming state. `buf` holds the encrypted bytes and is decrypted in
+    /// place.
+    fn decrypt_range(&self, range: Range<u64>, buf: &mut [u8]) -> Result<(), DecryptError> {
+        let seekable = self.as_seekable().ok_or_else(|| {
+            DecryptError::new(
+                DecryptErrorCode::RangeNotSupported,
+                "decryptor is not seekable",
+            )
+        })?;
+
+        if range.end < range.start || (range.end - range.start) != buf.len() as u64 {
+            return Err(DecryptError::new(
+                DecryptErrorCode::InvalidBlockSize,
+                "range does not match buffer size",
+            ));
+        }
+
+        seekable.decrypt_at(range.start, buf)
+    }
+}
+
+/// Decryptors able to decrypt bytes at an arbitrary offset of the file.
+pub trait SeekableDecryptor {
+    /// Decrypt `buf` in place, `buf` holding the encrypted bytes found at
+    /// `offset` of the file.
+    fn decrypt_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), DecryptError>;
+
+    /// Offset from which `decrypt_at` works, past any header that can only be
+    /// decrypted as a whole.
+    fn first_seekable_offset(&self) -> u64 {
+        0
+    }
+}
+
+/// Decryptors whose streaming state can be saved, so an interrupted job can
+/// resume where it stopped instead of starting over.
+///
+/// After `restore`, feed the input starting right after the bytes written
+/// before the `snapshot` was taken.
+pub trait ResumableDecryptor {
+    /// Serialize the minimal state needed to resume.
+    fn snapshot(&self) -> Vec<u8>;
+
+    /// Resume from a `snapshot` taken from a decryptor of the same type and
+    /// keys. Pending output is not part of the snapshot.
+    fn restore(&mut self, snapshot: &[u8]) -> Result<(), DecryptError>;
+}
+
+/// Conveniences available on every decryptor, including `dyn Decryptor`.
+pub trait DecryptorExt {
+    /// Decrypt a whole in-memory file, feeding `data` in pieces of `chunk`
+    /// bytes and handing each piece of output to `on_output` as soon as it is
+    /// ready, so the output is never held in full. Calls `end` once all of
+    /// `data` has been written; like [`crate::io`] streaming, the trailing
+    /// [`Decryptor::get_eof_reserve`] bytes are left out.
+    fn write_all_chunked(
+        &mut self,
+        data: &[u8],
+        chunk: usize,
+        on_output: impl FnMut(&[u8]),
+    ) -> Result<(), DecryptError>;
+}
+
+impl<D: Decryptor + ?Sized> DecryptorExt for D {
+    fn write_all_chunked(
+        &mut self,
+        data: &[u8],
+        chunk: usize,
+        mut on_output: impl FnMut(&[u8]),
+    ) -> Result<(), DecryptError> {
+        if chunk == 0 {
+            return Err(DecryptError::new(
+                DecryptErrorCode::InvalidBlockSize,
+                "chunk size must not be zero",
+            ));
+        }
+
+        // As with streaming, the trailing `get_eof_reserve()` bytes are not
+        // part of the encrypted stream and are never written.
+        let data = &data[..data.len().saturating_sub(self.get_eof_reserve())];
+        for piece in data.chunks(chunk) {
+            self.write(piece)?;
+            let output = self.consume_output(self.output_ready());
+            if !output.is_empty() {
+                on_output(&output);
+            }
+        }
+
+        self.end()?;
+        let output = self.read_all_output();
+        if !output.is_empty() {
+            on_output(&output);
+        }
+        Ok(())
+    }
+}
+
+/// Opens a `debug` span covering a single `write` call, recording the current
+/// offset (and state, when the decryptor has one).
+macro_rules! trace_write_span {
+    ($self:ident) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "write",
+            decryptor = %$self.data.name,
+            offset = $self.data.offset
+        )
+        .entered();
+    };
+    ($self:ident, state) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "write",
+            decryptor = %$self.data.name,
+            offset = $self.data.offset,
+            state = ?$self.state
+        )
+        .entered();
+    };
 }
 
+/// Moves the decryptor to the next state, emitting an `info` event for the
+/// transition.
+#[allow(unused_macros)] // Only stateful formats use it.
+macro_rules! transition_state {
+    ($self:ident, $next:expr) => {{
+        let next = $next;
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            decryptor = %$self.data.name,
+            offset = $self.data.offset,
+            "{:?} -> {:?}",
+            $self.state,
+            next
+        );
+        $self.state = next;
+    }};
+}
+
+#[allow(unused_imports)]
+pub(crate) use {trace_write_span, transition_state};
+
 mod detail {
     #[macro_export]
     macro_rules! impl_decryptor_inner_helper {
@@ -139,6 +696,216 @@ mod detail {
             fn get_eof_reserve(&self) -> usize {
                 self.data.reserve_eof
             }
+
+            #[inline(always)]
+            fn output_ready(&self) -> usize {
+                self.data.buf_out.len()
+            }
+
+            fn consume_output(&mut self, len: usize) -> Vec<u8> {
+                $crate::decryptor::consume_output_buffer(
+                    &mut self.data.buf_out,
+                    len,
+                    self.data.output_shrink_factor,
+                )
+            }
+
+            fn set_output_shrink_factor(&mut self, factor: usize) {
+                self.data.output_shrink_factor = factor;
+            }
         };
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        check_invariants, consume_output_buffer, BaseDecryptorData, DecryptError, DecryptErrorCode,
+        Decryptor, DecryptorExt, MIN_HEADER_BYTES,
+    };
+
+    #[test]
+    fn test_new_with_capacity() {
+        let data = BaseDecryptorData::new_with_capacity("test", 0x10000);
+        assert!(data.buf_out.capacity() >= 0x10000);
+        assert!(data.buf_in.capacity() >= MIN_HEADER_BYTES);
+        assert!(data.buf_out.is_empty() && data.buf_in.is_empty());
+        assert_eq!(data.name, "test");
+        assert_eq!(data.offset, 0);
+    }
+
+    #[test]
+    fn test_decrypt_error_through_io_error() {
+        let err = DecryptError::new(DecryptErrorCode::UnknownMagicHeader, "unknown magic header");
+        let io_err = std::io::Error::from(err);
+        assert_eq!(io_err.kind(), std::io::ErrorKind::InvalidData);
+
+        let inner = io_err
+            .into_inner()
+            .unwrap()
+            .downcast::<DecryptError>()
+            .unwrap();
+        assert!(matches!(inner.code(), DecryptErrorCode::UnknownMagicHeader));
+        assert_eq!(
+            inner.to_string(),
+            "UnknownMagicHeader: unknown magic header"
+        );
+    }
+
+    #[test]
+    fn test_decrypt_error_boxed() {
+        fn fails() -> Result<(), Box<dyn std::error::Error>> {
+            Err(DecryptError::new(
+                DecryptErrorCode::MissingKey,
+                "missing key",
+            ))?;
+            Ok(())
+        }
+
+        let err = fails().unwrap_err();
+        assert!(err.downcast_ref::<DecryptError>().is_some());
+    }
+
+    #[test]
+    fn test_consume_output_shrinks_buffer() {
+        let mut buf = vec![0u8; 0x100_0000];
+        let output = consume_output_buffer(&mut buf, 0x100_0000 - 0x100, 4);
+        assert_eq!(output.len(), 0x100_0000 - 0x100);
+        assert_eq!(buf.len(), 0x100);
+        assert!(buf.capacity() <= super::OUTPUT_SHRINK_MIN_CAPACITY);
+
+        let mut buf = vec![0u8; 0x100_0000];
+        consume_output_buffer(&mut buf, 0x100_0000 - 0x100, 0);
+        assert_eq!(buf.capacity(), 0x100_0000);
+
+        consume_output_buffer(&mut buf, 0, super::DEFAULT_OUTPUT_SHRINK_FACTOR);
+        assert!(buf.capacity() <= super::OUTPUT_SHRINK_MIN_CAPACITY);
+    }
+
+    /// A decryptor implementing only the required methods, with an EOF
+    /// reserve of `.1` bytes.
+    struct BareDecryptor(Vec<u8>, usize);
+
+    impl Decryptor for BareDecryptor {
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            self.0.extend_from_slice(data);
+            Ok(())
+        }
+
+        fn get_name(&self) -> &str {
+            "bare"
+        }
+
+        fn read_all_output(&mut self) -> Vec<u8> {
+            std::mem::take(&mut self.0)
+        }
+
+        fn get_eof_reserve(&self) -> usize {
+            self.1
+        }
+    }
+
+    #[test]
+    fn test_default_output_draining() {
+        let mut decryptor = BareDecryptor(vec![], 0);
+        decryptor.write(b"data").unwrap();
+        assert_eq!(decryptor.output_ready(), 0);
+        assert!(decryptor.consume_output(4).is_empty());
+
+        // Output still arrives in full, drained after `end`.
+        let mut output = vec![];
+        let mut decryptor = BareDecryptor(vec![], 0);
+        decryptor
+            .write_all_chunked(b"chunked data", 5, |p| output.extend_from_slice(p))
+            .unwrap();
+        assert_eq!(output, b"chunked data");
+    }
+
+    #[test]
+    fn test_write_all_chunked_skips_eof_reserve() {
+        for (data, expected) in [(&b"data+footer"[..], &b"data"[..]), (b"foot", b"")] {
+            let mut output = vec![];
+            let mut decryptor = BareDecryptor(vec![], 7);
+            decryptor
+                .write_all_chunked(data, 3, |p| output.extend_from_slice(p))
+                .unwrap();
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_read_until_offset_reserves_header() {
+        let mut data = BaseDecryptorData::new("test");
+
+        let mut p: &[u8] = &[0u8; 1];
+        assert!(!data.read_until_offset(&mut p, 1024));
+        let capacity = data.buf_in.capacity();
+        assert!(capacity >= 1024);
+
+        for _ in 1..1024 {
+            let mut p: &[u8] = &[0u8; 1];
+            data.read_until_offset(&mut p, 1024);
+        }
+        assert_eq!(data.offset, 1024);
+        assert_eq!(data.buf_in.capacity(), capacity);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "test: offset moved back from 32 to 16")]
+    fn test_check_invariants_offset() {
+        let mut data = BaseDecryptorData::new("test");
+        data.offset = 0x10;
+        check_invariants(&data, 0x20, 0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "test: output shrank from 8 to 4 bytes")]
+    fn test_check_invariants_output() {
+        let mut data = BaseDecryptorData::new("test");
+        data.buf_out = vec![0u8; 4];
+        check_invariants(&data, 0, 8);
+    }
+
+    #[test]
+    fn test_write_all_chunked_rejects_zero_chunk() {
+        let mut decryptor = crate::decryption::new_passthrough();
+        let err = decryptor.write_all_chunked(b"data", 0, |_| {}).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidBlockSize));
+    }
+
+    #[cfg(feature = "ximalaya")]
+    #[test]
+    fn test_write_all_chunked_matches_single_write() {
+        use crate::{
+            decryption::ximalaya::{build_scramble_table, new_x3m, XMLY_SCRAMBLE_SIZE},
+            decryptor::Decryptor,
+            utils::test_util::test::generate_test_data,
+        };
+
+        let key = generate_test_data(32, "chunked key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "chunked seed");
+        let table = build_scramble_table(&seed).unwrap();
+        let encrypted = generate_test_data(0x4321, "chunked data");
+
+        let mut decryptor = new_x3m(key[..].try_into().unwrap(), table);
+        decryptor.write(&encrypted).unwrap();
+        decryptor.end().unwrap();
+        let expected = decryptor.read_all_output();
+
+        let mut decryptor: Box<dyn Decryptor> =
+            new_x3m(key[..].try_into().unwrap(), table).into_boxed();
+        let mut pieces = 0;
+        let mut output = vec![];
+        decryptor
+            .write_all_chunked(&encrypted, 0x100, |piece| {
+                pieces += 1;
+                output.extend_from_slice(piece);
+            })
+            .unwrap();
+
+        assert_eq!(output, expected);
+        assert!(pieces > 1);
+    }
+}
@@ -0,0 +1,62 @@
+use bytes::Bytes;
+
+use crate::decryptor::{DecryptError, Decryptor};
+
+/// `bytes` integration for any decryptor.
+///
+/// Output is handed off by converting the drained `Vec` into `Bytes`, which
+/// takes ownership of the allocation instead of copying it.
+pub trait BytesDecryptorExt {
+    fn write_bytes_buf(&mut self, data: Bytes) -> Result<(), DecryptError>;
+
+    fn read_output_bytes(&mut self) -> Bytes;
+}
+
+impl<D: Decryptor + ?Sized> BytesDecryptorExt for D {
+    fn write_bytes_buf(&mut self, data: Bytes) -> Result<(), DecryptError> {
+        self.write(&data)
+    }
+
+    fn read_output_bytes(&mut self) -> Bytes {
+        Bytes::from(self.read_all_output())
+    }
+}
+
+#[cfg(all(test, feature = "ximalaya"))]
+mod test {
+    use bytes::{Bytes, BytesMut};
+
+    use super::BytesDecryptorExt;
+    use crate::{
+        decryption::ximalaya::{build_scramble_table, new_x2m, XMLY_SCRAMBLE_SIZE},
+        decryptor::Decryptor,
+        utils::test_util::test::{decrypt_in_chunks, generate_test_data},
+    };
+
+    #[test]
+    fn test_bytes_matches_vec_path() {
+        let key = generate_test_data(4, "bytes key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "bytes seed");
+        let table = build_scramble_table(&seed).unwrap();
+        let encrypted = Bytes::from(generate_test_data(0x3000, "bytes data"));
+
+        let mut decryptor = new_x2m(key[..].try_into().unwrap(), table);
+        let expected = decrypt_in_chunks(&mut decryptor, &encrypted, 0x100);
+
+        let mut decryptor = new_x2m(key[..].try_into().unwrap(), table);
+        let mut output = BytesMut::new();
+        let mut offset = 0;
+        while offset < encrypted.len() {
+            let end = (offset + 0x100).min(encrypted.len());
+            decryptor
+                .write_bytes_buf(encrypted.slice(offset..end))
+                .unwrap();
+            output.extend_from_slice(&decryptor.read_output_bytes());
+            offset = end;
+        }
+        decryptor.end().unwrap();
+        output.extend_from_slice(&decryptor.read_output_bytes());
+
+        assert_eq!(output.freeze(), expected);
+    }
+}
@@ -0,0 +1,107 @@
+use std::io::{Cursor, ErrorKind, Read, Write};
+
+use super::{file::decrypt_stream, io_error, DETECT_HEADER_SIZE};
+use crate::{
+    decryption::{detect_format, new_decryptor, AudioFormat},
+    decryptor::{DecryptError, DecryptErrorCode},
+    keys::KeyProvider,
+};
+
+/// Read up to `DETECT_HEADER_SIZE` bytes, fewer only at EOF.
+fn read_prefix(src: &mut impl Read) -> Result<Vec<u8>, DecryptError> {
+    let mut prefix = vec![0u8; DETECT_HEADER_SIZE];
+    let mut len = 0;
+    while len < prefix.len() {
+        match src.read(&mut prefix[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(io_error("read header failed", err)),
+        }
+    }
+    prefix.truncate(len);
+    Ok(prefix)
+}
+
+/// Detect the format of `src` from its first bytes and decrypt all of it
+/// into `dst`, taking keys from `keys`.
+///
+/// The bytes read for detection are buffered and fed to the decryptor first,
+/// so `src` does not need to be seekable. Returns the number of bytes written
+/// to `dst` and the detected format.
+pub fn copy_decrypt(
+    src: &mut impl Read,
+    dst: &mut impl Write,
+    keys: &dyn KeyProvider,
+) -> Result<(u64, AudioFormat), DecryptError> {
+    let prefix = read_prefix(src)?;
+    let format = detect_format(&prefix)
+        .ok_or_else(|| DecryptError::new(DecryptErrorCode::UnsupportedFormat, "unknown format"))?;
+
+    let mut decryptor = new_decryptor(format, keys)?;
+    let written = decrypt_stream(decryptor.as_mut(), &mut Cursor::new(prefix).chain(src), dst)?;
+    Ok((written, format))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::copy_decrypt;
+    use crate::{
+        decryption::AudioFormat,
+        decryptor::DecryptErrorCode,
+        keys::StaticKeyProvider,
+        utils::{audio::AudioType, test_util::test::generate_test_data},
+    };
+
+    #[cfg(feature = "ncm")]
+    #[test]
+    fn test_copy_decrypt_ncm() {
+        use crate::{
+            decryption::{
+                ncm::{test::ncm_test_data, NCM_DECRYPTION_KEY_SIZE},
+                new_ncm,
+            },
+            keys::KeyId,
+            utils::test_util::test::decrypt_in_chunks,
+        };
+
+        let key = generate_test_data(NCM_DECRYPTION_KEY_SIZE, "ncm-test-key");
+        let encrypted = ncm_test_data();
+        let expected = decrypt_in_chunks(&mut new_ncm(key[..].try_into().unwrap()), &encrypted, 0);
+
+        let keys = StaticKeyProvider::new().with_key(KeyId::NcmCoreKey, &key);
+        let mut output = vec![];
+        let (written, format) =
+            copy_decrypt(&mut Cursor::new(&encrypted), &mut output, &keys).unwrap();
+
+        assert_eq!(format, AudioFormat::Ncm);
+        assert_eq!(written, expected.len() as u64);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_copy_decrypt_plain_and_unknown() {
+        let mut plain = generate_test_data(0x100, "copy plain");
+        plain[..4].copy_from_slice(b"fLaC");
+        let mut output = vec![];
+        let (written, format) = copy_decrypt(
+            &mut Cursor::new(&plain),
+            &mut output,
+            &StaticKeyProvider::new(),
+        )
+        .unwrap();
+        assert_eq!(format, AudioFormat::Plain(AudioType::FLAC));
+        assert_eq!(written, 0x100);
+        assert_eq!(output, plain);
+
+        let err = copy_decrypt(
+            &mut Cursor::new([0u8; 0x20]),
+            &mut vec![],
+            &StaticKeyProvider::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::UnsupportedFormat));
+    }
+}
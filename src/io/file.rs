@@ -0,0 +1,262 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+use super::io_error;
+use crate::decryptor::{DecryptError, Decryptor};
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Create a fresh temporary file next to `dst`, so the final rename stays on
+/// the same filesystem.
+fn create_temp_file(dst: &Path) -> Result<(File, PathBuf), DecryptError> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = match dst.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file_name = dst
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    loop {
+        let temp_path = dir.join(format!(
+            ".{}.{}.{}.tmp",
+            file_name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((file, temp_path)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(io_error("create temp file failed", err)),
+        }
+    }
+}
+
+/// Stream everything from `input` through `decryptor` into `output`,
+/// returning the number of bytes written.
+pub(super) fn decrypt_stream(
+    decryptor: &mut dyn Decryptor,
+    input: &mut impl Read,
+    output: &mut impl Write,
+) -> Result<u64, DecryptError> {
+    // The trailing `get_eof_reserve()` bytes (e.g. a footer) are not part of
+    // the encrypted stream, so the tail of the input is held back until EOF.
+    let reserve = decryptor.get_eof_reserve();
+    let mut pending: Vec<u8> = Vec::with_capacity(READ_BUFFER_SIZE + reserve);
+    let mut buf = vec![0u8; READ_BUFFER_SIZE];
+    let mut written = 0u64;
+    let mut write_output = |output: &mut dyn Write, chunk: Vec<u8>| {
+        written += chunk.len() as u64;
+        output
+            .write_all(&chunk)
+            .map_err(|err| io_error("write output failed", err))
+    };
+
+    loop {
+        let n = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(io_error("read input failed", err)),
+        };
+
+        pending.extend_from_slice(&buf[..n]);
+        if pending.len() > reserve {
+            let ready = pending.len() - reserve;
+            decryptor.write(&pending[..ready])?;
+            pending.drain(..ready);
+
+            write_output(output, decryptor.read_all_output())?;
+        }
+    }
+
+    decryptor.end()?;
+    write_output(output, decryptor.read_all_output())?;
+    Ok(written)
+}
+
+/// Options for [`decrypt_file`].
+#[derive(Debug, Clone, Default)]
+pub struct DecryptFileOptions {
+    /// Give the output the modification time of the source file. Skipped on
+    /// platforms that do not report one.
+    pub preserve_timestamps: bool,
+}
+
+fn decrypt_to_temp_file(
+    decryptor: &mut dyn Decryptor,
+    input: &mut impl Read,
+    dst: &Path,
+    mtime: Option<SystemTime>,
+) -> Result<PathBuf, DecryptError> {
+    let (mut temp_file, temp_path) = create_temp_file(dst)?;
+
+    let result = decrypt_stream(decryptor, input, &mut temp_file).and_then(|_| {
+        // Set before the rename, so `dst` never shows up with a fresh mtime.
+        if let Some(mtime) = mtime {
+            temp_file
+                .set_modified(mtime)
+                .map_err(|err| io_error("set output mtime failed", err))?;
+        }
+        temp_file
+            .sync_all()
+            .map_err(|err| io_error("sync output failed", err))
+    });
+    drop(temp_file);
+
+    let result = result
+        .and_then(|_| fs::rename(&temp_path, dst).map_err(|err| io_error("rename failed", err)));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result.map(|_| dst.to_path_buf())
+}
+
+/// Decrypt everything from `input` into `dst`.
+///
+/// Output goes to a temporary file in the same directory, which is renamed to
+/// `dst` only once decryption succeeded; on error it is removed and `dst` is
+/// left untouched.
+pub fn decrypt_to_file(
+    decryptor: &mut dyn Decryptor,
+    input: &mut impl Read,
+    dst: &Path,
+) -> Result<PathBuf, DecryptError> {
+    decrypt_to_temp_file(decryptor, input, dst, None)
+}
+
+/// Decrypt the file at `src` into `dst`, like [`decrypt_to_file`].
+pub fn decrypt_file(
+    decryptor: &mut dyn Decryptor,
+    src: &Path,
+    dst: &Path,
+    options: &DecryptFileOptions,
+) -> Result<PathBuf, DecryptError> {
+    let mut input = File::open(src).map_err(|err| io_error("open input failed", err))?;
+    let mtime = if options.preserve_timestamps {
+        let metadata = input
+            .metadata()
+            .map_err(|err| io_error("read input metadata failed", err))?;
+        metadata.modified().ok()
+    } else {
+        None
+    };
+
+    decrypt_to_temp_file(decryptor, &mut input, dst, mtime)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        io::{Cursor, Read},
+        path::PathBuf,
+    };
+
+    use super::{decrypt_file, decrypt_to_file, DecryptFileOptions};
+    use crate::{
+        decryption::new_passthrough,
+        decryptor::DecryptErrorCode,
+        utils::test_util::test::{generate_test_data, TEST_SIZE_1MB},
+    };
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("parakeet-core-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Yields `len` bytes, then fails.
+    struct FailingReader {
+        len: usize,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.len == 0 {
+                return Err(std::io::Error::other("boom"));
+            }
+            let n = self.len.min(buf.len());
+            buf[..n].fill(0x55);
+            self.len -= n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_decrypt_to_file() {
+        let dir = test_dir("decrypt-to-file");
+        let dst = dir.join("out.bin");
+        let test_data = generate_test_data(TEST_SIZE_1MB, "decrypt to file");
+
+        let mut decryptor = new_passthrough();
+        let path = decrypt_to_file(&mut decryptor, &mut Cursor::new(&test_data), &dst).unwrap();
+
+        assert_eq!(path, dst);
+        assert_eq!(fs::read(&dst).unwrap(), test_data);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_to_file_error_leaves_no_file() {
+        let dir = test_dir("decrypt-to-file-error");
+        let dst = dir.join("out.bin");
+
+        let mut decryptor = new_passthrough();
+        let mut input = FailingReader { len: 200_000 };
+        let err = decrypt_to_file(&mut decryptor, &mut input, &dst).unwrap_err();
+
+        assert!(matches!(err.code(), DecryptErrorCode::IOError));
+        assert!(!dst.exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_decrypt_file_preserves_mtime() {
+        use std::time::{Duration, SystemTime};
+
+        let dir = test_dir("decrypt-file-mtime");
+        let src = dir.join("in.bin");
+        let test_data = generate_test_data(0x1000, "decrypt file mtime");
+        fs::write(&src, &test_data).unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&src)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        let preserved = dir.join("preserved.bin");
+        let options = DecryptFileOptions {
+            preserve_timestamps: true,
+        };
+        decrypt_file(&mut new_passthrough(), &src, &preserved, &options).unwrap();
+        assert_eq!(fs::read(&preserved).unwrap(), test_data);
+        let modified = |path: &PathBuf| fs::metadata(path).unwrap().modified().unwrap();
+        assert_eq!(modified(&preserved), mtime);
+
+        let fresh = dir.join("fresh.bin");
+        let options = DecryptFileOptions::default();
+        decrypt_file(&mut new_passthrough(), &src, &fresh, &options).unwrap();
+        assert_ne!(modified(&fresh), mtime);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::io_error;
+use crate::decryptor::{DecryptError, DecryptErrorCode, Decryptor};
+
+/// Size of each sampled window.
+const WINDOW_SIZE: u64 = 4096;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+fn fnv1a(mut hash: u64, data: &[u8]) -> u64 {
+    for &b in data {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Fingerprint the decrypted content of `input` (`total_len` bytes long) by
+/// decrypting a window at its start, middle and end, without decrypting the
+/// whole file. `decryptor` must be seekable.
+///
+/// Windows stay within the part of the file `decrypt_at` can decrypt: past a
+/// header decrypted as a whole (see
+/// [`crate::decryptor::SeekableDecryptor::first_seekable_offset`]), and
+/// ahead of the trailing [`Decryptor::get_eof_reserve`] bytes.
+///
+/// This is a heuristic for spotting duplicates, not a cryptographic hash:
+/// files differing only outside the sampled windows share a fingerprint.
+pub fn content_fingerprint<R: Read + Seek>(
+    decryptor: &dyn Decryptor,
+    input: &mut R,
+    total_len: u64,
+) -> Result<u64, DecryptError> {
+    let seekable = decryptor.as_seekable().ok_or_else(|| {
+        DecryptError::new(
+            DecryptErrorCode::RangeNotSupported,
+            "decryptor is not seekable",
+        )
+    })?;
+    let start = seekable.first_seekable_offset().min(total_len);
+    let end = total_len
+        .saturating_sub(decryptor.get_eof_reserve() as u64)
+        .max(start);
+
+    let window = WINDOW_SIZE.min(end - start);
+    let last = end - window;
+    let mut offsets = vec![start, start + (last - start) / 2, last];
+    offsets.dedup();
+
+    let mut hash = fnv1a(FNV_OFFSET_BASIS, &total_len.to_le_bytes());
+    let mut buf = vec![0u8; window as usize];
+    for offset in offsets {
+        input
+            .seek(SeekFrom::Start(offset))
+            .map_err(|err| io_error("seek input failed", err))?;
+        input
+            .read_exact(&mut buf)
+            .map_err(|err| io_error("read input failed", err))?;
+
+        seekable.decrypt_at(offset, &mut buf)?;
+        hash = fnv1a(hash, &buf);
+    }
+
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::content_fingerprint;
+    use crate::{
+        decryption::new_passthrough,
+        utils::test_util::test::{generate_test_data, TEST_SIZE_1MB},
+    };
+
+    fn fingerprint(data: &[u8]) -> u64 {
+        content_fingerprint(
+            &new_passthrough(),
+            &mut Cursor::new(data),
+            data.len() as u64,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_content_fingerprint() {
+        let data = generate_test_data(TEST_SIZE_1MB, "fingerprint data");
+        assert_eq!(fingerprint(&data), fingerprint(&data.clone()));
+
+        let mut changed = data.clone();
+        changed[TEST_SIZE_1MB / 2] ^= 1;
+        assert_ne!(fingerprint(&data), fingerprint(&changed));
+
+        let other = generate_test_data(TEST_SIZE_1MB, "fingerprint other data");
+        assert_ne!(fingerprint(&data), fingerprint(&other));
+
+        // Shorter than a window.
+        assert_ne!(fingerprint(&data[..100]), fingerprint(&data[..101]));
+    }
+
+    #[cfg(feature = "ximalaya")]
+    #[test]
+    fn test_content_fingerprint_x2m() {
+        use crate::decryption::ximalaya::{
+            build_scramble_table, new_x2m, test::encrypt, XMLY_SCRAMBLE_SIZE,
+        };
+
+        let key = generate_test_data(4, "fingerprint x2m key");
+        let table = build_scramble_table(&generate_test_data(2048, "fingerprint seed")).unwrap();
+        let plain = generate_test_data(TEST_SIZE_1MB, "fingerprint x2m data");
+        let encrypted = encrypt(&key, &table, &plain);
+        let decryptor = new_x2m(key[..].try_into().unwrap(), table);
+        let fingerprint = |data: &[u8]| {
+            content_fingerprint(&decryptor, &mut Cursor::new(data), data.len() as u64).unwrap()
+        };
+
+        let result = fingerprint(&encrypted);
+        assert_eq!(result, fingerprint(&encrypted));
+        let mut changed = encrypted.clone();
+        changed[XMLY_SCRAMBLE_SIZE] ^= 1;
+        assert_ne!(result, fingerprint(&changed));
+
+        // All header, nothing to sample.
+        fingerprint(&encrypted[..XMLY_SCRAMBLE_SIZE]);
+    }
+}
@@ -0,0 +1,399 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    ops::Range,
+    time::Duration,
+};
+
+use super::{io_error, open::infer_format, DETECT_HEADER_SIZE};
+use crate::{
+    decryption::new_decryptor,
+    decryptor::{DecryptError, DecryptErrorCode},
+    keys::KeyProvider,
+};
+
+/// Random access to the bytes of a remote encrypted file.
+pub trait RangeSource {
+    /// Fetch the bytes at `range`. Fewer bytes are returned when the range
+    /// extends past the end of the file.
+    fn fetch_range(&mut self, range: Range<u64>) -> Result<Vec<u8>, DecryptError>;
+
+    /// Extension of the remote file name, used when the header is not
+    /// recognised on its own (e.g. X2M).
+    fn extension(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Bytes of status line and headers accepted on top of the requested range.
+const MAX_RESPONSE_HEAD_SIZE: u64 = 16 * 1024;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Plain HTTP/1.1 `Range` requests against a `http://host[:port]/path` URL,
+/// one connection per request. TLS is not supported.
+///
+/// Servers must honour the range (`206 Partial Content`); the response is
+/// never read past the requested length.
+#[derive(Debug, Clone)]
+pub struct HttpRangeSource {
+    host: String,
+    port: u16,
+    path: String,
+    timeout: Duration,
+}
+
+impl HttpRangeSource {
+    pub fn new(url: &str) -> Result<Self, DecryptError> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            DecryptError::new(DecryptErrorCode::IOError, "only http:// URLs are supported")
+        })?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port.parse().map_err(|_| {
+                    DecryptError::new(DecryptErrorCode::IOError, "invalid port in URL")
+                })?;
+                (host, port)
+            }
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(DecryptError::new(
+                DecryptErrorCode::IOError,
+                "missing host in URL",
+            ));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Timeout for connecting, and for each read and write. Defaults to 30
+    /// seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn connect(&self) -> Result<TcpStream, DecryptError> {
+        let addrs = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|err| io_error("resolve host failed", err))?;
+        let mut last_err = None;
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => {
+                    stream
+                        .set_read_timeout(Some(self.timeout))
+                        .and_then(|_| stream.set_write_timeout(Some(self.timeout)))
+                        .map_err(|err| io_error("set timeout failed", err))?;
+                    return Ok(stream);
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(match last_err {
+            Some(err) => io_error("connect failed", err),
+            None => DecryptError::new(DecryptErrorCode::IOError, "host has no address"),
+        })
+    }
+
+    fn request(&self, range: &Range<u64>) -> String {
+        let host = match self.port {
+            80 => self.host.clone(),
+            port => format!("{}:{}", self.host, port),
+        };
+        format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nRange: bytes={}-{}\r\nConnection: close\r\n\r\n",
+            self.path,
+            host,
+            range.start,
+            range.end - 1
+        )
+    }
+}
+
+/// Status code, lowercased headers and body of a response.
+type Response<'a> = (u16, Vec<(String, String)>, &'a [u8]);
+
+fn parse_response(response: &[u8]) -> Result<Response<'_>, DecryptError> {
+    let malformed = || DecryptError::new(DecryptErrorCode::IOError, "malformed HTTP response");
+
+    let head_len = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(malformed)?;
+    let head = std::str::from_utf8(&response[..head_len]).map_err(|_| malformed())?;
+    let body = &response[head_len + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(malformed)?;
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    Ok((status, headers, body))
+}
+
+impl RangeSource for HttpRangeSource {
+    fn fetch_range(&mut self, range: Range<u64>) -> Result<Vec<u8>, DecryptError> {
+        if range.start >= range.end {
+            return Ok(vec![]);
+        }
+
+        let len = range.end - range.start;
+        let mut stream = self.connect()?;
+        stream
+            .write_all(self.request(&range).as_bytes())
+            .map_err(|err| io_error("send request failed", err))?;
+        let mut response = vec![];
+        stream
+            .take(len.saturating_add(MAX_RESPONSE_HEAD_SIZE))
+            .read_to_end(&mut response)
+            .map_err(|err| io_error("read response failed", err))?;
+
+        let (status, headers, mut body) = parse_response(&response)?;
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+        if header("transfer-encoding").is_some_and(|v| !v.eq_ignore_ascii_case("identity")) {
+            return Err(DecryptError::new(
+                DecryptErrorCode::IOError,
+                "chunked HTTP responses are not supported",
+            ));
+        }
+        if let Some(len) = header("content-length").and_then(|v| v.parse::<usize>().ok()) {
+            body = &body[..len.min(body.len())];
+        }
+
+        let len = usize::try_from(len).unwrap_or(usize::MAX);
+        match status {
+            206 => Ok(Vec::from(&body[..len.min(body.len())])),
+            416 => Ok(vec![]),
+            // The server ignored the range and is sending the whole file.
+            200 => Err(DecryptError::new(
+                DecryptErrorCode::RangeNotSupported,
+                "server does not support range requests",
+            )),
+            _ => Err(DecryptError::new(
+                DecryptErrorCode::IOError,
+                &format!("unexpected HTTP status: {}", status),
+            )),
+        }
+    }
+
+    fn extension(&self) -> Option<&str> {
+        let path = self.path.split(['?', '#']).next()?;
+        let name = path.rsplit('/').next()?;
+        name.rsplit_once('.').map(|(_, ext)| ext)
+    }
+}
+
+/// Decrypt the bytes at `range` of the file behind `source`, without fetching
+/// the rest of it.
+///
+/// The header is fetched first to pick the format. It is also streamed
+/// through the decryptor, so a range overlapping a header the format can not
+/// decrypt on its own (e.g. the scrambled X2M header) still works. Only
+/// formats implementing [`crate::decryptor::SeekableDecryptor`] are supported.
+pub fn decrypt_source_range(
+    source: &mut dyn RangeSource,
+    range: Range<u64>,
+    keys: &dyn KeyProvider,
+) -> Result<Vec<u8>, DecryptError> {
+    let header = source.fetch_range(0..DETECT_HEADER_SIZE as u64)?;
+    let format = infer_format(&header, source.extension()).ok_or_else(|| {
+        DecryptError::new(DecryptErrorCode::UnsupportedFormat, "unknown remote format")
+    })?;
+    let mut decryptor = new_decryptor(format, keys)?;
+    if decryptor.as_seekable().is_none() {
+        return Err(DecryptError::new(
+            DecryptErrorCode::RangeNotSupported,
+            "format can not be decrypted by range",
+        ));
+    }
+
+    let mut buf = source.fetch_range(range.clone())?;
+    let end = range.start + buf.len() as u64;
+
+    // Seekable formats keep input and output offsets aligned, so the streamed
+    // header output is the plaintext of the same bytes.
+    let split = if range.start < header.len() as u64 {
+        decryptor.write(&header)?;
+        let plain_header = decryptor.read_all_output();
+        let split = end.min(plain_header.len() as u64).max(range.start);
+        if split > range.start {
+            let n = (split - range.start) as usize;
+            buf[..n].copy_from_slice(&plain_header[range.start as usize..split as usize]);
+        }
+        split
+    } else {
+        range.start
+    };
+
+    if split < end {
+        let offset = (split - range.start) as usize;
+        decryptor.decrypt_range(split..end, &mut buf[offset..])?;
+    }
+    Ok(buf)
+}
+
+/// Decrypt the bytes at `range` of the encrypted file at `url`, using HTTP
+/// `Range` requests. See [`decrypt_source_range`].
+pub fn decrypt_http_range(
+    url: &str,
+    range: Range<u64>,
+    keys: &dyn KeyProvider,
+) -> Result<Vec<u8>, DecryptError> {
+    decrypt_source_range(&mut HttpRangeSource::new(url)?, range, keys)
+}
+
+#[cfg(test)]
+mod test {
+    use super::HttpRangeSource;
+    use crate::decryptor::DecryptErrorCode;
+
+    #[test]
+    fn test_http_range_source_url() {
+        let source = HttpRangeSource::new("http://cdn.example:8080/a/song.x2m?sig=1").unwrap();
+        assert_eq!(source.host, "cdn.example");
+        assert_eq!(source.port, 8080);
+        assert_eq!(super::RangeSource::extension(&source), Some("x2m"));
+
+        let err = HttpRangeSource::new("https://cdn.example/song.x2m").unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::IOError));
+    }
+
+    /// Answer one request with `response`, returning the URL to fetch.
+    fn serve_once(response: Vec<u8>) -> (String, std::thread::JoinHandle<()>) {
+        use std::{
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/song.bin", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for line in BufReader::new(&stream).lines() {
+                if line.unwrap().is_empty() {
+                    break;
+                }
+            }
+            // The client may hang up once it read enough.
+            let _ = stream.write_all(&response);
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn test_fetch_range_requires_partial_content() {
+        use super::RangeSource;
+
+        let (url, server) = serve_once([&b"HTTP/1.1 200 OK\r\n\r\n"[..], &[0x55; 0x1000]].concat());
+        let err = HttpRangeSource::new(&url)
+            .unwrap()
+            .fetch_range(0x10..0x20)
+            .unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::RangeNotSupported));
+        server.join().unwrap();
+
+        // A body longer than the range is cut short.
+        let (url, server) = serve_once(
+            [
+                &b"HTTP/1.1 206 Partial Content\r\n\r\n"[..],
+                &[0x55; 0x1000],
+            ]
+            .concat(),
+        );
+        let result = HttpRangeSource::new(&url)
+            .unwrap()
+            .fetch_range(0x10..0x20)
+            .unwrap();
+        assert_eq!(result, [0x55; 0x10]);
+        server.join().unwrap();
+    }
+
+    #[cfg(feature = "ximalaya")]
+    #[test]
+    fn test_decrypt_http_range_x2m() {
+        use std::{
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+        };
+
+        use crate::{
+            decryption::ximalaya::{build_scramble_table, test::encrypt, XMLY_SCRAMBLE_SIZE},
+            keys::{KeyId, StaticKeyProvider},
+            utils::test_util::test::generate_test_data,
+        };
+
+        let key = generate_test_data(4, "http range key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "http range seed");
+        let table = build_scramble_table(&seed).unwrap();
+        let plain = generate_test_data(0x8000, "http range data");
+        let encrypted = encrypt(&key, &table, &plain);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let file = encrypted.clone();
+        let server = std::thread::spawn(move || {
+            // Two requests (header + range) per decrypted range.
+            for stream in listener.incoming().take(6) {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(spec) = line.strip_prefix("Range: bytes=") {
+                        let (start, end) = spec.split_once('-').unwrap();
+                        let start: usize = start.parse().unwrap();
+                        let end: usize = end.parse::<usize>().unwrap().min(file.len() - 1);
+                        range = Some(start..end + 1);
+                    }
+                }
+                let range = range.unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\n\r\n",
+                    range.len()
+                )
+                .unwrap();
+                stream.write_all(&file[range]).unwrap();
+            }
+        });
+
+        let keys = StaticKeyProvider::new()
+            .with_key(KeyId::XimalayaX2mKey, &key)
+            .with_key(KeyId::XimalayaScrambleSeed, &seed);
+        let url = format!("http://127.0.0.1:{}/media/song.x2m", port);
+
+        let result = super::decrypt_http_range(&url, 0x5000..0x5100, &keys).unwrap();
+        assert_eq!(result, &plain[0x5000..0x5100]);
+        // Inside and across the scrambled header.
+        let result = super::decrypt_http_range(&url, 0x100..0x200, &keys).unwrap();
+        assert_eq!(result, &plain[0x100..0x200]);
+        let result = super::decrypt_http_range(&url, 0x300..0x600, &keys).unwrap();
+        assert_eq!(result, &plain[0x300..0x600]);
+
+        server.join().unwrap();
+    }
+}
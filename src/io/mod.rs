@@ -0,0 +1,30 @@
+#[cfg(feature = "bytes")]
+mod bytes_buf;
+mod copy;
+mod file;
+mod fingerprint;
+#[cfg(feature = "http")]
+mod http;
+mod open;
+mod retry;
+mod segment;
+mod split;
+
+#[cfg(feature = "bytes")]
+pub use bytes_buf::BytesDecryptorExt;
+pub use copy::copy_decrypt;
+pub use file::{decrypt_file, decrypt_to_file, DecryptFileOptions};
+pub use fingerprint::content_fingerprint;
+#[cfg(feature = "http")]
+pub use http::{decrypt_http_range, decrypt_source_range, HttpRangeSource, RangeSource};
+pub use open::open_decryptor;
+pub(crate) use open::DETECT_HEADER_SIZE;
+pub use retry::RetryingReader;
+pub use segment::{SegmentInfo, SegmentStreamDecryptor};
+pub use split::SplittingWriter;
+
+use crate::decryptor::{DecryptError, DecryptErrorCode};
+
+pub(crate) fn io_error(message: &str, err: std::io::Error) -> DecryptError {
+    DecryptError::new(DecryptErrorCode::IOError, &format!("{}: {}", message, err))
+}
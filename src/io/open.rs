@@ -0,0 +1,125 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use super::io_error;
+use crate::{
+    decryption::{detect_format, new_decryptor, AudioFormat},
+    decryptor::{DecryptError, DecryptErrorCode, Decryptor},
+    keys::KeyProvider,
+};
+
+/// Bytes read from the start of the file for detection.
+pub(crate) const DETECT_HEADER_SIZE: usize = 0x1000;
+
+/// Pick the format of a file from its header and extension.
+///
+/// Encrypted formats with a magic header are trusted over the extension. Plain
+/// audio signatures are short enough to show up by chance in encrypted data
+/// (e.g. an X2M header), so a known extension wins over them.
+pub(super) fn infer_format(header: &[u8], ext: Option<&str>) -> Option<AudioFormat> {
+    let by_magic = detect_format(header);
+    let by_ext = ext.and_then(AudioFormat::from_extension);
+    match (by_magic, by_ext) {
+        (Some(format), _) if !matches!(format, AudioFormat::Plain(_)) => Some(format),
+        (_, Some(format)) => Some(format),
+        (format, None) => format,
+    }
+}
+
+/// Open `path` and create the decryptor for it, taking keys from `keys`.
+///
+/// Returns the file rewound to its start, ready to be fed to the decryptor
+/// from the first byte.
+pub fn open_decryptor(
+    path: &Path,
+    keys: &dyn KeyProvider,
+) -> Result<(Box<dyn Decryptor>, File), DecryptError> {
+    let mut file = File::open(path).map_err(|err| io_error("open input failed", err))?;
+
+    let mut header = Vec::with_capacity(DETECT_HEADER_SIZE);
+    (&mut file)
+        .take(DETECT_HEADER_SIZE as u64)
+        .read_to_end(&mut header)
+        .map_err(|err| io_error("read header failed", err))?;
+
+    let ext = path.extension().and_then(|ext| ext.to_str());
+    let format = infer_format(&header, ext).ok_or_else(|| {
+        DecryptError::new(
+            DecryptErrorCode::UnsupportedFormat,
+            &format!("unknown format: {}", path.display()),
+        )
+    })?;
+    let decryptor = new_decryptor(format, keys)?;
+
+    file.seek(SeekFrom::Start(0))
+        .map_err(|err| io_error("rewind input failed", err))?;
+    Ok((decryptor, file))
+}
+
+#[cfg(test)]
+mod test {
+    use super::infer_format;
+    use crate::{decryption::AudioFormat, utils::audio::AudioType};
+
+    #[test]
+    fn test_infer_format() {
+        let mp3_sync = [0xff, 0xfb, 0x90, 0x64];
+        assert_eq!(infer_format(&mp3_sync, Some("x2m")), Some(AudioFormat::X2m));
+        assert_eq!(
+            infer_format(&mp3_sync, None),
+            Some(AudioFormat::Plain(AudioType::MP3))
+        );
+        assert_eq!(
+            infer_format(b"CTENFDAM", Some("mp3")),
+            Some(AudioFormat::Ncm)
+        );
+        assert_eq!(infer_format(b"\x00\x00\x00\x00", Some("txt")), None);
+    }
+
+    #[cfg(feature = "ximalaya")]
+    #[test]
+    fn test_open_decryptor_x2m() {
+        use std::{fs, io::Read};
+
+        use super::open_decryptor;
+        use crate::{
+            decryption::ximalaya::{build_scramble_table, new_x2m, XMLY_SCRAMBLE_SIZE},
+            keys::{KeyId, StaticKeyProvider},
+            utils::test_util::test::{decrypt_in_chunks, generate_test_data},
+        };
+
+        let key = generate_test_data(4, "open key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "open seed");
+        let table = build_scramble_table(&seed).unwrap();
+        let encrypted = generate_test_data(0x3000, "open data");
+        let expected = decrypt_in_chunks(
+            &mut new_x2m(key[..].try_into().unwrap(), table),
+            &encrypted,
+            0,
+        );
+
+        let path = std::env::temp_dir().join(format!(
+            "parakeet-core-open-decryptor-{}.x2m",
+            std::process::id()
+        ));
+        fs::write(&path, &encrypted).unwrap();
+
+        let keys = StaticKeyProvider::new()
+            .with_key(KeyId::XimalayaX2mKey, &key)
+            .with_key(KeyId::XimalayaScrambleSeed, &seed);
+        let (mut decryptor, mut file) = open_decryptor(&path, &keys).unwrap();
+        assert_eq!(decryptor.get_name(), "Ximalaya(X2M)");
+
+        let mut input = vec![];
+        file.read_to_end(&mut input).unwrap();
+        assert_eq!(input, encrypted);
+        assert_eq!(
+            decrypt_in_chunks(decryptor.as_mut(), &input, 0x100),
+            expected
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
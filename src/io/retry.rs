@@ -0,0 +1,109 @@
+use std::{
+    io::{ErrorKind, Read},
+    time::Duration,
+};
+
+/// Retries reads failing with a transient error (`Interrupted` or
+/// `WouldBlock`), e.g. from a flaky network stream, before giving up.
+///
+/// Wrap the input given to [`super::copy_decrypt`] or
+/// [`super::decrypt_to_file`] with it. At most `max_retries` retries are made
+/// per read; any other error is returned right away.
+pub struct RetryingReader<R> {
+    inner: R,
+    max_retries: usize,
+    delay: Duration,
+}
+
+impl<R: Read> RetryingReader<R> {
+    pub fn new(inner: R, max_retries: usize) -> Self {
+        Self {
+            inner,
+            max_retries,
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Sleep for `delay` before retrying a `WouldBlock` read.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for RetryingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut retries = 0;
+        loop {
+            match self.inner.read(buf) {
+                Err(err)
+                    if retries < self.max_retries
+                        && matches!(err.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock) =>
+                {
+                    retries += 1;
+                    if err.kind() == ErrorKind::WouldBlock && !self.delay.is_zero() {
+                        std::thread::sleep(self.delay);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{ErrorKind, Read};
+
+    use super::RetryingReader;
+
+    /// Fails with `kind` `failures` times, then reads from `data`.
+    struct FlakyReader {
+        data: &'static [u8],
+        kind: ErrorKind,
+        failures: usize,
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(self.kind.into());
+            }
+            self.data.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_retrying_reader() {
+        let flaky = |kind, failures| FlakyReader {
+            data: b"decrypted",
+            kind,
+            failures,
+        };
+
+        // Direct reads: `read_to_end` would retry `Interrupted` by itself.
+        let mut buf = [0u8; 4];
+        let mut reader = RetryingReader::new(flaky(ErrorKind::Interrupted, 3), 3);
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"decr");
+
+        let mut reader = RetryingReader::new(flaky(ErrorKind::Interrupted, 4), 3);
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+
+        let mut reader = RetryingReader::new(flaky(ErrorKind::WouldBlock, 3), 2);
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+
+        // Not transient: surfaced on the first failure.
+        let mut reader = RetryingReader::new(flaky(ErrorKind::ConnectionReset, 1), 5);
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+    }
+}
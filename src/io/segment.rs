@@ -0,0 +1,111 @@
+use crate::decryptor::{DecryptError, Decryptor};
+
+/// Where a pushed segment sits in the concatenated stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentInfo {
+    /// Offset of the segment's first byte in the concatenated input.
+    pub input_offset: u64,
+    pub input_len: usize,
+    /// Decrypted bytes returned by the `push_segment` call of this segment.
+    pub output_len: usize,
+}
+
+/// Decrypts a file delivered as many small segments sharing one key (e.g.
+/// HLS-style `.ts` parts), as one continuous stream.
+///
+/// Offset based ciphers keep counting across segments, so the output equals
+/// decrypting the concatenation. A segment's output can lag behind its input
+/// while the decryptor buffers (e.g. a header spanning several segments); it
+/// is then returned by a later `push_segment` or by [`Self::finish`].
+pub struct SegmentStreamDecryptor<D> {
+    decryptor: D,
+    segments: Vec<SegmentInfo>,
+    input_len: u64,
+}
+
+impl<D: Decryptor> SegmentStreamDecryptor<D> {
+    pub fn new(decryptor: D) -> Self {
+        Self {
+            decryptor,
+            segments: vec![],
+            input_len: 0,
+        }
+    }
+
+    /// Decrypt the next segment, returning the output it made available.
+    pub fn push_segment(&mut self, segment: &[u8]) -> Result<Vec<u8>, DecryptError> {
+        self.decryptor.write(segment)?;
+        let output = self.decryptor.read_all_output();
+        self.segments.push(SegmentInfo {
+            input_offset: self.input_len,
+            input_len: segment.len(),
+            output_len: output.len(),
+        });
+        self.input_len += segment.len() as u64;
+        Ok(output)
+    }
+
+    /// Segments pushed so far, in order.
+    pub fn segments(&self) -> &[SegmentInfo] {
+        &self.segments
+    }
+
+    /// End the stream, returning output still held back by the decryptor.
+    pub fn finish(&mut self) -> Result<Vec<u8>, DecryptError> {
+        self.decryptor.end()?;
+        Ok(self.decryptor.read_all_output())
+    }
+
+    pub fn into_inner(self) -> D {
+        self.decryptor
+    }
+}
+
+#[cfg(all(test, feature = "qmc"))]
+mod test {
+    use super::{SegmentInfo, SegmentStreamDecryptor};
+    use crate::{
+        decryption::new_qmc_v1,
+        utils::test_util::test::{decrypt_in_chunks, generate_test_data},
+    };
+
+    #[test]
+    fn test_segments_match_concatenation() {
+        let key = generate_test_data(256, "segment key");
+        let segments = [
+            generate_test_data(0x5000, "segment 0"),
+            generate_test_data(0x4321, "segment 1"),
+            generate_test_data(0x1234, "segment 2"),
+        ];
+        let expected = decrypt_in_chunks(&mut new_qmc_v1(&key), &segments.concat(), 0);
+
+        let mut decryptor = SegmentStreamDecryptor::new(new_qmc_v1(&key));
+        let mut result = vec![];
+        for segment in segments.iter() {
+            result.extend(decryptor.push_segment(segment).unwrap());
+        }
+        result.extend(decryptor.finish().unwrap());
+        assert_eq!(result, expected);
+
+        assert_eq!(
+            decryptor.segments(),
+            [
+                SegmentInfo {
+                    input_offset: 0,
+                    input_len: 0x5000,
+                    output_len: 0x5000,
+                },
+                SegmentInfo {
+                    input_offset: 0x5000,
+                    input_len: 0x4321,
+                    output_len: 0x4321,
+                },
+                SegmentInfo {
+                    input_offset: 0x9321,
+                    input_len: 0x1234,
+                    output_len: 0x1234,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::{fs::File, io::Write, path::PathBuf};
+
+use super::io_error;
+use crate::decryptor::{DecryptError, Decryptor};
+
+/// Writes the output of a decryptor to numbered part files of at most
+/// `max_part_bytes` each, e.g. for chapter-sized audiobook files.
+///
+/// Parts are split at exact byte counts, regardless of how the input is cut
+/// into `write` calls. Part `n` (from 0) is written to `part_path(n)`.
+pub struct SplittingWriter<D, F> {
+    decryptor: D,
+    part_path: F,
+    max_part_bytes: usize,
+    current: Option<File>,
+    current_len: usize,
+    parts: Vec<PathBuf>,
+}
+
+impl<D: Decryptor, F: FnMut(usize) -> PathBuf> SplittingWriter<D, F> {
+    /// # Panics
+    ///
+    /// If `max_part_bytes` is zero.
+    pub fn new(decryptor: D, max_part_bytes: usize, part_path: F) -> Self {
+        assert!(max_part_bytes > 0, "part size must not be zero");
+        Self {
+            decryptor,
+            part_path,
+            max_part_bytes,
+            current: None,
+            current_len: 0,
+            parts: vec![],
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+        self.decryptor.write(data)?;
+        let output = self.decryptor.consume_output(self.decryptor.output_ready());
+        self.write_output(&output)
+    }
+
+    /// End the stream and flush the last part, returning the paths of all
+    /// parts written.
+    pub fn finish(mut self) -> Result<Vec<PathBuf>, DecryptError> {
+        self.decryptor.end()?;
+        let output = self.decryptor.read_all_output();
+        self.write_output(&output)?;
+        self.close_part()?;
+        Ok(self.parts)
+    }
+
+    fn write_output(&mut self, mut output: &[u8]) -> Result<(), DecryptError> {
+        while !output.is_empty() {
+            if self.current_len == self.max_part_bytes {
+                self.close_part()?;
+            }
+            if self.current.is_none() {
+                let path = (self.part_path)(self.parts.len());
+                let file =
+                    File::create(&path).map_err(|err| io_error("create part failed", err))?;
+                self.current = Some(file);
+                self.current_len = 0;
+                self.parts.push(path);
+            }
+
+            let n = (self.max_part_bytes - self.current_len).min(output.len());
+            if let Some(file) = self.current.as_mut() {
+                file.write_all(&output[..n])
+                    .map_err(|err| io_error("write part failed", err))?;
+            }
+            self.current_len += n;
+            output = &output[n..];
+        }
+        Ok(())
+    }
+
+    fn close_part(&mut self) -> Result<(), DecryptError> {
+        match self.current.take() {
+            Some(file) => file
+                .sync_all()
+                .map_err(|err| io_error("sync part failed", err)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "ximalaya"))]
+mod test {
+    use std::fs;
+
+    use super::SplittingWriter;
+    use crate::{
+        decryption::ximalaya::{build_scramble_table, new_x2m, XMLY_SCRAMBLE_SIZE},
+        utils::test_util::test::{decrypt_in_chunks, generate_test_data, TEST_SIZE_1MB},
+    };
+
+    #[test]
+    fn test_splitting_writer() {
+        let dir = std::env::temp_dir().join(format!(
+            "parakeet-core-splitting-writer-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let key = generate_test_data(4, "split key");
+        let seed = generate_test_data(XMLY_SCRAMBLE_SIZE * 2, "split seed");
+        let table = build_scramble_table(&seed).unwrap();
+        let encrypted = generate_test_data(TEST_SIZE_1MB, "split data");
+        let expected = decrypt_in_chunks(
+            &mut new_x2m(key[..].try_into().unwrap(), table),
+            &encrypted,
+            0,
+        );
+
+        let part_size = 300 * 1024;
+        let mut writer = SplittingWriter::new(
+            new_x2m(key[..].try_into().unwrap(), table),
+            part_size,
+            |n| dir.join(format!("part{}.bin", n)),
+        );
+        // Odd chunks, so part boundaries fall inside a `write`.
+        for chunk in encrypted.chunks(0x1_2345) {
+            writer.write(chunk).unwrap();
+        }
+        let parts = writer.finish().unwrap();
+
+        assert_eq!(parts.len(), TEST_SIZE_1MB.div_ceil(part_size));
+        let mut result = vec![];
+        for (i, part) in parts.iter().enumerate() {
+            let data = fs::read(part).unwrap();
+            if i + 1 < parts.len() {
+                assert_eq!(data.len(), part_size);
+            }
+            result.extend_from_slice(&data);
+        }
+        assert_eq!(result, expected);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,16 @@
+//! Fixed keys shipped with the clients, so callers do not have to supply
+//! them. These are public knowledge: they are embedded in every copy of the
+//! respective client and widely published.
+//!
+//! Only keys that are the same for every install live here; per-file or
+//! per-device keys still have to be provided.
+//!
+//! Only the NCM keys are covered so far. The Kugou mask tables and the QMCv1
+//! static map are not shipped: `KeyId::KugouT1`, `KugouT2`, `KugouV2` and
+//! `QmcStaticKey` still have to come from the caller.
+
+/// NCM core key (`hzHRAmso5kInbaxW`), decrypting the per-file content key.
+pub const NCM_CORE_KEY: [u8; 16] = *b"hzHRAmso5kInbaxW";
+
+/// NCM metadata key (`#14ljk_!\]&0U<'(`), decrypting the metadata block.
+pub const NCM_META_KEY: [u8; 16] = *b"#14ljk_!\\]&0U<'(";
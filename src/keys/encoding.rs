@@ -0,0 +1,59 @@
+use crate::decryptor::{DecryptError, DecryptErrorCode};
+
+/// Drop what commonly sticks to pasted keys: a leading UTF-8 BOM and
+/// surrounding whitespace.
+fn trim_pasted(value: &str) -> &str {
+    let value = value.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    value
+        .strip_prefix('\u{feff}')
+        .unwrap_or(value)
+        .trim_matches(|c: char| c.is_ascii_whitespace())
+}
+
+/// Decode a hex key. Whitespace anywhere is ignored, so hex dumps can be
+/// pasted as-is.
+pub fn parse_hex(value: &str) -> Result<Vec<u8>, DecryptError> {
+    let value: String = trim_pasted(value)
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    data_encoding::HEXLOWER_PERMISSIVE
+        .decode(value.as_bytes())
+        .map_err(|_| DecryptError::new(DecryptErrorCode::InvalidKeyEncoding, "invalid hex key"))
+}
+
+pub fn parse_base64(value: &str) -> Result<Vec<u8>, DecryptError> {
+    base64::decode(trim_pasted(value))
+        .map_err(|_| DecryptError::new(DecryptErrorCode::InvalidKeyEncoding, "invalid base64 key"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_base64, parse_hex};
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("0aFf").unwrap(), vec![0x0a, 0xff]);
+        assert!(parse_hex("0g").is_err());
+        assert!(parse_hex("abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_base64() {
+        assert_eq!(parse_base64("UGFyYWtlZXQ=").unwrap(), b"Parakeet");
+        assert!(parse_base64("UGFy!").is_err());
+    }
+
+    #[test]
+    fn test_parse_pasted_keys() {
+        let key = vec![0x0a, 0xff, 0x12, 0x34];
+        assert_eq!(parse_hex(" 0aff1234\r\n").unwrap(), key);
+        assert_eq!(parse_hex("0a ff\n12 34\n").unwrap(), key);
+        assert_eq!(parse_hex("\u{feff}0aff1234").unwrap(), key);
+        assert!(parse_hex("0a\u{feff}ff").is_err());
+
+        assert_eq!(parse_base64("\tUGFyYWtlZXQ=\n").unwrap(), b"Parakeet");
+        assert_eq!(parse_base64("\u{feff}UGFyYWtlZXQ=").unwrap(), b"Parakeet");
+        assert!(parse_base64("UGFy YWtlZXQ=").is_err());
+    }
+}
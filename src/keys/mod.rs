@@ -0,0 +1,274 @@
+#[cfg(feature = "default-keys")]
+pub mod defaults;
+pub mod encoding;
+
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    decryption::AudioFormat,
+    decryptor::{DecryptError, DecryptErrorCode},
+};
+
+/// Identifies a piece of key material required by one of the decryptors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyId {
+    JooxUuid,
+    KugouT1,
+    KugouT2,
+    KugouV2,
+    KugouVprKey,
+    KuwoKey,
+    NcmCoreKey,
+    QmcStaticKey,
+    XimalayaX2mKey,
+    XimalayaX3mKey,
+    XimalayaScrambleSeed,
+}
+
+impl KeyId {
+    pub const ALL: &'static [KeyId] = &[
+        KeyId::JooxUuid,
+        KeyId::KugouT1,
+        KeyId::KugouT2,
+        KeyId::KugouV2,
+        KeyId::KugouVprKey,
+        KeyId::KuwoKey,
+        KeyId::NcmCoreKey,
+        KeyId::QmcStaticKey,
+        KeyId::XimalayaX2mKey,
+        KeyId::XimalayaX3mKey,
+        KeyId::XimalayaScrambleSeed,
+    ];
+
+    /// Name of the environment variable (and key file entry) for this key.
+    pub fn env_name(&self) -> &'static str {
+        match self {
+            KeyId::JooxUuid => "PARAKEET_JOOX_UUID",
+            KeyId::KugouT1 => "PARAKEET_KUGOU_T1",
+            KeyId::KugouT2 => "PARAKEET_KUGOU_T2",
+            KeyId::KugouV2 => "PARAKEET_KUGOU_V2",
+            KeyId::KugouVprKey => "PARAKEET_KUGOU_VPR_KEY",
+            KeyId::KuwoKey => "PARAKEET_KUWO_KEY",
+            KeyId::NcmCoreKey => "PARAKEET_NCM_CORE_KEY",
+            KeyId::QmcStaticKey => "PARAKEET_QMC_STATIC_KEY",
+            KeyId::XimalayaX2mKey => "PARAKEET_XIMALAYA_X2M_KEY",
+            KeyId::XimalayaX3mKey => "PARAKEET_XIMALAYA_X3M_KEY",
+            KeyId::XimalayaScrambleSeed => "PARAKEET_XIMALAYA_SCRAMBLE_SEED",
+        }
+    }
+
+    /// Decode a textual key value: the Joox UUID is used as-is, everything
+    /// else is binary and expected to be hex encoded.
+    pub fn parse_value(&self, value: &str) -> Result<Vec<u8>, DecryptError> {
+        match self {
+            KeyId::JooxUuid => Ok(Vec::from(value.as_bytes())),
+            _ => encoding::parse_hex(value),
+        }
+    }
+}
+
+/// Expected shape of a key value, as accepted by the decryptors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// Raw bytes of exactly this length.
+    Bytes(usize),
+    /// Raw bytes of at least this length.
+    MinBytes(usize),
+    /// A UTF-8 string, used as-is.
+    Text,
+}
+
+/// A key a format needs before it can be decrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequiredKey {
+    pub id: KeyId,
+    pub format: KeyFormat,
+}
+
+impl RequiredKey {
+    pub const fn new(id: KeyId, format: KeyFormat) -> Self {
+        Self { id, format }
+    }
+}
+
+pub trait KeyProvider {
+    fn get_key(&self, id: KeyId) -> Option<Vec<u8>>;
+}
+
+/// What a file footer tells about a file whose key is held by a key server.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResolveContext {
+    /// Numeric id of the file (e.g. the QMC song id).
+    pub file_id: String,
+    /// Opaque id of the resource, when known (e.g. the QMC song mid).
+    pub resource_id: Option<String>,
+}
+
+/// Fetches the key of files not carrying one, e.g. QMCv2 files ending with an
+/// `STag` footer. Applications plug in their own key server.
+pub trait KeyResolver {
+    /// Return the decoded file key for `format`.
+    fn resolve(&self, format: AudioFormat, ctx: &ResolveContext) -> Result<Vec<u8>, DecryptError>;
+}
+
+/// A fixed set of keys, assembled from explicit values, the environment or a
+/// key file.
+///
+/// Sources can be layered with [`StaticKeyProvider::or`], e.g. to give
+/// explicit values precedence over the environment, and the environment
+/// precedence over a key file:
+///
+/// ```no_run
+/// use parakeet_core::keys::StaticKeyProvider;
+///
+/// let keys = StaticKeyProvider::new()
+///     .or(StaticKeyProvider::from_env().unwrap())
+///     .or(StaticKeyProvider::from_key_file("keys.env").unwrap());
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct StaticKeyProvider {
+    keys: HashMap<KeyId, Vec<u8>>,
+}
+
+impl StaticKeyProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_key<T: AsRef<[u8]>>(mut self, id: KeyId, key: T) -> Self {
+        self.set_key(id, key);
+        self
+    }
+
+    pub fn set_key<T: AsRef<[u8]>>(&mut self, id: KeyId, key: T) {
+        self.keys.insert(id, Vec::from(key.as_ref()));
+    }
+
+    /// Fill in keys missing from `self` using `fallback`.
+    pub fn or(mut self, fallback: StaticKeyProvider) -> Self {
+        for (id, key) in fallback.keys {
+            self.keys.entry(id).or_insert(key);
+        }
+        self
+    }
+
+    pub fn from_env() -> Result<Self, DecryptError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Look up every [`KeyId::env_name`] through `lookup`.
+    pub fn from_vars<F: Fn(&str) -> Option<String>>(lookup: F) -> Result<Self, DecryptError> {
+        let mut result = Self::new();
+        for id in KeyId::ALL {
+            if let Some(value) = lookup(id.env_name()) {
+                result.set_key(*id, id.parse_value(value.trim())?);
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn from_key_file<P: AsRef<Path>>(path: P) -> Result<Self, DecryptError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|_| DecryptError::new(DecryptErrorCode::IOError, "could not read key file"))?;
+        Self::parse_key_file(&content)
+    }
+
+    /// Parse `NAME=value` lines, using the same names as the environment
+    /// variables. Empty lines and lines starting with `#` are ignored.
+    pub fn parse_key_file(content: &str) -> Result<Self, DecryptError> {
+        let mut entries = HashMap::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = line.split_once('=').ok_or_else(|| {
+                DecryptError::new(
+                    DecryptErrorCode::InvalidKeyEncoding,
+                    "malformed key file line",
+                )
+            })?;
+            entries.insert(name.trim().to_string(), value.trim().to_string());
+        }
+
+        Self::from_vars(|name| entries.get(name).cloned())
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn get_key(&self, id: KeyId) -> Option<Vec<u8>> {
+        self.keys.get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{KeyId, KeyProvider, StaticKeyProvider};
+
+    #[test]
+    #[cfg(feature = "ximalaya")]
+    fn test_keys_from_vars_decrypt() {
+        use crate::{
+            decryption::ximalaya::{build_scramble_table, new_x2m},
+            utils::test_util::test::{decrypt_test_content, generate_test_data, TEST_SIZE_1MB},
+        };
+
+        let key = data_encoding::HEXLOWER.encode(&generate_test_data(4, "x2m content key"));
+        let seed = data_encoding::HEXLOWER.encode(&generate_test_data(2048, "x2m seed"));
+
+        let keys = StaticKeyProvider::from_vars(|name| match name {
+            "PARAKEET_XIMALAYA_X2M_KEY" => Some(key.clone()),
+            "PARAKEET_XIMALAYA_SCRAMBLE_SEED" => Some(format!("{}\n", seed)),
+            _ => None,
+        })
+        .unwrap();
+
+        let key = keys.get_key(KeyId::XimalayaX2mKey).unwrap();
+        let seed = keys.get_key(KeyId::XimalayaScrambleSeed).unwrap();
+        let mut decryptor = new_x2m(
+            key.try_into().unwrap(),
+            build_scramble_table(&seed).unwrap(),
+        );
+
+        let test_data = generate_test_data(TEST_SIZE_1MB, "x2m-test-data");
+        let result = decrypt_test_content(&mut decryptor, test_data);
+        assert_eq!(
+            result,
+            "fd1ac1c4750f48b8d3c9562013f1c3202b12e45137b344995eda32a4f6b8a61f"
+        );
+    }
+
+    #[test]
+    fn test_key_source_precedence() {
+        let explicit = StaticKeyProvider::new().with_key(KeyId::JooxUuid, "explicit");
+        let env = StaticKeyProvider::from_vars(|name| match name {
+            "PARAKEET_JOOX_UUID" => Some("env".to_string()),
+            "PARAKEET_NCM_CORE_KEY" => Some("00112233".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let file = StaticKeyProvider::parse_key_file(concat!(
+            "# comment\n",
+            "PARAKEET_JOOX_UUID = file\n",
+            "PARAKEET_NCM_CORE_KEY = ffffffff\n",
+            "PARAKEET_KUWO_KEY = 0102\n",
+        ))
+        .unwrap();
+
+        let keys = explicit.or(env).or(file);
+        assert_eq!(keys.get_key(KeyId::JooxUuid).unwrap(), b"explicit");
+        assert_eq!(
+            keys.get_key(KeyId::NcmCoreKey).unwrap(),
+            [0x00, 0x11, 0x22, 0x33]
+        );
+        assert_eq!(keys.get_key(KeyId::KuwoKey).unwrap(), [0x01, 0x02]);
+        assert_eq!(keys.get_key(KeyId::XimalayaX3mKey), None);
+    }
+
+    #[test]
+    fn test_key_file_rejects_bad_values() {
+        assert!(StaticKeyProvider::parse_key_file("PARAKEET_KUWO_KEY = xyz").is_err());
+        assert!(StaticKeyProvider::parse_key_file("PARAKEET_KUWO_KEY").is_err());
+    }
+}
@@ -1,5 +1,11 @@
+pub mod adapters;
+pub mod container;
 pub mod decryption;
 pub mod decryptor;
+pub mod io;
+pub mod keys;
+pub mod prelude;
+#[cfg(feature = "qmc")]
 pub mod tencent;
 pub mod utils;
 
@@ -0,0 +1,39 @@
+//! The commonly needed types and factories, for a single glob import.
+//!
+//! ```
+//! use parakeet_core::prelude::*;
+//!
+//! let data = b"fLaC\x00\x00\x00\x22 not really audio";
+//! let format = detect_format(data).unwrap();
+//! assert_eq!(format, AudioFormat::Plain(AudioType::FLAC));
+//!
+//! let mut decryptor = new_decryptor(format, &StaticKeyProvider::new()).unwrap();
+//! decryptor.write(data).unwrap();
+//! decryptor.end().unwrap();
+//! assert_eq!(decryptor.read_all_output(), data);
+//! ```
+
+pub use crate::{
+    decryption::{
+        decryptor_from_extension, detect_format, new_decryptor, new_passthrough, required_keys,
+        AudioFormat, DecryptorInfo,
+    },
+    decryptor::{DecryptError, DecryptErrorCode, Decryptor, DecryptorExt},
+    keys::{KeyId, KeyProvider, StaticKeyProvider},
+    utils::audio::AudioType,
+};
+
+#[cfg(feature = "joox")]
+pub use crate::decryption::joox::new_joox;
+#[cfg(feature = "kuwo")]
+pub use crate::decryption::new_kwm;
+#[cfg(feature = "ncm")]
+pub use crate::decryption::{ncm::NcmBuilder, new_ncm, new_ncm_audio_only, new_ncm_with_metadata};
+#[cfg(feature = "kugou")]
+pub use crate::decryption::{
+    new_kgm, new_kgm_with_table, new_vpr, new_vpr_with_table, KgmMaskTable,
+};
+#[cfg(feature = "qmc")]
+pub use crate::decryption::{new_qmc_v1, new_qmc_v2, new_qmc_v2_with_key};
+#[cfg(feature = "ximalaya")]
+pub use crate::decryption::{new_x2m, new_x3m, new_ximalaya, try_new_x2m, try_new_x3m};
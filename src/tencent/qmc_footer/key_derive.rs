@@ -43,28 +43,30 @@ pub fn parse_ekey(ekey: &str) -> Option<Box<[u8]>> {
 }
 
 #[cfg(test)]
-mod test {
+pub mod test {
     use crate::utils::test_util::test::generate_test_data;
 
+    /// The "qmcv2 rc4 cipher key" test key (first 8 bytes set to `'4'`), as
+    /// an ekey.
+    pub const TEST_EKEY: &str = concat!(
+        "NDQ0NDQ0NDQeUefW/SNkzbCL/cLZx5vkzk/fVdAN3tJaTQt6ES1bX3qHHwYFJKiFcQBYf1bU1Ywf",
+        "jzpJxGa1tmLwuLL648K5zYEzPDQzigPQyi55pfR9MZxTC5LoCwWj5LK6kaLoWs3yzQ0rDkMEpfbp",
+        "s1hl+0Xo341OI9uTrJ8MFK3OiihEXItE74RCDB5fLpuGB1M+WMdETNR6F2Yd+QdKZcIrXiJXOmCu",
+        "5zvxFPxSq6ofpg23K4NM26Z/nGgeWIHQqVqCXaXmKiKTSYSpdtbqNsITUa0PqEbLF0h0ZjQba2+N",
+        "3udJPQJRUMJKbhpIvlJWoHDQzy5D4fmgf49aPYwx5daPRCnA9t7MfbExXguDHKtRHGyUbNJD5OZ3",
+        "CHOe5sa+7AxQ+64qhyzaXTi4wM4mNn/EZxIwFzTIGGqzv11qOZFaWhgNBUPorMiMZe0BpF4OdCZG",
+        "AXh/MBFp79Ruoiwp/nhp9AodEyEC8ni2rjaJGe33wjNpjzL5HUq4qiax1t6o+KcUdmZvQdx+wfo5",
+        "gSkavob1Bwm5Nyq93YnPnXEttR2pp+c04fmpdIPu0OQgaX0WPsTYRO7i4xAab2s77UiVP4IXxsY8",
+        "aHzDG3IRMalr7fHFLLVSX9bqlk8kigao5gho2/oZD6eT1Uct59WesYQ/q3yST0PCSMCCefwONYgI",
+        "8IcRDCzOgguq/P3uZpO9"
+    );
+
     #[test]
     fn test_ekey_generation() {
         let mut test_key = generate_test_data(512, "qmcv2 rc4 cipher key");
         test_key[0..8].fill(b'4');
 
-        let pre_generated_key = concat!(
-            "NDQ0NDQ0NDQeUefW/SNkzbCL/cLZx5vkzk/fVdAN3tJaTQt6ES1bX3qHHwYFJKiFcQBYf1bU1Ywf",
-            "jzpJxGa1tmLwuLL648K5zYEzPDQzigPQyi55pfR9MZxTC5LoCwWj5LK6kaLoWs3yzQ0rDkMEpfbp",
-            "s1hl+0Xo341OI9uTrJ8MFK3OiihEXItE74RCDB5fLpuGB1M+WMdETNR6F2Yd+QdKZcIrXiJXOmCu",
-            "5zvxFPxSq6ofpg23K4NM26Z/nGgeWIHQqVqCXaXmKiKTSYSpdtbqNsITUa0PqEbLF0h0ZjQba2+N",
-            "3udJPQJRUMJKbhpIvlJWoHDQzy5D4fmgf49aPYwx5daPRCnA9t7MfbExXguDHKtRHGyUbNJD5OZ3",
-            "CHOe5sa+7AxQ+64qhyzaXTi4wM4mNn/EZxIwFzTIGGqzv11qOZFaWhgNBUPorMiMZe0BpF4OdCZG",
-            "AXh/MBFp79Ruoiwp/nhp9AodEyEC8ni2rjaJGe33wjNpjzL5HUq4qiax1t6o+KcUdmZvQdx+wfo5",
-            "gSkavob1Bwm5Nyq93YnPnXEttR2pp+c04fmpdIPu0OQgaX0WPsTYRO7i4xAab2s77UiVP4IXxsY8",
-            "aHzDG3IRMalr7fHFLLVSX9bqlk8kigao5gho2/oZD6eT1Uct59WesYQ/q3yST0PCSMCCefwONYgI",
-            "8IcRDCzOgguq/P3uZpO9"
-        );
-
-        match super::parse_ekey(pre_generated_key) {
+        match super::parse_ekey(TEST_EKEY) {
             Some(key) => {
                 assert_eq!(key.as_ref(), test_key);
             }
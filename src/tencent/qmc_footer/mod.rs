@@ -2,4 +2,6 @@ mod key_derive;
 mod parser;
 
 pub use parser::parse;
+pub use parser::parse_stag;
 pub use parser::QMCFooterParseResult;
+pub use parser::QMCSTagFooter;
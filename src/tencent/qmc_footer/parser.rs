@@ -13,40 +13,55 @@ impl QMCFooterParseResult {
     }
 }
 
+/// An `STag` footer: the song it belongs to, but no key.
+#[derive(Debug, PartialEq)]
+pub struct QMCSTagFooter {
+    pub song_id: String,
+    pub song_mid: String,
+    pub eof_bytes_ignore: usize,
+}
+
 mod detail {
     use std::mem::size_of;
 
-    use super::QMCFooterParseResult;
+    use super::{QMCFooterParseResult, QMCSTagFooter};
     use crate::{tencent::qmc_footer::key_derive::parse_ekey, utils::array_ext::ByteSliceExt};
 
     const MAGIC_QMC2_QTAG: u32 = u32::from_be_bytes(*b"QTag");
     const MAGIC_QMC2_STAG: u32 = u32::from_be_bytes(*b"STag");
 
-    #[inline]
-    fn parse_android_qtag_footer(buf: &[u8]) -> Option<QMCFooterParseResult> {
-        // Legacy Android format.
-        //   metadata := [ansi ekey_b64] ","
-        //               [ansi songid] ","
-        //               [ansi metadata_version '2']
-        //   eof_mark := [(be)uint32_t meta_len] [bytes 'QTag']
-        //   qmc_file := [encrypted_data] [metadata] [eof_mark]
-        //
-        // Where:
-        //   meta_len := bytes( [metadata] [eof_mark] ).size()
+    /// Split the metadata of a `QTag`/`STag` footer into its comma separated
+    /// fields, returning them with the footer length.
+    //   eof_mark := [(be)uint32_t meta_len] [bytes tag]
+    //   qmc_file := [encrypted_data] [metadata] [eof_mark]
+    //
+    // Where:
+    //   meta_len := bytes( [metadata] [eof_mark] ).size()
+    fn parse_tag_metadata(buf: &[u8]) -> Option<(Vec<String>, usize)> {
         let len = buf.len();
         let required_len = buf.read_be::<u32>(len - 2 * size_of::<u32>()) as usize;
-        if len < required_len {
+        if required_len < 2 * size_of::<u32>() || required_len > len {
             return None;
         }
 
         let line = String::from_utf8_lossy(&buf[len - required_len..len - 2 * size_of::<u32>()]);
-        let csv: Vec<&str> = line.split(',').collect();
+        let fields = line.split(',').map(String::from).collect();
+        Some((fields, required_len))
+    }
+
+    #[inline]
+    fn parse_android_qtag_footer(buf: &[u8]) -> Option<QMCFooterParseResult> {
+        // Legacy Android format.
+        //   metadata := [ansi ekey_b64] ","
+        //               [ansi songid] ","
+        //               [ansi metadata_version '2']
+        let (csv, required_len) = parse_tag_metadata(buf)?;
 
         if csv.len() != 3 || csv[2] != "2" {
             return None;
         }
 
-        let ekey_b64 = csv[0];
+        let ekey_b64 = &csv[0];
         let ekey = parse_ekey(ekey_b64)?;
 
         Some(QMCFooterParseResult::new(ekey, required_len))
@@ -60,9 +75,9 @@ mod detail {
         // qmc_file := [encrypted_data] [ekey_b64] [eof_mark]
         let len = buf.len();
         let payload_size = buf.read_le::<u32>(len - size_of::<u32>()) as usize;
-        let required_len = payload_size + size_of::<u32>();
+        let required_len = payload_size.checked_add(size_of::<u32>())?;
 
-        if required_len < len {
+        if required_len > len {
             return None;
         }
 
@@ -78,6 +93,30 @@ mod detail {
         Some(QMCFooterParseResult::new(ekey, required_len))
     }
 
+    /// Parse an `STag` footer, which only identifies the song: its key has to
+    /// be fetched from a key server.
+    //   metadata := [ansi songid] ","
+    //               [ansi songmid] ","
+    //               [ansi metadata_version '2']
+    pub fn parse_stag<T: AsRef<[u8]>>(buf: T) -> Option<QMCSTagFooter> {
+        let buf = buf.as_ref();
+        let len = buf.len();
+
+        if len < 8 || buf.read_be::<u32>(len - 4) != MAGIC_QMC2_STAG {
+            return None;
+        }
+
+        let (csv, required_len) = parse_tag_metadata(buf)?;
+        match <[String; 3]>::try_from(csv) {
+            Ok([song_id, song_mid, version]) if version == "2" => Some(QMCSTagFooter {
+                song_id,
+                song_mid,
+                eof_bytes_ignore: required_len,
+            }),
+            _ => None,
+        }
+    }
+
     pub fn parse<T: AsRef<[u8]>>(buf: T) -> Option<QMCFooterParseResult> {
         let buf = buf.as_ref();
         let len = buf.len();
@@ -89,7 +128,7 @@ mod detail {
         let eof_magic = buf.read_be::<u32>(len - 4);
 
         if eof_magic == MAGIC_QMC2_QTAG {
-            parse_android_qtag_footer(&buf[..len - 4])
+            parse_android_qtag_footer(buf)
         } else if eof_magic == MAGIC_QMC2_STAG {
             None
         } else {
@@ -99,14 +138,99 @@ mod detail {
 }
 
 pub use detail::parse;
+pub use detail::parse_stag;
 
 #[cfg(test)]
 mod tests {
-    use super::parse;
+    use proptest::prelude::*;
+
+    use super::{parse, parse_stag, QMCSTagFooter};
+    use crate::tencent::qmc_footer::key_derive::{parse_ekey, test::TEST_EKEY};
+
+    fn qtag_footer(ekey: &str) -> Vec<u8> {
+        let metadata = format!("{},123456,2", ekey);
+        let meta_len = (metadata.len() + 8) as u32;
+        [metadata.as_bytes(), &meta_len.to_be_bytes(), b"QTag"].concat()
+    }
+
+    fn pc_footer(ekey: &str) -> Vec<u8> {
+        [ekey.as_bytes(), &(ekey.len() as u32).to_le_bytes()].concat()
+    }
 
     #[test]
     fn test_parse_small_buffer_boundary_check() {
-        assert_eq!(parse(&[0u8; 7]), None);
-        assert_eq!(parse(&[0u8; 8]), None);
+        assert_eq!(parse([0u8; 7]), None);
+        assert_eq!(parse([0u8; 8]), None);
+    }
+
+    #[test]
+    fn test_parse_qtag_footer() {
+        let key = parse_ekey(TEST_EKEY).unwrap();
+        let footer = qtag_footer(TEST_EKEY);
+        let file = [&b"encrypted audio"[..], &footer].concat();
+
+        let result = parse(&file).unwrap();
+        assert_eq!(result.key, key.as_ref());
+        assert_eq!(result.eof_bytes_ignore, footer.len());
+    }
+
+    #[test]
+    fn test_parse_pc_footer() {
+        let key = parse_ekey(TEST_EKEY).unwrap();
+        let footer = pc_footer(TEST_EKEY);
+        let file = [&b"encrypted audio"[..], &footer].concat();
+
+        let result = parse(&file).unwrap();
+        assert_eq!(result.key, key.as_ref());
+        assert_eq!(result.eof_bytes_ignore, footer.len());
+    }
+
+    #[test]
+    fn test_parse_malformed_footer_lengths() {
+        // QTag with a meta_len shorter than the eof_mark itself, or longer
+        // than the buffer.
+        assert_eq!(parse(b"\0\0\0\0QTag"), None);
+        assert_eq!(parse(b"xx\0\0\0\x07QTag"), None);
+        assert_eq!(parse(b"\xff\xff\xff\xffQTag"), None);
+        // STag footers carry no key.
+        assert_eq!(parse(b"\0\0\0\x08STag"), None);
+        // PC footer payload larger than the buffer.
+        assert_eq!(parse(b"abcd\xff\xff\xff\xff"), None);
+        assert_eq!(parse(b"abcd\x05\0\0\0"), None);
+    }
+
+    #[test]
+    fn test_parse_stag_footer() {
+        let footer = b"12345,004abcdE,2\x00\x00\x00\x18STag";
+        let file = [&b"encrypted audio"[..], footer].concat();
+
+        assert_eq!(parse(&file), None);
+        assert_eq!(
+            parse_stag(&file),
+            Some(QMCSTagFooter {
+                song_id: "12345".to_string(),
+                song_mid: "004abcdE".to_string(),
+                eof_bytes_ignore: footer.len(),
+            })
+        );
+        assert_eq!(parse_stag(qtag_footer(TEST_EKEY)), None);
+        assert_eq!(parse_stag(b"12345,2\x00\x00\x00\x0fSTag"), None);
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_arbitrary_footer(
+            data in prop::collection::vec(any::<u8>(), 0..256),
+            tail in prop::sample::select(vec![&b"QTag"[..], b"STag", b""]),
+        ) {
+            let buf = [&data[..], tail].concat();
+            if let Some(result) = parse(&buf) {
+                prop_assert!(result.key.len() <= buf.len());
+                prop_assert!(result.eof_bytes_ignore <= buf.len());
+            }
+            if let Some(result) = parse_stag(&buf) {
+                prop_assert!(result.eof_bytes_ignore <= buf.len());
+            }
+        }
     }
 }
@@ -3,7 +3,9 @@ use std::mem::size_of;
 use num_traits::PrimInt;
 
 pub trait VecExtension {
+    #[allow(dead_code)]
     fn append_of_size(&mut self, len: usize) -> &mut [u8];
+    #[cfg_attr(not(feature = "qmc"), allow(dead_code))]
     fn append_data<T: AsRef<[u8]>>(&mut self, data: T) -> &mut [u8];
 }
 
@@ -22,7 +24,9 @@ impl VecExtension for Vec<u8> {
 }
 
 pub trait IntHelper {
+    #[allow(dead_code)]
     fn from_le_bytes(bytes: &[u8]) -> Self;
+    #[allow(dead_code)]
     fn from_be_bytes(bytes: &[u8]) -> Self;
 
     fn read_le(bytes: &[u8], offset: usize) -> Self;
@@ -33,6 +37,15 @@ pub trait IntHelper {
     //   a small table, which is faster than doing div/mul for
     //   modular calculation.
     // src: https://stackoverflow.com/a/33333924
+    #[cfg_attr(
+        not(any(
+            feature = "kugou",
+            feature = "ncm",
+            feature = "qmc",
+            feature = "ximalaya"
+        )),
+        allow(dead_code)
+    )]
     fn fast_mod(&self, rhs: Self) -> Self;
 }
 
@@ -74,6 +87,16 @@ macro_rules! impl_int_helper (( $($int:ident),* ) => {
 impl_int_helper!(u8, u16, u32, u64, u128, usize);
 impl_int_helper!(i8, i16, i32, i64, i128, isize);
 
+#[cfg_attr(
+    not(any(
+        feature = "kugou",
+        feature = "kuwo",
+        feature = "ncm",
+        feature = "qmc",
+        feature = "ximalaya"
+    )),
+    allow(dead_code)
+)]
 pub trait USizeable {
     fn to_usize(&self) -> usize;
 }
@@ -90,11 +113,32 @@ macro_rules! impl_unsigned_sizeable_from (( $($int:ident),* ) => {
 });
 impl_unsigned_sizeable_from!(u8, u16, u32, usize);
 
+#[cfg_attr(
+    not(any(
+        feature = "kugou",
+        feature = "kuwo",
+        feature = "ncm",
+        feature = "qmc",
+        feature = "ximalaya"
+    )),
+    allow(dead_code)
+)]
 pub trait ArrayExtension<T: PrimInt> {
+    #[cfg_attr(
+        not(any(
+            feature = "kugou",
+            feature = "ncm",
+            feature = "qmc",
+            feature = "ximalaya"
+        )),
+        allow(dead_code)
+    )]
     fn get_mod_n<I: USizeable>(&self, i: I) -> T;
     fn get_value_unchecked<I: USizeable>(&self, i: I) -> T;
 
+    #[cfg_attr(not(feature = "qmc"), allow(dead_code))]
     fn swap_unsigned_index<I: USizeable, J: USizeable>(&mut self, a: I, b: J);
+    #[cfg_attr(not(any(feature = "ncm", feature = "qmc")), allow(dead_code))]
     fn at<I: USizeable>(&self, a: I) -> T;
 }
 
@@ -120,16 +164,43 @@ impl<T: PrimInt> ArrayExtension<T> for [T] {
     }
 }
 
+/// Bit rotation of a single byte, for ciphers built on `rol`/`ror`. Public
+/// as `utils::ByteExt`; none of the decryptors here need it yet.
+pub trait ByteExt {
+    /// Rotate left by `n` bits; `n` is taken modulo 8.
+    fn rol(self, n: u32) -> u8;
+    /// Rotate right by `n` bits; `n` is taken modulo 8.
+    fn ror(self, n: u32) -> u8;
+}
+
+impl ByteExt for u8 {
+    #[inline(always)]
+    fn rol(self, n: u32) -> u8 {
+        self.rotate_left(n % 8)
+    }
+
+    #[inline(always)]
+    fn ror(self, n: u32) -> u8 {
+        self.rotate_right(n % 8)
+    }
+}
+
 pub trait ByteSliceExt {
     fn read_le<R: PrimInt + IntHelper>(&self, offset: usize) -> R;
     fn read_be<R: PrimInt + IntHelper>(&self, offset: usize) -> R;
 
+    #[cfg_attr(
+        not(any(feature = "kuwo", feature = "ncm", feature = "qmc")),
+        allow(dead_code)
+    )]
     fn xor_key_with_key_offset<T: AsRef<[u8]>>(&mut self, key: T, offset: usize);
+    #[cfg_attr(not(any(feature = "kuwo", feature = "qmc")), allow(dead_code))]
     #[inline(always)]
     fn xor_key<T: AsRef<[u8]>>(&mut self, key: T) {
         self.xor_key_with_key_offset(key, 0)
     }
 
+    #[allow(dead_code)]
     unsafe fn set_unchecked(&mut self, i: usize, value: u8);
 }
 
@@ -164,3 +235,51 @@ impl ByteSliceExt for [u8] {
         *self.get_unchecked_mut(i) = value;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{ByteExt, ByteSliceExt};
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_byte_rotation() {
+        assert_eq!(0b1000_0001u8.rol(1), 0b0000_0011);
+        assert_eq!(0b1000_0001u8.ror(1), 0b1100_0000);
+        assert_eq!(0x12u8.rol(4), 0x21);
+        assert_eq!(0xa5u8.ror(3), 0xb4);
+        assert_eq!(0xa5u8.rol(3).ror(3), 0xa5);
+
+        for v in [0x00u8, 0x5a, 0xff] {
+            assert_eq!(v.rol(0), v);
+            assert_eq!(v.rol(8), v);
+            assert_eq!(v.ror(8), v);
+            assert_eq!(v.rol(9), v.rol(1));
+        }
+    }
+
+    fn xor_reference(buf: &mut [u8], key: &[u8], offset: usize) {
+        for (i, v) in buf.iter_mut().enumerate() {
+            *v ^= key[(offset + i) % key.len()];
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(10_000))]
+
+        /// Guards `xor_key_with_key_offset` (and any faster keystream path
+        /// replacing it) against the plain per-byte XOR, across alignments.
+        #[test]
+        fn test_xor_key_with_key_offset_matches_reference(
+            key in prop::collection::vec(any::<u8>(), 1..=512),
+            offset in 0usize..0x10_0000,
+            data in prop::collection::vec(any::<u8>(), 0..2048),
+        ) {
+            let mut expected = data.clone();
+            xor_reference(&mut expected, &key, offset);
+
+            let mut actual = data;
+            actual.xor_key_with_key_offset(&key, offset);
+            prop_assert_eq!(actual, expected);
+        }
+    }
+}
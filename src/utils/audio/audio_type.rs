@@ -1,6 +1,7 @@
 const AUDIO_TYPE_MASK_LOSSLESS: isize = 1 << 5;
 
 #[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AudioType {
     UnknownType = 0,
 
@@ -22,3 +22,4 @@ mod metadata;
 pub use audio_type::AudioExtensionName;
 pub use audio_type::AudioType;
 pub use detect::detect_audio_type;
+pub use metadata::get_audio_header_metadata_size;
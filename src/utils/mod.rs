@@ -1,6 +1,15 @@
 pub mod audio;
 
+#[cfg(feature = "ncm")]
 pub(crate) mod aes_ecb;
 pub(crate) mod array_ext;
+pub mod crc32;
+pub(crate) mod md5;
+#[cfg(feature = "joox")]
+pub mod pbkdf2;
+#[cfg(any(feature = "ncm", feature = "qmc", test))]
 pub(crate) mod rc4;
+pub(crate) mod sha1;
 pub(crate) mod test_util;
+
+pub use array_ext::ByteExt;
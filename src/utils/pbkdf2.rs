@@ -0,0 +1,160 @@
+//! PBKDF2-HMAC-SHA1 (RFC 2898 / RFC 2104 / FIPS 180-1), enough for the Joox
+//! key derivation without pulling in a crypto library.
+//!
+//! With the `ring` feature, `derive_key` uses ring's implementation instead.
+
+use super::sha1::{Sha1, SHA1_BLOCK_SIZE};
+use crate::decryptor::{DecryptError, DecryptErrorCode};
+
+pub use super::sha1::SHA1_OUTPUT_LEN;
+
+/// HMAC-SHA1 with the inner and outer pads already absorbed, so each use of
+/// the same key only hashes the message.
+#[derive(Clone)]
+pub struct HmacSha1 {
+    inner: Sha1,
+    outer: Sha1,
+}
+
+impl HmacSha1 {
+    pub fn new(key: &[u8]) -> Self {
+        let mut block_key = [0u8; SHA1_BLOCK_SIZE];
+        if key.len() > SHA1_BLOCK_SIZE {
+            let mut hash = Sha1::new();
+            hash.update(key);
+            block_key[..SHA1_OUTPUT_LEN].copy_from_slice(&hash.finish());
+        } else {
+            block_key[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = Sha1::new();
+        let mut outer = Sha1::new();
+        inner.update(&block_key.map(|v| v ^ 0x36));
+        outer.update(&block_key.map(|v| v ^ 0x5c));
+        Self { inner, outer }
+    }
+
+    pub fn sign(&self, message: &[u8]) -> [u8; SHA1_OUTPUT_LEN] {
+        let mut inner = self.inner.clone();
+        inner.update(message);
+
+        let mut outer = self.outer.clone();
+        outer.update(&inner.finish());
+        outer.finish()
+    }
+}
+
+fn zero_iterations() -> DecryptError {
+    DecryptError::new(
+        DecryptErrorCode::InvalidKeyDerivation,
+        "pbkdf2 iterations must not be zero",
+    )
+}
+
+#[cfg(not(feature = "ring"))]
+pub fn derive_key(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    out: &mut [u8],
+) -> Result<(), DecryptError> {
+    if iterations == 0 {
+        return Err(zero_iterations());
+    }
+
+    let hmac = HmacSha1::new(password);
+
+    for (i, chunk) in out.chunks_mut(SHA1_OUTPUT_LEN).enumerate() {
+        let block_index = (i as u32 + 1).to_be_bytes();
+        let mut u = hmac.sign(&[salt, &block_index].concat());
+        let mut t = u;
+        for _ in 1..iterations {
+            u = hmac.sign(&u);
+            for (t, u) in t.iter_mut().zip(u) {
+                *t ^= u;
+            }
+        }
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "ring")]
+pub fn derive_key(
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    out: &mut [u8],
+) -> Result<(), DecryptError> {
+    let iterations = std::num::NonZeroU32::new(iterations).ok_or_else(zero_iterations)?;
+    ring::pbkdf2::derive(
+        ring::pbkdf2::PBKDF2_HMAC_SHA1,
+        iterations,
+        salt,
+        password,
+        out,
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{derive_key, HmacSha1};
+    use crate::decryptor::DecryptErrorCode;
+
+    fn hex(data: &[u8]) -> String {
+        data_encoding::HEXLOWER.encode(data)
+    }
+
+    #[test]
+    fn test_hmac_sha1() {
+        // RFC 2202, test case 2.
+        let hmac = HmacSha1::new(b"Jefe");
+        assert_eq!(
+            hex(&hmac.sign(b"what do ya want for nothing?")),
+            "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79"
+        );
+    }
+
+    fn pbkdf2(password: &[u8], salt: &[u8], iterations: u32, len: usize) -> String {
+        let mut out = vec![0u8; len];
+        derive_key(password, salt, iterations, &mut out).unwrap();
+        hex(&out)
+    }
+
+    #[test]
+    fn test_pbkdf2_rfc6070() {
+        assert_eq!(
+            pbkdf2(b"password", b"salt", 1, 20),
+            "0c60c80f961f0e71f3a9b524af6012062fe037a6"
+        );
+        assert_eq!(
+            pbkdf2(b"password", b"salt", 2, 20),
+            "ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957"
+        );
+        assert_eq!(
+            pbkdf2(b"password", b"salt", 4096, 20),
+            "4b007901b765489abead49d926f721d065a429c1"
+        );
+        assert_eq!(
+            pbkdf2(
+                b"passwordPASSWORDpassword",
+                b"saltSALTsaltSALTsaltSALTsaltSALTsalt",
+                4096,
+                25
+            ),
+            "3d2eec4fe41c849b80c8d83662c0e44a8b291a964cf2f07038"
+        );
+        assert_eq!(
+            pbkdf2(b"pass\0word", b"sa\0lt", 4096, 16),
+            "56fa6aa75548099dcc37d7f03425e0c3"
+        );
+    }
+
+    #[test]
+    fn test_pbkdf2_zero_iterations() {
+        let mut out = [0u8; 20];
+        let err = derive_key(b"password", b"salt", 0, &mut out).unwrap_err();
+        assert!(matches!(err.code(), DecryptErrorCode::InvalidKeyDerivation));
+    }
+}
@@ -1,9 +1,17 @@
+#[cfg_attr(not(any(feature = "ncm", test)), allow(dead_code))]
 mod base;
+#[cfg(feature = "ncm")]
 mod netease;
+#[cfg(test)]
 mod standard;
+#[cfg(feature = "qmc")]
 mod tencent_qmc_v2;
 
+#[cfg_attr(not(any(feature = "ncm", test)), allow(unused_imports))]
 pub use base::{RC4Derive, RC4State};
+#[cfg(feature = "ncm")]
 pub use netease::RC4Netease;
+#[cfg(test)]
 pub use standard::RC4Standard;
+#[cfg(feature = "qmc")]
 pub use tencent_qmc_v2::RC4TencentQmcV2;
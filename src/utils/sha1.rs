@@ -0,0 +1,123 @@
+//! SHA-1 (FIPS 180-1), computed incrementally. Used by the PBKDF2 fallback
+//! and for matching legacy checksums; not for anything security related.
+
+pub const SHA1_OUTPUT_LEN: usize = 20;
+pub const SHA1_BLOCK_SIZE: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    block: [u8; SHA1_BLOCK_SIZE],
+    block_len: usize,
+    total_len: u64,
+}
+
+impl Default for Sha1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha1 {
+    pub fn new() -> Self {
+        Self {
+            state: [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0],
+            block: [0u8; SHA1_BLOCK_SIZE],
+            block_len: 0,
+            total_len: 0,
+        }
+    }
+
+    fn compress(state: &mut [u32; 5], block: &[u8; SHA1_BLOCK_SIZE]) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = *state;
+        for (i, w) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*w);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+
+        while !data.is_empty() {
+            let n = std::cmp::min(SHA1_BLOCK_SIZE - self.block_len, data.len());
+            self.block[self.block_len..self.block_len + n].copy_from_slice(&data[..n]);
+            self.block_len += n;
+            data = &data[n..];
+
+            if self.block_len == SHA1_BLOCK_SIZE {
+                Self::compress(&mut self.state, &self.block);
+                self.block_len = 0;
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> [u8; SHA1_OUTPUT_LEN] {
+        let bit_len = self.total_len.wrapping_mul(8);
+
+        self.update(&[0x80]);
+        while self.block_len != SHA1_BLOCK_SIZE - 8 {
+            self.update(&[0]);
+        }
+        self.update(&bit_len.to_be_bytes());
+
+        let mut result = [0u8; SHA1_OUTPUT_LEN];
+        for (out, s) in result.chunks_exact_mut(4).zip(self.state) {
+            out.copy_from_slice(&s.to_be_bytes());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Sha1;
+
+    fn hex(data: &[u8]) -> String {
+        data_encoding::HEXLOWER.encode(data)
+    }
+
+    #[test]
+    fn test_sha1() {
+        let mut hash = Sha1::new();
+        hash.update(b"abc");
+        assert_eq!(
+            hex(&hash.finish()),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+
+        let mut hash = Sha1::new();
+        hash.update(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq");
+        assert_eq!(
+            hex(&hash.finish()),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+}
@@ -1,8 +1,12 @@
 #[cfg(test)]
 pub mod test {
 
+    #[cfg(feature = "ximalaya")]
+    use crate::decryption::ximalaya::{self, ScrambleTable, X2MContentKey, X3MContentKey};
+    #[cfg(feature = "kuwo")]
+    use crate::utils::array_ext::ByteSliceExt;
     use crate::{
-        decryptor::Decryptor,
+        decryptor::{DecryptError, Decryptor},
         utils::rc4::{RC4Derive, RC4Standard},
     };
     use ring::digest::{Context, SHA256};
@@ -18,31 +22,391 @@ pub mod test {
     #[allow(unused)]
     pub const TEST_SIZE_8MB: usize = TEST_SIZE_1MB * 8;
 
-    pub fn generate_test_data(len: usize, name: &str) -> Vec<u8> {
-        let mut result = vec![0u8; len];
+    /// Byte source for deterministic test data.
+    pub trait TestRng {
+        fn next_byte(&mut self) -> u8;
+    }
+
+    /// The test data generator shared by the parakeet ports: the standard RC4
+    /// keystream, keyed with the name of the data set.
+    pub struct ParakeetRng(RC4Standard);
+
+    impl ParakeetRng {
+        pub fn new(name: &str) -> Self {
+            Self(RC4Standard::new(name.as_bytes()))
+        }
+    }
 
-        let mut rc4 = RC4Standard::new(name.as_bytes());
-        for v in result.iter_mut() {
-            *v = rc4.next();
+    impl TestRng for ParakeetRng {
+        fn next_byte(&mut self) -> u8 {
+            self.0.next()
         }
+    }
 
-        result
+    pub fn generate_test_data_with(rng: &mut impl TestRng, len: usize) -> Vec<u8> {
+        (0..len).map(|_| rng.next_byte()).collect()
+    }
+
+    pub fn generate_test_data(len: usize, name: &str) -> Vec<u8> {
+        generate_test_data_with(&mut ParakeetRng::new(name), len)
     }
 
     pub fn sha256(data: &[u8]) -> String {
         let mut context = Context::new(&SHA256);
         context.update(data);
         let result = context.finish();
-        return data_encoding::HEXLOWER.encode(result.as_ref());
+        data_encoding::HEXLOWER.encode(result.as_ref())
     }
 
-    pub fn decrypt_test_content<T: AsRef<[u8]>>(decryptor: &mut impl Decryptor, data: T) -> String {
+    #[allow(unused)]
+    pub fn decrypt_test_content<T: AsRef<[u8]>>(
+        decryptor: &mut (impl Decryptor + ?Sized),
+        data: T,
+    ) -> String {
         let data = data.as_ref();
         decryptor.write(data).unwrap();
         decryptor.end().unwrap();
         sha256(decryptor.read_all_output().as_ref())
     }
 
+    #[allow(unused)]
+    /// Chunk sizes used to split the input, `0` meaning "everything at once".
+    pub const ROUNDTRIP_CHUNK_PATTERNS: &[usize] = &[0, 1, 7, 1023, 4096 + 3];
+
+    pub fn decrypt_in_chunks(
+        decryptor: &mut (impl Decryptor + ?Sized),
+        data: &[u8],
+        chunk: usize,
+    ) -> Vec<u8> {
+        let chunk = if chunk == 0 { data.len().max(1) } else { chunk };
+        let mut result = vec![];
+        for p in data.chunks(chunk) {
+            decryptor.write(p).unwrap();
+            result.append(&mut decryptor.read_all_output());
+        }
+        decryptor.end().unwrap();
+        result.append(&mut decryptor.read_all_output());
+        result
+    }
+
+    #[allow(unused)]
+    /// Like [`decrypt_in_chunks`], cutting `data` into chunks of the sizes in
+    /// `chunks` (cycled until the input runs out).
+    pub fn decrypt_in_chunk_sizes(
+        decryptor: &mut (impl Decryptor + ?Sized),
+        mut data: &[u8],
+        chunks: &[usize],
+    ) -> Vec<u8> {
+        assert!(chunks.iter().all(|&chunk| chunk > 0), "empty chunk");
+        let mut result = vec![];
+        for &chunk in chunks.iter().cycle() {
+            if data.is_empty() {
+                break;
+            }
+            let (p, rest) = data.split_at(chunk.min(data.len()));
+            decryptor.write(p).unwrap();
+            result.append(&mut decryptor.read_all_output());
+            data = rest;
+        }
+        decryptor.end().unwrap();
+        result.append(&mut decryptor.read_all_output());
+        result
+    }
+
+    /// Decrypt `data`, `reset`, then decrypt it again (in a differently sized
+    /// chunk pattern): both runs must match a fresh decryptor, so no state
+    /// leaks across a reset.
+    pub fn assert_deterministic_after_reset<F, D>(factory: F, data: &[u8])
+    where
+        F: Fn() -> D,
+        D: Decryptor,
+    {
+        let expected = decrypt_in_chunks(&mut factory(), data, 0);
+
+        let mut decryptor = factory();
+        assert_eq!(decrypt_in_chunks(&mut decryptor, data, 0x100), expected);
+        decryptor.reset().unwrap();
+        assert_eq!(decrypt_in_chunks(&mut decryptor, data, 7), expected);
+
+        // Reset midway through a run as well.
+        decryptor.reset().unwrap();
+        decryptor.write(&data[..data.len() / 2]).unwrap();
+        decryptor.reset().unwrap();
+        assert_eq!(decrypt_in_chunks(&mut decryptor, data, 0x1000), expected);
+    }
+
+    #[allow(unused)]
+    /// For each size, encrypt deterministic test data and check that streaming
+    /// the ciphertext through a fresh decryptor in every chunk pattern gives the
+    /// plaintext back.
+    pub fn verify_roundtrip<E, F, D>(encrypt: E, new_decryptor: F, sizes: &[usize])
+    where
+        E: Fn(&[u8]) -> Vec<u8>,
+        F: Fn() -> D,
+        D: Decryptor,
+    {
+        for &size in sizes {
+            let plain = generate_test_data(size, &format!("roundtrip data {}", size));
+            let encrypted = encrypt(&plain);
+
+            for &chunk in ROUNDTRIP_CHUNK_PATTERNS {
+                let mut decryptor = new_decryptor();
+                let result = decrypt_in_chunks(&mut decryptor, &encrypted, chunk);
+                assert!(
+                    result == plain,
+                    "roundtrip mismatch: size={}, chunk={}",
+                    size,
+                    chunk
+                );
+            }
+        }
+    }
+
+    /// Generate the tests every format should pass, in a module named `$name`:
+    ///
+    /// - ending an empty stream gives `$empty_end`, matched against the
+    ///   `end()` result mapped to its error code, and produces no output;
+    /// - every [`ROUNDTRIP_CHUNK_PATTERNS`] split decrypts like the whole input;
+    /// - so does a random split (by proptest), catching state lost at a cut
+    ///   inside a header or segment;
+    /// - the output is the same after a [`Decryptor::reset`];
+    /// - `write` after `end` fails until the next reset;
+    /// - the decryptor is `Send`.
+    ///
+    /// `$factory` creates a fresh decryptor, `$input_builder` turns plaintext
+    /// into the matching ciphertext. Both are evaluated inside the generated
+    /// module, with the enclosing module's items in scope. `$empty_end` is a
+    /// pattern such as `Err(DecryptErrorCode::EmptyInput)` or `Ok(())`.
+    #[allow(unused_macros)]
+    macro_rules! decryptor_conformance {
+        ($name:ident, $factory:expr, $input_builder:expr, $empty_end:pat) => {
+            mod $name {
+                #[allow(unused_imports)]
+                use super::*;
+                use proptest::prelude::*;
+                use $crate::{
+                    decryptor::{DecryptErrorCode, Decryptor},
+                    utils::test_util::test::{
+                        assert_deterministic_after_reset, decrypt_in_chunk_sizes,
+                        decrypt_in_chunks, generate_test_data, ROUNDTRIP_CHUNK_PATTERNS,
+                    },
+                };
+
+                const CONFORMANCE_SIZE: usize = 0x8000 + 17;
+
+                fn input() -> (Vec<u8>, Vec<u8>) {
+                    let plain = generate_test_data(CONFORMANCE_SIZE, stringify!($name));
+                    let encrypted = ($input_builder)(&plain[..]);
+                    (plain, encrypted)
+                }
+
+                #[test]
+                fn empty_input() {
+                    let mut decryptor = ($factory)();
+                    let result = decryptor.end();
+                    assert!(
+                        matches!(result.as_ref().map_err(|err| err.code()), $empty_end),
+                        "end() on empty input: {:?}",
+                        result
+                    );
+                    assert!(decryptor.read_all_output().is_empty());
+                }
+
+                #[test]
+                fn chunked_equals_whole() {
+                    let (plain, encrypted) = input();
+                    for &chunk in ROUNDTRIP_CHUNK_PATTERNS {
+                        let result = decrypt_in_chunks(&mut ($factory)(), &encrypted, chunk);
+                        assert!(result == plain, "chunk={}", chunk);
+                    }
+                }
+
+                proptest! {
+                    #![proptest_config(ProptestConfig::with_cases(64))]
+                    #[test]
+                    fn random_chunking_equals_whole(
+                        chunks in prop::collection::vec(1usize..0x1400, 1..16),
+                    ) {
+                        let (plain, encrypted) = input();
+                        let result =
+                            decrypt_in_chunk_sizes(&mut ($factory)(), &encrypted, &chunks);
+                        prop_assert!(result == plain, "chunks={:?}", chunks);
+                    }
+                }
+
+                #[test]
+                fn deterministic_after_reset() {
+                    let (_, encrypted) = input();
+                    assert_deterministic_after_reset($factory, &encrypted);
+                }
+
+                #[test]
+                fn write_after_end() {
+                    let (_, encrypted) = input();
+                    let mut decryptor = ($factory)();
+                    decrypt_in_chunks(&mut decryptor, &encrypted, 0);
+                    let err = decryptor.write(&encrypted).unwrap_err();
+                    assert!(matches!(err.code(), DecryptErrorCode::WriteAfterFinalize));
+
+                    if decryptor.reset().is_ok() {
+                        decryptor.write(&encrypted).unwrap();
+                    }
+                }
+
+                #[test]
+                fn is_send() {
+                    fn assert_send<T: Send>(_: &T) {}
+                    assert_send(&($factory)());
+                }
+            }
+        };
+    }
+
+    #[allow(unused_imports)]
+    pub(crate) use decryptor_conformance;
+
+    /// Simulates a slow cipher: each `write` forwards at most `max_per_write`
+    /// bytes to the inner decryptor, buffering the rest until later writes
+    /// (or `end`).
+    pub struct SlowDecryptor<D: Decryptor> {
+        inner: D,
+        max_per_write: usize,
+        pending: Vec<u8>,
+    }
+
+    #[allow(unused)]
+    impl<D: Decryptor> SlowDecryptor<D> {
+        pub fn new(inner: D, max_per_write: usize) -> Self {
+            assert!(max_per_write > 0);
+            Self {
+                inner,
+                max_per_write,
+                pending: vec![],
+            }
+        }
+
+        /// Input accepted but not yet processed.
+        pub fn pending(&self) -> usize {
+            self.pending.len()
+        }
+    }
+
+    impl<D: Decryptor> Decryptor for SlowDecryptor<D> {
+        fn write(&mut self, data: &[u8]) -> Result<(), DecryptError> {
+            self.pending.extend_from_slice(data);
+            let n = self.pending.len().min(self.max_per_write);
+            let chunk: Vec<u8> = self.pending.drain(..n).collect();
+            self.inner.write(&chunk)
+        }
+
+        fn end(&mut self) -> Result<(), DecryptError> {
+            let pending = std::mem::take(&mut self.pending);
+            self.inner.write(&pending)?;
+            self.inner.end()
+        }
+
+        fn get_name(&self) -> &str {
+            self.inner.get_name()
+        }
+
+        fn read_all_output(&mut self) -> Vec<u8> {
+            self.inner.read_all_output()
+        }
+
+        fn get_eof_reserve(&self) -> usize {
+            self.inner.get_eof_reserve()
+        }
+
+        fn output_ready(&self) -> usize {
+            self.inner.output_ready()
+        }
+
+        fn consume_output(&mut self, len: usize) -> Vec<u8> {
+            self.inner.consume_output(len)
+        }
+    }
+
+    #[cfg(any(feature = "kuwo", feature = "ximalaya"))]
+    enum EncryptedFormat {
+        #[cfg(feature = "ximalaya")]
+        Ximalaya(Vec<u8>, Box<ScrambleTable>),
+        #[cfg(feature = "kuwo")]
+        Kuwo([u8; 0x20], u64),
+    }
+
+    #[cfg(any(feature = "kuwo", feature = "ximalaya"))]
+    /// Assembles a synthetic encrypted file (header + body) for a format.
+    pub struct EncryptedBuilder {
+        format: EncryptedFormat,
+        body: Vec<u8>,
+    }
+
+    #[cfg(any(feature = "kuwo", feature = "ximalaya"))]
+    #[allow(unused)]
+    impl EncryptedBuilder {
+        #[cfg(feature = "ximalaya")]
+        pub fn x2m(key: X2MContentKey, scramble_table: ScrambleTable) -> Self {
+            Self::ximalaya(key, scramble_table)
+        }
+
+        #[cfg(feature = "ximalaya")]
+        pub fn x3m(key: X3MContentKey, scramble_table: ScrambleTable) -> Self {
+            Self::ximalaya(key, scramble_table)
+        }
+
+        #[cfg(feature = "ximalaya")]
+        fn ximalaya<const KEY_SIZE: usize>(
+            key: [u8; KEY_SIZE],
+            scramble_table: ScrambleTable,
+        ) -> Self {
+            Self::new(EncryptedFormat::Ximalaya(
+                Vec::from(key),
+                Box::new(scramble_table),
+            ))
+        }
+
+        #[cfg(feature = "kuwo")]
+        pub fn kuwo(key: [u8; 0x20], resource_id: u64) -> Self {
+            Self::new(EncryptedFormat::Kuwo(key, resource_id))
+        }
+
+        fn new(format: EncryptedFormat) -> Self {
+            Self {
+                format,
+                body: vec![],
+            }
+        }
+
+        pub fn body<T: AsRef<[u8]>>(mut self, body: T) -> Self {
+            self.body = Vec::from(body.as_ref());
+            self
+        }
+
+        pub fn build(&self) -> Vec<u8> {
+            match &self.format {
+                #[cfg(feature = "ximalaya")]
+                EncryptedFormat::Ximalaya(key, scramble_table) => {
+                    ximalaya::test::encrypt(key, scramble_table, &self.body)
+                }
+
+                #[cfg(feature = "kuwo")]
+                EncryptedFormat::Kuwo(key, resource_id) => {
+                    let mut header = vec![0u8; 0x400];
+                    header[..0x10].copy_from_slice(b"yeelion-kuwo-tme");
+                    header[0x10] = 1;
+                    header[0x18..0x20].copy_from_slice(&resource_id.to_le_bytes());
+
+                    let mut key = *key;
+                    key.xor_key(resource_id.to_string().as_bytes());
+                    let mut body = self.body.clone();
+                    body.xor_key(key);
+
+                    [header, body].concat()
+                }
+            }
+        }
+    }
+
     #[test]
     fn hash_check() {
         let result = sha256(b"Parakeet");
@@ -52,6 +416,16 @@ pub mod test {
         );
     }
 
+    #[test]
+    fn parakeet_rng_matches_rc4_vector() {
+        // Well-known RC4 test vector: key "Key".
+        let data = generate_test_data_with(&mut ParakeetRng::new("Key"), 10);
+        assert_eq!(
+            data,
+            [0xEB, 0x9F, 0x77, 0x81, 0xB7, 0x34, 0xCA, 0x72, 0xA7, 0x19]
+        );
+    }
+
     #[test]
     fn generate_data_check() {
         let data = generate_test_data(256, "test_data_stable");
@@ -61,4 +435,57 @@ pub mod test {
             "cdffdcbb64563d64c7d56cf02dfe8f2642d9075ff6215aa61378541617ab6cb3"
         );
     }
+
+    #[test]
+    #[cfg(feature = "ximalaya")]
+    fn encrypted_builder_x2m() {
+        let key = generate_test_data(4, "builder x2m key");
+        let seed = generate_test_data(2048, "builder x2m seed");
+        let table = ximalaya::build_scramble_table(&seed).unwrap();
+        let plain = generate_test_data(TEST_SIZE_1MB, "builder x2m body");
+
+        let encrypted = EncryptedBuilder::x2m(key[..].try_into().unwrap(), table)
+            .body(&plain)
+            .build();
+        assert_ne!(encrypted, plain);
+
+        let mut decryptor = ximalaya::new_x2m(key[..].try_into().unwrap(), table);
+        assert_eq!(decrypt_in_chunks(&mut decryptor, &encrypted, 4096), plain);
+    }
+
+    #[test]
+    #[cfg(feature = "ximalaya")]
+    fn slow_decryptor_x2m() {
+        let key = generate_test_data(4, "slow x2m key");
+        let seed = generate_test_data(2048, "slow x2m seed");
+        let table = ximalaya::build_scramble_table(&seed).unwrap();
+        let encrypted = generate_test_data(0x3000, "slow x2m data");
+
+        let mut decryptor = ximalaya::new_x2m(key[..].try_into().unwrap(), table);
+        let expected = decrypt_in_chunks(&mut decryptor, &encrypted, 0);
+
+        let mut decryptor =
+            SlowDecryptor::new(ximalaya::new_x2m(key[..].try_into().unwrap(), table), 0x100);
+        decryptor.write(&encrypted[..0x1000]).unwrap();
+        assert_eq!(decryptor.pending(), 0xf00);
+        assert_eq!(decryptor.output_ready(), 0);
+
+        let result = decrypt_in_chunks(&mut decryptor, &encrypted[0x1000..], 0x200);
+        assert_eq!(decryptor.pending(), 0);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "kuwo")]
+    fn encrypted_builder_kuwo() {
+        let key = generate_test_data(0x20, "builder kuwo key");
+        let plain = generate_test_data(TEST_SIZE_1MB, "builder kuwo body");
+
+        let encrypted = EncryptedBuilder::kuwo(key[..].try_into().unwrap(), 0x1122_3344)
+            .body(&plain)
+            .build();
+
+        let mut decryptor = crate::decryption::kuwo::new_kwm(key[..].try_into().unwrap());
+        assert_eq!(decrypt_in_chunks(&mut decryptor, &encrypted, 4096), plain);
+    }
 }